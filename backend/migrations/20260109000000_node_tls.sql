@@ -0,0 +1,10 @@
+-- Per-node TLS verification settings
+CREATE TYPE node_tls_mode AS ENUM ('system', 'custom_ca', 'pinned');
+
+-- Existing nodes keep verifying against the system trust store. Pinning is opt-in: a pinned
+-- node without a confirmed fingerprint refuses every handshake until an admin trusts one.
+ALTER TABLE nodes
+    ADD COLUMN tls_mode node_tls_mode NOT NULL DEFAULT 'system',
+    ADD COLUMN tls_ca_cert TEXT,
+    ADD COLUMN tls_fingerprint TEXT,
+    ADD COLUMN tls_pending_fingerprint TEXT;
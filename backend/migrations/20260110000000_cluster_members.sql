@@ -0,0 +1,10 @@
+-- Hypervisor hosts that belong to a dashboard node's cluster
+CREATE TABLE cluster_members (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    node_id UUID NOT NULL REFERENCES nodes(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    address TEXT,
+    online BOOLEAN NOT NULL DEFAULT FALSE,
+    last_seen TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (node_id, name)
+);
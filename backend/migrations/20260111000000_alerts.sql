@@ -0,0 +1,57 @@
+-- Threshold alerting: rules evaluated by the backend, notification channels, silences and fired alerts
+CREATE TYPE alert_metric AS ENUM ('node_cpu', 'node_memory', 'node_disk', 'node_offline', 'vm_stopped');
+CREATE TYPE alert_channel_kind AS ENUM ('webhook', 'slack', 'email');
+CREATE TYPE alert_state AS ENUM ('firing', 'resolved');
+
+CREATE TABLE alert_channels (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    name TEXT NOT NULL,
+    kind alert_channel_kind NOT NULL,
+    config JSONB NOT NULL DEFAULT '{}',
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE alert_rules (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    name TEXT NOT NULL,
+    metric alert_metric NOT NULL,
+    -- Percentage for node_cpu / node_memory / node_disk; unused for state-based metrics
+    threshold DOUBLE PRECISION,
+    duration_secs INTEGER NOT NULL DEFAULT 0,
+    -- NULL applies the rule to every node
+    node_id UUID REFERENCES nodes(id) ON DELETE CASCADE,
+    vm_id TEXT,
+    channel_ids UUID[] NOT NULL DEFAULT '{}',
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE alert_silences (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    rule_id UUID REFERENCES alert_rules(id) ON DELETE CASCADE,
+    node_id UUID REFERENCES nodes(id) ON DELETE CASCADE,
+    starts_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    ends_at TIMESTAMPTZ NOT NULL,
+    reason TEXT,
+    created_by TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE alert_events (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    rule_id UUID NOT NULL REFERENCES alert_rules(id) ON DELETE CASCADE,
+    node_id UUID REFERENCES nodes(id) ON DELETE CASCADE,
+    -- Node id, or "node_id:vm_id" for guest alerts
+    subject TEXT NOT NULL,
+    state alert_state NOT NULL DEFAULT 'firing',
+    value DOUBLE PRECISION,
+    message TEXT NOT NULL,
+    silenced BOOLEAN NOT NULL DEFAULT FALSE,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    resolved_at TIMESTAMPTZ
+);
+
+-- At most one open alert per rule and subject
+CREATE UNIQUE INDEX alert_events_open ON alert_events (rule_id, subject) WHERE state = 'firing';
+CREATE INDEX alert_events_started_at ON alert_events (started_at DESC);
@@ -0,0 +1,19 @@
+-- Recorded VNC sessions; the RFB stream itself lives on disk under VNC_RECORDINGS_DIR
+CREATE TABLE vnc_recordings (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    session_id UUID NOT NULL,
+    user_id UUID REFERENCES users(id) ON DELETE SET NULL,
+    -- Kept separately so recordings stay attributable after the account is deleted
+    username TEXT NOT NULL,
+    node_id UUID NOT NULL,
+    vm_id TEXT NOT NULL,
+    client_ip TEXT,
+    file_name TEXT NOT NULL,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    ended_at TIMESTAMPTZ,
+    duration_ms BIGINT NOT NULL DEFAULT 0,
+    size_bytes BIGINT NOT NULL DEFAULT 0
+);
+
+CREATE INDEX vnc_recordings_vm ON vnc_recordings (node_id, vm_id, started_at DESC);
+CREATE INDEX vnc_recordings_user ON vnc_recordings (user_id, started_at DESC);
@@ -0,0 +1,15 @@
+-- Console access to a single VM for people without a dashboard account
+CREATE TABLE console_share_links (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    node_id UUID NOT NULL REFERENCES nodes(id) ON DELETE CASCADE,
+    vm_id TEXT NOT NULL,
+    label TEXT,
+    -- Keyboard, mouse and clipboard input is dropped by the proxy
+    view_only BOOLEAN NOT NULL DEFAULT FALSE,
+    expires_at TIMESTAMPTZ NOT NULL,
+    revoked_at TIMESTAMPTZ,
+    created_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX console_share_links_vm ON console_share_links (node_id, vm_id, created_at DESC);
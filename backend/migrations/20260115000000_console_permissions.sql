@@ -0,0 +1,16 @@
+-- What a non-admin user may do on one VM's console; admins always have full control
+CREATE TYPE console_access AS ENUM ('view', 'control');
+
+CREATE TABLE vm_console_permissions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    node_id UUID NOT NULL REFERENCES nodes(id) ON DELETE CASCADE,
+    vm_id TEXT NOT NULL,
+    -- 'view' watches through the read-only proxy, 'control' can type and click
+    access console_access NOT NULL,
+    granted_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (user_id, node_id, vm_id)
+);
+
+CREATE INDEX vm_console_permissions_vm ON vm_console_permissions (node_id, vm_id);
@@ -1,27 +1,131 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
 use async_trait::async_trait;
+use futures::StreamExt;
 use reqwest::Client;
 use serde_json::Value;
+use sha2::{Digest, Sha256};
+use crate::models::cloud_init::{CloudInitConfig, CloudInitUpdate};
+use crate::models::media::{MediaItem, MediaKind, MediaStorage};
 use crate::models::node::NodeStatus;
-use super::NodeClient;
+use crate::models::resize::{ResizeLimits, ResizeOutcome, VmResize};
+use super::{is_path_segment, is_reachable_ip, ChecksumMismatch, ClusterMember, ConfigConflict, MediaStream, VmDetails, ClusterStatus, FsFreezeAction, GuestInfo, NodeClient, NodeDiscovery, VmCounters};
+
+const MIB: u64 = 1024 * 1024;
+const GIB: u64 = 1024 * MIB;
 
 pub struct IncusClient {
     client: Client,
     api_url: String,
+    tls: Arc<rustls::ClientConfig>,
 }
 
 impl IncusClient {
-    pub fn new(api_url: String, _api_key: String, _api_secret: Option<String>) -> Self {
-        // Incus usually requires client certificates for the REST API.
-        // For now, initializing a basic client. certificate logic will be added later.
+    pub fn new(api_url: String, _api_key: String, _api_secret: Option<String>, tls: Arc<rustls::ClientConfig>) -> Self {
+        // Incus authenticates us by client certificate, which the TLS config already presents
         let client = Client::builder()
-            .danger_accept_invalid_certs(true)
+            .use_preconfigured_tls((*tls).clone())
             .build()
             .unwrap();
 
         Self {
             client,
             api_url,
+            tls,
+        }
+    }
+}
+
+impl IncusClient {
+    /// `GET /1.0`, failing unless the server trusts our client certificate
+    async fn server_info(&self) -> anyhow::Result<Value> {
+        let url = format!("{}/1.0", self.api_url);
+        let resp = self.client.get(&url).send().await?;
+        if !resp.status().is_success() {
+            anyhow::bail!("Failed to query Incus server: {}", resp.status());
+        }
+
+        let data: Value = resp.json().await?;
+        let auth = data["metadata"]["auth"].as_str().unwrap_or("untrusted");
+        if auth != "trusted" {
+            anyhow::bail!("Incus does not trust the client certificate (auth: {})", auth);
+        }
+
+        Ok(data["metadata"].clone())
+    }
+
+    /// GET an API path and return its `metadata`
+    async fn get_metadata(&self, path: &str) -> anyhow::Result<Value> {
+        let url = format!("{}{}", self.api_url, path);
+        let resp = self.client.get(&url).send().await?;
+        if !resp.status().is_success() {
+            let err_text = resp.text().await.unwrap_or_default();
+            anyhow::bail!("Incus request {} failed: {}", path, err_text);
+        }
+
+        let data: Value = resp.json().await?;
+        Ok(data["metadata"].clone())
+    }
+
+    /// Check a response and, for background operations, wait until the operation is done
+    async fn complete(&self, resp: reqwest::Response, what: &str) -> anyhow::Result<()> {
+        if !resp.status().is_success() {
+            let err_text = resp.text().await.unwrap_or_default();
+            anyhow::bail!("Incus {} failed: {}", what, err_text);
+        }
+
+        let data: Value = resp.json().await?;
+        let Some(operation) = data["operation"].as_str().filter(|_| data["type"] == "async") else {
+            return Ok(());
+        };
+        let url = format!("{}{}/wait", self.api_url, operation);
+        let done: Value = self.client.get(&url).send().await?.json().await?;
+        if done["metadata"]["status_code"].as_u64() == Some(200) {
+            Ok(())
+        } else {
+            let err = done["metadata"]["err"].as_str().or(done["error"].as_str()).unwrap_or("unknown error");
+            anyhow::bail!("Incus {} failed: {}", what, err)
+        }
+    }
+
+    /// Cluster members own their resources; standalone servers report "none" as location
+    fn target_query(instance: &Value) -> String {
+        match instance["location"].as_str() {
+            Some(location) if !location.is_empty() && location != "none" => format!("?target={}", location),
+            _ => String::new(),
+        }
+    }
+
+    /// Start an operation that waits for websockets (exec, console) and return the websocket
+    /// URL for each of its file descriptors, keyed like Incus does ("0", "control", ...)
+    async fn websocket_operation(&self, url: &str, body: Value) -> anyhow::Result<HashMap<String, String>> {
+        let resp = self.client.post(url).json(&body).send().await?;
+        if !resp.status().is_success() {
+            let err_text = resp.text().await.unwrap_or_default();
+            anyhow::bail!("Incus operation failed: {}", err_text);
         }
+
+        let data: Value = resp.json().await?;
+        let operation = data["operation"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("No operation in Incus response"))?;
+        let fds = data["metadata"]["metadata"]["fds"]
+            .as_object()
+            .ok_or_else(|| anyhow::anyhow!("Incus operation exposes no websockets"))?;
+
+        let ws_host = self.api_url
+            .replace("https://", "wss://")
+            .replace("http://", "ws://")
+            .trim_end_matches('/')
+            .to_string();
+
+        Ok(fds.iter()
+            .filter_map(|(fd, secret)| secret.as_str().map(|secret| (
+                fd.clone(),
+                format!("{}{}/websocket?secret={}", ws_host, operation, urlencoding::encode(secret)),
+            )))
+            .collect())
     }
 }
 
@@ -39,14 +143,14 @@ impl NodeClient for IncusClient {
     }
 
     async fn list_vms(&self) -> anyhow::Result<Vec<Value>> {
-        let url = format!("{}/1.0/instances?recursion=1", self.api_url);
+        // recursion=2 includes each instance's runtime state
+        let url = format!("{}/1.0/instances?recursion=2", self.api_url);
         let resp = self.client.get(&url).send().await?;
         
         if resp.status().is_success() {
             let data: Value = resp.json().await?;
-            // Incus returns a list of URLs by default, or detailed objects if recursion is used
             let vms = data["metadata"].as_array().cloned().unwrap_or_default();
-            Ok(vms)
+            Ok(vms.into_iter().map(with_usage).collect())
         } else {
             anyhow::bail!("Failed to list Incus instances: {}", resp.status())
         }
@@ -54,11 +158,18 @@ impl NodeClient for IncusClient {
 
     async fn vm_power_action(&self, vm_id: &str, action: &str) -> anyhow::Result<()> {
         let url = format!("{}/1.0/instances/{}/state", self.api_url, vm_id);
-        
+
+        // Incus has no shutdown/reboot actions; a non-forced stop or restart asks the guest
+        // (through its agent for VMs that run one) to shut down cleanly
+        let (action, force) = match action {
+            "shutdown" => ("stop", false),
+            "reboot" => ("restart", false),
+            other => (other, true),
+        };
         let payload = serde_json::json!({
             "action": action,
             "timeout": 30,
-            "force": true
+            "force": force
         });
 
         let resp = self.client.put(&url).json(&payload).send().await?;
@@ -71,42 +182,63 @@ impl NodeClient for IncusClient {
         }
     }
 
-    async fn update_vm_config(&self, vm_id: &str, config: Value) -> anyhow::Result<()> {
+    async fn update_vm_config(&self, vm_id: &str, config: Value, digest: Option<&str>) -> anyhow::Result<()> {
         let url = format!("{}/1.0/instances/{}", self.api_url, vm_id);
         
         // Incus uses PATCH for configuration updates
-        let resp = self.client.patch(&url).json(&config).send().await?;
+        let mut request = self.client.patch(&url).json(&config);
+        if let Some(etag) = digest {
+            request = request.header(reqwest::header::IF_MATCH, etag);
+        }
+        let resp = request.send().await?;
 
         if resp.status().is_success() {
             Ok(())
+        } else if resp.status() == reqwest::StatusCode::PRECONDITION_FAILED {
+            Err(ConfigConflict.into())
         } else {
             let err_text = resp.text().await.unwrap_or_default();
             anyhow::bail!("Incus config update failed: {}", err_text)
         }
     }
 
-    async fn get_vm_details(&self, vm_id: &str) -> anyhow::Result<Value> {
+    async fn get_vm_details(&self, vm_id: &str) -> anyhow::Result<VmDetails> {
         let url = format!("{}/1.0/instances/{}", self.api_url, vm_id);
         let resp = self.client.get(&url).send().await?;
 
         if resp.status().is_success() {
+            let digest = resp.headers().get(reqwest::header::ETAG)
+                .and_then(|etag| etag.to_str().ok())
+                .map(str::to_string);
             let data: Value = resp.json().await?;
-            Ok(data["metadata"].clone())
+            Ok(VmDetails { config: data["metadata"].clone(), digest })
         } else {
             anyhow::bail!("Failed to get Incus instance details: {}", resp.status())
         }
     }
 
     async fn mount_media(&self, vm_id: &str, iso_path: &str) -> anyhow::Result<()> {
+        if is_image_fingerprint(iso_path) {
+            anyhow::bail!("Incus images create instances and cannot be attached as a CD-ROM");
+        }
         let url = format!("{}/1.0/instances/{}", self.api_url, vm_id);
         
+        // Library ISOs are `pool:volume`; anything else is a path on the host
+        let device = match iso_path.split_once(':').filter(|_| !iso_path.starts_with('/')) {
+            Some((pool, volume)) => serde_json::json!({
+                "type": "disk",
+                "pool": pool,
+                "source": volume
+            }),
+            None => serde_json::json!({
+                "type": "disk",
+                "source": iso_path,
+                "path": "/dev/cdrom"
+            }),
+        };
         let config = serde_json::json!({
             "devices": {
-                "cdrom": {
-                    "type": "disk",
-                    "source": iso_path,
-                    "path": "/dev/cdrom"
-                }
+                "cdrom": device
             }
         });
 
@@ -120,13 +252,610 @@ impl NodeClient for IncusClient {
         }
     }
 
-    async fn get_vnc_info(&self, vm_id: &str) -> anyhow::Result<super::VncInfo> {
-        // Placeholder for Incus VNC/Console
-        let ws_host = self.api_url.replace("https://", "wss://").replace("http://", "ws://");
-        Ok(super::VncInfo {
-            url: format!("{}/1.0/instances/{}/console?type=vnc", ws_host, vm_id),
-            ticket: "".to_string(),
-            port: 0,
+    async fn eject_media(&self, vm_id: &str) -> anyhow::Result<()> {
+        let VmDetails { config: mut instance, digest } = self.get_vm_details(vm_id).await?;
+        let removed = instance["devices"].as_object_mut().and_then(|devices| devices.remove("cdrom"));
+        if removed.is_none() {
+            anyhow::bail!("No CD-ROM is attached to {}", vm_id);
+        }
+
+        // PATCH merges devices, so removing one takes a full PUT
+        let url = format!("{}/1.0/instances/{}", self.api_url, vm_id);
+        let mut request = self.client.put(&url).json(&instance);
+        if let Some(etag) = digest {
+            request = request.header(reqwest::header::IF_MATCH, etag);
+        }
+        let resp = request.send().await?;
+        if resp.status() == reqwest::StatusCode::PRECONDITION_FAILED {
+            return Err(ConfigConflict.into());
+        }
+        self.complete(resp, "media eject").await
+    }
+
+    async fn list_media_storages(&self, _host: Option<&str>) -> anyhow::Result<Vec<MediaStorage>> {
+        let pools = self.get_metadata("/1.0/storage-pools?recursion=1").await?;
+
+        Ok(pools.as_array().into_iter().flatten()
+            .filter_map(|pool| Some(MediaStorage {
+                name: pool["name"].as_str()?.to_string(),
+                storage_type: pool["driver"].as_str().unwrap_or_default().to_string(),
+                total: None,
+                available: None,
+            }))
+            .collect())
+    }
+
+    async fn list_media(&self, storage: Option<&str>, _host: Option<&str>) -> anyhow::Result<Vec<MediaItem>> {
+        let pools = match storage {
+            Some(pool) => vec![pool.to_string()],
+            None => self.list_media_storages(None).await?.into_iter().map(|s| s.name).collect(),
+        };
+
+        let mut items = Vec::new();
+        for pool in pools {
+            let volumes = self.get_metadata(&format!("/1.0/storage-pools/{}/volumes/custom?recursion=1", pool)).await?;
+            items.extend(volumes.as_array().into_iter().flatten()
+                .filter(|volume| volume["content_type"] == "iso")
+                .filter_map(|volume| {
+                    let name = volume["name"].as_str()?;
+                    Some(MediaItem {
+                        id: format!("{}:{}", pool, name),
+                        name: name.to_string(),
+                        kind: MediaKind::Iso,
+                        storage: pool.clone(),
+                        size: volume["config"]["size"].as_str().and_then(parse_size),
+                        created_at: parse_timestamp(&volume["created_at"]),
+                    })
+                }));
+        }
+
+        // Images live in the server's image store rather than a pool
+        if storage.is_none() {
+            let images = self.get_metadata("/1.0/images?recursion=1").await?;
+            items.extend(images.as_array().into_iter().flatten().filter_map(|image| {
+                let fingerprint = image["fingerprint"].as_str()?;
+                let name = image["aliases"][0]["name"].as_str()
+                    .or(image["properties"]["description"].as_str())
+                    .unwrap_or(&fingerprint[..fingerprint.len().min(12)]);
+                Some(MediaItem {
+                    id: fingerprint.to_string(),
+                    name: name.to_string(),
+                    kind: MediaKind::Image,
+                    storage: "images".to_string(),
+                    size: image["size"].as_u64(),
+                    created_at: parse_timestamp(&image["created_at"]),
+                })
+            }));
+        }
+        Ok(items)
+    }
+
+    async fn upload_media(&self, storage: &str, _host: Option<&str>, filename: &str, size: u64, sha256: Option<&str>, body: MediaStream) -> anyhow::Result<MediaItem> {
+        let name = filename.trim_end_matches(".iso").trim_end_matches(".img");
+        // Incus cannot check a checksum, so a verified upload lands under a temporary name and
+        // only becomes visible once its digest matched
+        let staged = match sha256 {
+            Some(_) => format!("{}-upload-{}", name, &uuid::Uuid::new_v4().simple().to_string()[..8]),
+            None => name.to_string(),
+        };
+
+        let hasher = Arc::new(Mutex::new(Sha256::new()));
+        let body = body.map({
+            let hasher = hasher.clone();
+            move |chunk| {
+                if let Ok(chunk) = &chunk {
+                    hasher.lock().unwrap().update(chunk);
+                }
+                chunk
+            }
+        });
+
+        // ISO volumes are created straight from the raw file body
+        let volumes_url = format!("{}/1.0/storage-pools/{}/volumes/custom", self.api_url, storage);
+        let resp = self.client.post(&volumes_url)
+            .header(reqwest::header::CONTENT_TYPE, "application/octet-stream")
+            .header(reqwest::header::CONTENT_LENGTH, size)
+            .header("X-Incus-name", &staged)
+            .header("X-Incus-type", "iso")
+            .body(reqwest::Body::wrap_stream(body))
+            .send()
+            .await?;
+        self.complete(resp, "ISO upload").await?;
+
+        if let Some(expected) = sha256 {
+            let staged_url = format!("{}/{}", volumes_url, staged);
+            let actual = hex::encode(hasher.lock().unwrap().clone().finalize());
+            let verified = if actual.eq_ignore_ascii_case(expected) {
+                let resp = self.client.post(&staged_url).json(&serde_json::json!({ "name": name })).send().await?;
+                self.complete(resp, "ISO rename").await
+            } else {
+                Err(ChecksumMismatch { actual }.into())
+            };
+
+            if let Err(e) = verified {
+                let resp = self.client.delete(&staged_url).send().await?;
+                if let Err(cleanup) = self.complete(resp, "ISO delete").await {
+                    anyhow::bail!("{}; the staged volume {}:{} could not be removed: {}", e, storage, staged, cleanup);
+                }
+                return Err(e);
+            }
+        }
+
+        Ok(MediaItem {
+            id: format!("{}:{}", storage, name),
+            name: name.to_string(),
+            kind: MediaKind::Iso,
+            storage: storage.to_string(),
+            size: Some(size),
+            created_at: Some(chrono::Utc::now()),
         })
     }
+
+    async fn delete_media(&self, id: &str, _host: Option<&str>) -> anyhow::Result<()> {
+        // Images are listed by their fingerprint
+        if is_image_fingerprint(id) {
+            let url = format!("{}/1.0/images/{}", self.api_url, id);
+            let resp = self.client.delete(&url).send().await?;
+            return self.complete(resp, "image delete").await;
+        }
+
+        let (pool, volume) = id.split_once(':')
+            .filter(|(pool, volume)| is_path_segment(pool) && is_path_segment(volume))
+            .ok_or_else(|| anyhow::anyhow!("{:?} is not an ISO volume or image", id))?;
+
+        let url = format!("{}/1.0/storage-pools/{}/volumes/custom/{}", self.api_url, pool, volume);
+        let resp = self.client.delete(&url).send().await?;
+        self.complete(resp, "ISO delete").await
+    }
+
+    async fn get_vnc_info(&self, _vm_id: &str) -> anyhow::Result<super::VncInfo> {
+        // The `vga` console type is a SPICE stream; starting it here would only leave an
+        // operation behind that nothing reads
+        anyhow::bail!("Incus VMs only offer a SPICE console, which the VNC viewer cannot display")
+    }
+
+    async fn open_terminal(&self, vm_id: &str, mode: super::TerminalMode, cols: u16, rows: u16) -> anyhow::Result<super::TerminalTarget> {
+        let (url, body) = match mode {
+            super::TerminalMode::Console => (
+                format!("{}/1.0/instances/{}/console", self.api_url, vm_id),
+                serde_json::json!({ "type": "console", "width": cols, "height": rows }),
+            ),
+            super::TerminalMode::Shell => (
+                format!("{}/1.0/instances/{}/exec", self.api_url, vm_id),
+                serde_json::json!({
+                    "command": ["/bin/sh", "-c", "exec $(command -v bash || command -v sh) -l"],
+                    "environment": { "TERM": "xterm-256color", "HOME": "/root" },
+                    "interactive": true,
+                    "wait-for-websocket": true,
+                    "width": cols,
+                    "height": rows,
+                }),
+            ),
+        };
+
+        let mut sockets = self.websocket_operation(&url, body).await?;
+        let data_url = sockets.remove("0")
+            .ok_or_else(|| anyhow::anyhow!("Incus terminal operation has no data websocket"))?;
+        let control_url = sockets.remove("control")
+            .ok_or_else(|| anyhow::anyhow!("Incus terminal operation has no control websocket"))?;
+
+        Ok(super::TerminalTarget::Incus { data_url, control_url })
+    }
+
+    async fn vm_counters(&self, vm_id: &str) -> anyhow::Result<VmCounters> {
+        // recursion=1 includes both the live state and the expanded config (for limits.cpu)
+        let url = format!("{}/1.0/instances/{}?recursion=1", self.api_url, vm_id);
+        let resp = self.client.get(&url).send().await?;
+        if !resp.status().is_success() {
+            anyhow::bail!("Failed to get Incus instance state: {}", resp.status());
+        }
+
+        let data: Value = resp.json().await?;
+        let instance = &data["metadata"];
+        let state = &instance["state"];
+
+        let (net_in, net_out) = state["network"].as_object()
+            .map(|nics| nics.iter()
+                .filter(|(name, _)| name.as_str() != "lo")
+                .fold((0, 0), |(rx, tx), (_, nic)| (
+                    rx + nic["counters"]["bytes_received"].as_u64().unwrap_or(0),
+                    tx + nic["counters"]["bytes_sent"].as_u64().unwrap_or(0),
+                )))
+            .unwrap_or((0, 0));
+
+        let mem_total = state["memory"]["total"].as_u64()
+            .filter(|t| *t > 0)
+            .unwrap_or_else(|| state["memory"]["usage_peak"].as_u64().unwrap_or(0));
+
+        Ok(VmCounters {
+            status: instance["status"].as_str().unwrap_or("Unknown").to_lowercase(),
+            cpu_fraction: None,
+            cpu_time_ns: state["cpu"]["usage"].as_u64(),
+            cpus: instance["expanded_config"]["limits.cpu"].as_str().and_then(|c| c.parse().ok()),
+            mem_used: state["memory"]["usage"].as_u64().unwrap_or(0),
+            mem_total,
+            // Incus does not expose block I/O counters in the instance state
+            disk_read: None,
+            disk_write: None,
+            net_in,
+            net_out,
+            uptime: None,
+        })
+    }
+
+    async fn guest_info(&self, vm_id: &str) -> anyhow::Result<GuestInfo> {
+        let url = format!("{}/1.0/instances/{}?recursion=1", self.api_url, vm_id);
+        let resp = self.client.get(&url).send().await?;
+        if !resp.status().is_success() {
+            anyhow::bail!("Failed to get Incus instance state: {}", resp.status());
+        }
+
+        let data: Value = resp.json().await?;
+        let instance = &data["metadata"];
+        let state = &instance["state"];
+
+        // Containers always report their interfaces; VMs only while incus-agent runs
+        let mut ip_addresses: Vec<String> = Vec::new();
+        for (name, nic) in state["network"].as_object().into_iter().flatten() {
+            if name == "lo" {
+                continue;
+            }
+            for address in nic["addresses"].as_array().into_iter().flatten() {
+                if let Some(ip) = address["address"].as_str().filter(|ip| is_reachable_ip(ip)) {
+                    if !ip_addresses.iter().any(|known| known == ip) {
+                        ip_addresses.push(ip.to_string());
+                    }
+                }
+            }
+        }
+
+        // os_info comes from the agent on newer Incus; the image metadata is the fallback
+        let os_info = &state["os_info"];
+        let config = &instance["config"];
+        Ok(GuestInfo {
+            agent: state["network"].as_object().is_some_and(|nics| !nics.is_empty()),
+            ip_addresses,
+            os_name: os_info["os"].as_str().or(config["image.os"].as_str()).map(str::to_string),
+            os_version: os_info["os_version"].as_str().or(config["image.release"].as_str()).map(str::to_string),
+        })
+    }
+
+    async fn guest_fsfreeze(&self, _vm_id: &str, _action: FsFreezeAction) -> anyhow::Result<Value> {
+        anyhow::bail!("Filesystem freeze is not supported on Incus")
+    }
+
+    async fn get_cloud_init(&self, vm_id: &str) -> anyhow::Result<CloudInitConfig> {
+        let instance = self.get_vm_details(vm_id).await?.config;
+        let config = &instance["config"];
+
+        // The `user.*` keys are the pre-Incus names, still honoured when the new ones are unset
+        let raw = |key: &str| {
+            config[format!("cloud-init.{}", key)].as_str()
+                .or(config[format!("user.{}", key)].as_str())
+                .filter(|v| !v.is_empty())
+                .map(str::to_string)
+        };
+
+        Ok(CloudInitConfig {
+            user_data: raw("user-data"),
+            network_config: raw("network-config"),
+            ..Default::default()
+        })
+    }
+
+    async fn update_cloud_init(&self, vm_id: &str, changes: &CloudInitUpdate) -> anyhow::Result<()> {
+        let mut config = serde_json::Map::new();
+        // An empty value unsets the key
+        if let Some(user_data) = &changes.user_data {
+            config.insert("cloud-init.user-data".to_string(), Value::String(user_data.clone()));
+        }
+        if let Some(network_config) = &changes.network_config {
+            config.insert("cloud-init.network-config".to_string(), Value::String(network_config.clone()));
+        }
+        if config.is_empty() {
+            return Ok(());
+        }
+
+        self.update_vm_config(vm_id, serde_json::json!({ "config": config }), None).await
+    }
+
+    async fn resize_limits(&self, vm_id: &str, disk: Option<&str>) -> anyhow::Result<ResizeLimits> {
+        let instance = self.get_metadata(&format!("/1.0/instances/{}", vm_id)).await?;
+        let target = Self::target_query(&instance);
+        let resources = self.get_metadata(&format!("/1.0/resources{}", target)).await?;
+        let host_cpus = resources["cpu"]["total"].as_u64().unwrap_or(1) as u32;
+        let host_memory_mib = resources["memory"]["total"].as_u64().unwrap_or(0) / MIB;
+
+        // Without limits an instance may use the whole host
+        let config = &instance["expanded_config"];
+        let cores = config["limits.cpu"].as_str().and_then(cpu_limit).unwrap_or(host_cpus);
+        let memory_mib = match config["limits.memory"].as_str() {
+            Some(percent) if percent.ends_with('%') => percent.trim_end_matches('%').parse::<u64>().map_or(host_memory_mib, |p| host_memory_mib * p / 100),
+            Some(size) => parse_size(size).map_or(host_memory_mib, |bytes| bytes / MIB),
+            None => host_memory_mib,
+        };
+
+        // Only the root disk can be grown through its device size
+        let name = disk.unwrap_or("root");
+        let device = &instance["expanded_devices"][name];
+        let pool = device["pool"].as_str().filter(|_| device["type"] == "disk" && device["path"] == "/");
+        let disk_free_gib = match pool {
+            Some(pool) => match self.get_metadata(&format!("/1.0/storage-pools/{}/resources{}", pool, target)).await {
+                Ok(space) => space["space"]["total"].as_u64()
+                    .zip(space["space"]["used"].as_u64())
+                    .map(|(total, used)| total.saturating_sub(used) / GIB),
+                Err(e) => {
+                    tracing::debug!("No free space for pool {}: {}", pool, e);
+                    None
+                }
+            },
+            None => None,
+        };
+
+        Ok(ResizeLimits {
+            host_cpus,
+            host_memory_mib,
+            cores,
+            sockets: 1,
+            memory_mib,
+            supports_sockets: false,
+            supports_balloon: false,
+            disk: pool.map(|_| name.to_string()),
+            disk_free_gib,
+        })
+    }
+
+    async fn resize_vm(&self, vm_id: &str, resize: &VmResize) -> anyhow::Result<ResizeOutcome> {
+        let instance = self.get_metadata(&format!("/1.0/instances/{}", vm_id)).await?;
+
+        let mut config = serde_json::Map::new();
+        if let Some(cores) = resize.cores {
+            config.insert("limits.cpu".to_string(), Value::String(cores.to_string()));
+        }
+        if let Some(memory) = resize.memory_mib {
+            config.insert("limits.memory".to_string(), Value::String(format!("{}MiB", memory)));
+        }
+
+        let mut devices = serde_json::Map::new();
+        if let Some(grow) = resize.disk_grow_gib {
+            let name = resize.disk.as_deref().ok_or_else(|| anyhow::anyhow!("No disk to grow"))?;
+            // Profile devices are copied into the instance so the new size overrides them
+            let mut device = instance["expanded_devices"][name].clone();
+            let current = match device["size"].as_str().and_then(parse_size) {
+                Some(bytes) => bytes,
+                None => {
+                    let pool = device["pool"].as_str().unwrap_or_default();
+                    let volume_type = if instance["type"] == "virtual-machine" { "virtual-machine" } else { "container" };
+                    let volume = self.get_metadata(&format!("/1.0/storage-pools/{}/volumes/{}/{}", pool, volume_type, vm_id)).await?;
+                    volume["config"]["size"].as_str()
+                        .and_then(parse_size)
+                        .ok_or_else(|| anyhow::anyhow!("Current size of {} is unknown; set a size on the device first", name))?
+                }
+            };
+            device["size"] = Value::String(format!("{}B", current + grow * GIB));
+            devices.insert(name.to_string(), device);
+        }
+
+        let mut patch = serde_json::Map::new();
+        if !config.is_empty() {
+            patch.insert("config".to_string(), Value::Object(config));
+        }
+        if !devices.is_empty() {
+            patch.insert("devices".to_string(), Value::Object(devices));
+        }
+        if !patch.is_empty() {
+            self.update_vm_config(vm_id, Value::Object(patch), None).await?;
+        }
+
+        // Containers and VM vCPUs resize live; a running VM keeps its memory until restarted
+        let running_vm = instance["type"] == "virtual-machine" && instance["status"] == "Running";
+        Ok(ResizeOutcome { reboot_required: running_vm && resize.memory_mib.is_some() })
+    }
+
+    async fn discover(&self) -> anyhow::Result<NodeDiscovery> {
+        let server = self.server_info().await?;
+        let cluster = self.cluster_status().await?;
+
+        Ok(NodeDiscovery {
+            version: server["environment"]["server_version"].as_str().unwrap_or("unknown").to_string(),
+            cluster_name: cluster.name,
+            members: cluster.members,
+            permissions: serde_json::json!({
+                "auth": server["auth"],
+                "auth_user_name": server["auth_user_name"],
+                "auth_user_method": server["auth_user_method"],
+            }),
+        })
+    }
+
+    async fn cluster_status(&self) -> anyhow::Result<ClusterStatus> {
+        let server = self.server_info().await?;
+        let env = &server["environment"];
+        let server_name = env["server_name"].as_str().unwrap_or_default().to_string();
+
+        if !env["server_clustered"].as_bool().unwrap_or(false) {
+            return Ok(ClusterStatus {
+                name: None,
+                quorate: true,
+                members: vec![ClusterMember {
+                    name: server_name,
+                    address: Some(self.api_url.clone()),
+                    online: true,
+                }],
+            });
+        }
+
+        let url = format!("{}/1.0/cluster/members?recursion=1", self.api_url);
+        let resp = self.client.get(&url).send().await?;
+        if !resp.status().is_success() {
+            anyhow::bail!("Failed to list Incus cluster members: {}", resp.status());
+        }
+        let data: Value = resp.json().await?;
+        let members: Vec<ClusterMember> = data["metadata"].as_array().cloned().unwrap_or_default()
+            .iter()
+            .map(|member| ClusterMember {
+                name: member["server_name"].as_str().unwrap_or_default().to_string(),
+                address: member["url"].as_str().map(|s| s.to_string()),
+                online: member["status"].as_str() == Some("Online"),
+            })
+            .collect();
+
+        // Incus uses dqlite, which needs a majority of members to stay writable
+        let online = members.iter().filter(|m| m.online).count();
+        Ok(ClusterStatus {
+            name: Some(server_name),
+            quorate: online * 2 > members.len(),
+            members,
+        })
+    }
+
+    fn tls_config(&self) -> Arc<rustls::ClientConfig> {
+        self.tls.clone()
+    }
+}
+
+/// `limits.cpu` is either a count or a pinned set like `0-3,6`
+fn cpu_limit(raw: &str) -> Option<u32> {
+    if let Ok(count) = raw.parse() {
+        return Some(count);
+    }
+    raw.split(',')
+        .map(|part| match part.split_once('-') {
+            Some((first, last)) => last.parse::<u32>().ok()?.checked_sub(first.parse().ok()?).map(|n| n + 1),
+            None => part.parse::<u32>().ok().map(|_| 1),
+        })
+        .sum()
+}
+
+/// Incus sizes like `10GiB`, `500MB` or plain bytes
+fn parse_size(raw: &str) -> Option<u64> {
+    let raw = raw.trim();
+    let (number, unit) = raw.split_at(raw.find(|c: char| !c.is_ascii_digit()).unwrap_or(raw.len()));
+    let multiplier: u64 = match unit.trim() {
+        "" | "B" => 1,
+        "kB" => 1_000,
+        "MB" => 1_000_000,
+        "GB" => 1_000_000_000,
+        "TB" => 1_000_000_000_000,
+        "KiB" => 1024,
+        "MiB" => MIB,
+        "GiB" => GIB,
+        "TiB" => 1024 * GIB,
+        _ => return None,
+    };
+    number.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+/// Lift memory use and uptime out of the nested instance state into the fields Proxmox
+/// listings use, and drop the rest of the state; CPU usage has no instantaneous figure here
+fn with_usage(mut instance: Value) -> Value {
+    let mem = instance["state"]["memory"]["usage"].as_u64();
+    let running = instance["status"].as_str().is_some_and(|s| s.eq_ignore_ascii_case("running"));
+    // `last_used_at` is when the instance was last started
+    let uptime = instance["last_used_at"].as_str()
+        .filter(|_| running)
+        .and_then(|started| chrono::DateTime::parse_from_rfc3339(started).ok())
+        .map(|started| (chrono::Utc::now() - started.with_timezone(&chrono::Utc)).num_seconds().max(0));
+
+    if let Some(obj) = instance.as_object_mut() {
+        obj.remove("state");
+        if let Some(mem) = mem {
+            obj.insert("mem".to_string(), mem.into());
+        }
+        if let Some(uptime) = uptime {
+            obj.insert("uptime".to_string(), uptime.into());
+        }
+    }
+    instance
+}
+
+/// Library ids of Incus images are their full SHA-256 fingerprint
+fn is_image_fingerprint(id: &str) -> bool {
+    id.len() == 64 && id.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+fn parse_timestamp(value: &Value) -> Option<chrono::DateTime<chrono::Utc>> {
+    let parsed = chrono::DateTime::parse_from_rfc3339(value.as_str()?).ok()?;
+    Some(parsed.with_timezone(&chrono::Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::Path, http::HeaderMap, routing::post, Json, Router};
+    use std::collections::BTreeSet;
+
+    const ISO: &[u8] = b"not really an ISO";
+
+    /// Canned storage pool `default` that keeps volume names, like Incus answering synchronously
+    async fn serve_pool() -> (IncusClient, Arc<Mutex<BTreeSet<String>>>) {
+        let volumes = Arc::new(Mutex::new(BTreeSet::new()));
+        let (created, renamed, deleted) = (volumes.clone(), volumes.clone(), volumes.clone());
+        let router = Router::new()
+            .route("/1.0/storage-pools/default/volumes/custom", post(move |headers: HeaderMap| async move {
+                let name = headers["x-incus-name"].to_str().unwrap().to_string();
+                created.lock().unwrap().insert(name);
+                Json(serde_json::json!({ "type": "sync", "status_code": 200 }))
+            }))
+            .route(
+                "/1.0/storage-pools/default/volumes/custom/:name",
+                post(move |Path(name): Path<String>, Json(body): Json<Value>| async move {
+                    let mut volumes = renamed.lock().unwrap();
+                    volumes.remove(&name);
+                    volumes.insert(body["name"].as_str().unwrap().to_string());
+                    Json(serde_json::json!({ "type": "sync", "status_code": 200 }))
+                })
+                .delete(move |Path(name): Path<String>| async move {
+                    deleted.lock().unwrap().remove(&name);
+                    Json(serde_json::json!({ "type": "sync", "status_code": 200 }))
+                }),
+            );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        let tls = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(rustls::RootCertStore::empty())
+            .with_no_client_auth();
+        (IncusClient::new(format!("http://{}", addr), String::new(), None, Arc::new(tls)), volumes)
+    }
+
+    fn body() -> MediaStream {
+        futures::stream::iter([Ok(bytes::Bytes::from_static(ISO))]).boxed()
+    }
+
+    #[tokio::test]
+    async fn verified_upload_is_renamed_into_place() {
+        let (client, volumes) = serve_pool().await;
+        let sha256 = hex::encode(Sha256::digest(ISO));
+        let item = client.upload_media("default", None, "alpine.iso", ISO.len() as u64, Some(&sha256), body()).await.unwrap();
+        assert_eq!(item.id, "default:alpine");
+        assert_eq!(*volumes.lock().unwrap(), BTreeSet::from(["alpine".to_string()]));
+    }
+
+    #[tokio::test]
+    async fn mismatching_upload_is_never_kept() {
+        let (client, volumes) = serve_pool().await;
+        let err = client.upload_media("default", None, "alpine.iso", ISO.len() as u64, Some(&"0".repeat(64)), body()).await.unwrap_err();
+        let mismatch = err.downcast_ref::<ChecksumMismatch>().expect("checksum mismatch");
+        assert_eq!(mismatch.actual, hex::encode(Sha256::digest(ISO)));
+        assert!(volumes.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn unverified_upload_keeps_its_name() {
+        let (client, volumes) = serve_pool().await;
+        client.upload_media("default", None, "alpine.iso", ISO.len() as u64, None, body()).await.unwrap();
+        assert_eq!(*volumes.lock().unwrap(), BTreeSet::from(["alpine".to_string()]));
+    }
+
+    #[test]
+    fn image_fingerprints_are_full_sha256() {
+        assert!(is_image_fingerprint(&"a1".repeat(32)));
+        assert!(!is_image_fingerprint("default:alpine"));
+        assert!(!is_image_fingerprint(&"a1".repeat(6)));
+    }
 }
@@ -1,8 +1,128 @@
 pub mod proxmox;
 pub mod incus;
+pub mod registry;
+pub mod tls;
 
+use std::sync::Arc;
 use async_trait::async_trait;
+use crate::models::cloud_init::{CloudInitConfig, CloudInitUpdate};
+use crate::models::media::{MediaItem, MediaStorage};
 use crate::models::node::NodeStatus;
+use crate::models::resize::{ResizeLimits, ResizeOutcome, VmResize};
+
+/// A hypervisor host that belongs to the same cluster as the node we talk to
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ClusterMember {
+    pub name: String,
+    pub address: Option<String>,
+    pub online: bool,
+}
+
+/// Membership and quorum of the cluster a node belongs to
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ClusterStatus {
+    pub name: Option<String>,
+    pub quorate: bool,
+    pub members: Vec<ClusterMember>,
+}
+
+/// What we learn about a node from its API once credentials are accepted
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct NodeDiscovery {
+    pub version: String,
+    pub cluster_name: Option<String>,
+    pub members: Vec<ClusterMember>,
+    pub permissions: serde_json::Value,
+}
+
+/// Raw runtime counters for one guest; rates and percentages are derived by the caller
+#[derive(Debug, Clone, Default)]
+pub struct VmCounters {
+    pub status: String,
+    /// Instantaneous CPU usage as a fraction of all vCPUs (Proxmox)
+    pub cpu_fraction: Option<f64>,
+    /// Cumulative CPU time in nanoseconds (Incus)
+    pub cpu_time_ns: Option<u64>,
+    pub cpus: Option<f64>,
+    pub mem_used: u64,
+    pub mem_total: u64,
+    pub disk_read: Option<u64>,
+    pub disk_write: Option<u64>,
+    pub net_in: u64,
+    pub net_out: u64,
+    pub uptime: Option<u64>,
+}
+
+/// What a running guest reports about itself: QEMU guest agent on Proxmox, instance state on Incus
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct GuestInfo {
+    /// Whether an agent inside the guest answered; Proxmox containers report addresses without one
+    pub agent: bool,
+    pub ip_addresses: Vec<String>,
+    pub os_name: Option<String>,
+    pub os_version: Option<String>,
+}
+
+/// Guest agent filesystem freeze commands, e.g. around a backup or snapshot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FsFreezeAction {
+    Freeze,
+    Thaw,
+    Status,
+}
+
+/// Addresses worth showing for a guest: not loopback or link-local
+pub(crate) fn is_reachable_ip(address: &str) -> bool {
+    match address.parse::<std::net::IpAddr>() {
+        Ok(std::net::IpAddr::V4(ip)) => !ip.is_loopback() && !ip.is_link_local() && !ip.is_unspecified(),
+        Ok(std::net::IpAddr::V6(ip)) => !ip.is_loopback() && !ip.is_unspecified() && (ip.segments()[0] & 0xffc0) != 0xfe80,
+        Err(_) => false,
+    }
+}
+
+/// A guest's raw config and the version it was read at: Proxmox `digest` or Incus `ETag`
+#[derive(Debug, Clone)]
+pub struct VmDetails {
+    pub config: serde_json::Value,
+    pub digest: Option<String>,
+}
+
+/// The config changed since the digest an update was based on
+#[derive(Debug)]
+pub struct ConfigConflict;
+
+impl std::fmt::Display for ConfigConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("config was modified since it was read")
+    }
+}
+
+impl std::error::Error for ConfigConflict {}
+
+/// An upload did not match the checksum it was sent with and was not kept
+#[derive(Debug)]
+pub struct ChecksumMismatch {
+    pub actual: String,
+}
+
+impl std::fmt::Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "upload has sha256 {}, not the expected checksum", self.actual)
+    }
+}
+
+impl std::error::Error for ChecksumMismatch {}
+
+/// Storage, host and file names that are safe to put in an API path
+pub(crate) fn is_path_segment(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+}
+
+/// Upload bodies streamed from the dashboard's client to a node
+pub type MediaStream = futures::stream::BoxStream<'static, std::io::Result<bytes::Bytes>>;
 
 pub struct VncInfo {
     pub url: String,
@@ -10,13 +130,67 @@ pub struct VncInfo {
     pub port: u64,
 }
 
+/// Which text session to open for a guest
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TerminalMode {
+    /// The guest's serial/text console
+    #[default]
+    Console,
+    /// A login shell started inside the guest (Incus exec)
+    Shell,
+}
+
+/// Upstream endpoint(s) for an interactive text session
+pub enum TerminalTarget {
+    /// Proxmox termproxy behind `vncwebsocket`, speaking the xterm.js framing
+    Xterm { url: String, user: String, ticket: String },
+    /// Incus operation websockets: raw PTY bytes on `data`, JSON control commands on `control`
+    Incus { data_url: String, control_url: String },
+}
+
 #[async_trait]
 pub trait NodeClient {
     async fn check_health(&self) -> anyhow::Result<NodeStatus>;
     async fn list_vms(&self) -> anyhow::Result<Vec<serde_json::Value>>;
     async fn vm_power_action(&self, vm_id: &str, action: &str) -> anyhow::Result<()>;
-    async fn update_vm_config(&self, vm_id: &str, config: serde_json::Value) -> anyhow::Result<()>;
-    async fn get_vm_details(&self, vm_id: &str) -> anyhow::Result<serde_json::Value>;
+    /// With a `digest` from `get_vm_details`, fails with `ConfigConflict` if the config changed since
+    async fn update_vm_config(&self, vm_id: &str, config: serde_json::Value, digest: Option<&str>) -> anyhow::Result<()>;
+    async fn get_vm_details(&self, vm_id: &str) -> anyhow::Result<VmDetails>;
     async fn mount_media(&self, vm_id: &str, iso_path: &str) -> anyhow::Result<()>;
+    async fn eject_media(&self, vm_id: &str) -> anyhow::Result<()>;
+    /// Storages that can hold ISOs; `host` picks the Proxmox cluster member
+    async fn list_media_storages(&self, host: Option<&str>) -> anyhow::Result<Vec<MediaStorage>>;
+    /// ISOs in one storage, or in all of them when `storage` is `None`
+    async fn list_media(&self, storage: Option<&str>, host: Option<&str>) -> anyhow::Result<Vec<MediaItem>>;
+    /// Stream an ISO of exactly `size` bytes into a storage, returning once it is in place.
+    /// With `sha256`, a mismatching upload is never kept: Proxmox checks it itself, Incus
+    /// uploads under a temporary name and fails with `ChecksumMismatch` instead of renaming.
+    async fn upload_media(&self, storage: &str, host: Option<&str>, filename: &str, size: u64, sha256: Option<&str>, body: MediaStream) -> anyhow::Result<MediaItem>;
+    /// Delete an ISO by the id `list_media` returned
+    async fn delete_media(&self, id: &str, host: Option<&str>) -> anyhow::Result<()>;
     async fn get_vnc_info(&self, vm_id: &str) -> anyhow::Result<VncInfo>;
+    async fn open_terminal(&self, vm_id: &str, mode: TerminalMode, cols: u16, rows: u16) -> anyhow::Result<TerminalTarget>;
+    async fn discover(&self) -> anyhow::Result<NodeDiscovery>;
+    async fn cluster_status(&self) -> anyhow::Result<ClusterStatus>;
+    async fn vm_counters(&self, vm_id: &str) -> anyhow::Result<VmCounters>;
+    /// IPs and OS as seen from inside the guest; `agent` is false when it cannot be reached
+    async fn guest_info(&self, vm_id: &str) -> anyhow::Result<GuestInfo>;
+    /// Freeze, thaw or query guest filesystems through the agent, returning its raw result
+    async fn guest_fsfreeze(&self, vm_id: &str, action: FsFreezeAction) -> anyhow::Result<serde_json::Value>;
+    async fn get_cloud_init(&self, vm_id: &str) -> anyhow::Result<CloudInitConfig>;
+    /// Apply validated cloud-init changes; guests pick them up on next boot
+    async fn update_cloud_init(&self, vm_id: &str, changes: &CloudInitUpdate) -> anyhow::Result<()>;
+    /// Current shape of the guest and capacity of its host; `disk` picks the disk to grow
+    async fn resize_limits(&self, vm_id: &str, disk: Option<&str>) -> anyhow::Result<ResizeLimits>;
+    /// Apply a resize already checked against `resize_limits`
+    async fn resize_vm(&self, vm_id: &str, resize: &VmResize) -> anyhow::Result<ResizeOutcome>;
+
+    /// TLS settings for this node, shared with console WebSocket upstreams
+    fn tls_config(&self) -> Arc<rustls::ClientConfig>;
+
+    /// Access Proxmox-specific APIs (metrics, node discovery) through a shared client
+    fn as_proxmox(&self) -> Option<&proxmox::ProxmoxClient> {
+        None
+    }
 }
@@ -1,16 +1,40 @@
+use std::sync::Arc;
+use std::time::Duration;
 use async_trait::async_trait;
+use futures::StreamExt;
 use reqwest::{Client, header};
 use serde_json::Value;
+use crate::models::cloud_init::{CloudInitConfig, CloudInitUpdate, IpConfig};
+use crate::models::media::{ChecksumAlgorithm, MediaItem, MediaKind, MediaStorage};
 use crate::models::node::NodeStatus;
-use super::NodeClient;
+use crate::models::resize::{ResizeLimits, ResizeOutcome, VmResize};
+use super::{is_path_segment, is_reachable_ip, ClusterMember, ConfigConflict, MediaStream, VmDetails, ClusterStatus, FsFreezeAction, GuestInfo, NodeClient, NodeDiscovery, VmCounters};
+
+/// `ipconfig0` to `ipconfig31`, one per possible NIC
+const IP_CONFIG_SLOTS: usize = 32;
+
+const MIB: u64 = 1024 * 1024;
+const GIB: u64 = 1024 * MIB;
+
+/// How long an upload may take to be verified and copied into its storage after the body is sent
+const UPLOAD_TASK_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+const TASK_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Where a guest lives inside a Proxmox cluster
+pub struct VmLocation {
+    pub node: String,
+    pub vm_type: String,
+    pub vmid: String,
+}
 
 pub struct ProxmoxClient {
     client: Client,
     api_url: String,
+    tls: Arc<rustls::ClientConfig>,
 }
 
 impl ProxmoxClient {
-    pub fn new(api_url: String, api_key: String, api_secret: String) -> Self {
+    pub fn new(api_url: String, api_key: String, api_secret: String, tls: Arc<rustls::ClientConfig>) -> Self {
         let mut headers = header::HeaderMap::new();
         // API Secret is the Token Value for Proxmox
         let auth_value = format!("PVEAPIToken={}={}", api_key, api_secret);
@@ -21,13 +45,14 @@ impl ProxmoxClient {
 
         let client = Client::builder()
             .default_headers(headers)
-            .danger_accept_invalid_certs(true) // Often useful for internal nodes
+            .use_preconfigured_tls((*tls).clone())
             .build()
             .unwrap();
 
         Self {
             client,
             api_url,
+            tls,
         }
     }
 
@@ -48,26 +73,207 @@ impl ProxmoxClient {
         }
     }
 
-    /// Get the first node name from the Proxmox cluster
-    pub async fn get_node_name(&self) -> anyhow::Result<String> {
-        let url = format!("{}/api2/json/nodes", self.api_url);
-        let resp = self.client.get(&url).send().await?;
-        
-        if !resp.status().is_success() {
-            anyhow::bail!("Failed to query Proxmox nodes: {}", resp.status());
+    /// Resolve a guest id to the cluster member currently hosting it.
+    ///
+    /// Accepts the `node/type/vmid` internal id from the VM list, `type/vmid`, or a bare VMID;
+    /// the latter two are looked up in `/cluster/resources` so migrated guests are found.
+    pub async fn resolve_vm(&self, vm_id: &str) -> anyhow::Result<VmLocation> {
+        let parts: Vec<&str> = vm_id.split('/').collect();
+        if let [node, vm_type, vmid] = parts[..] {
+            return Ok(VmLocation {
+                node: node.to_string(),
+                vm_type: vm_type.to_string(),
+                vmid: vmid.to_string(),
+            });
         }
-        
-        let data: Value = resp.json().await?;
-        let nodes = data["data"].as_array()
-            .ok_or_else(|| anyhow::anyhow!("No nodes in Proxmox response"))?;
-        
-        if let Some(first_node) = nodes.first() {
-            if let Some(node_name) = first_node["node"].as_str() {
-                return Ok(node_name.to_string());
+
+        let vmid = parts.last().copied().unwrap_or(vm_id);
+        let resources: Vec<Value> = self.get_json(&format!("{}/api2/json/cluster/resources?type=vm", self.api_url)).await?;
+        let resource = resources.iter()
+            .find(|r| r["vmid"].as_u64().map(|id| id.to_string()).as_deref() == Some(vmid))
+            .ok_or_else(|| anyhow::anyhow!("VM {} not found in Proxmox cluster", vmid))?;
+
+        Ok(VmLocation {
+            node: resource["node"].as_str().unwrap_or_default().to_string(),
+            vm_type: resource["type"].as_str().unwrap_or("qemu").to_string(),
+            vmid: vmid.to_string(),
+        })
+    }
+
+    /// Run a QEMU guest agent command and return its `result`. Fails when the agent is not
+    /// enabled in the VM config or not running, which is routine and left to callers to log.
+    async fn agent_command(&self, location: &VmLocation, method: reqwest::Method, command: &str) -> anyhow::Result<Value> {
+        if location.vm_type != "qemu" {
+            anyhow::bail!("The guest agent is only available for QEMU VMs");
+        }
+
+        let url = format!("{}/api2/json/nodes/{}/qemu/{}/agent/{}", self.api_url, location.node, location.vmid, command);
+        let resp = self.client.request(method, &url).send().await?;
+
+        if resp.status().is_success() {
+            let data: Value = resp.json().await?;
+            Ok(data["data"]["result"].clone())
+        } else {
+            let err_text = resp.text().await.unwrap_or_default();
+            anyhow::bail!("Guest agent {} failed: {}", command, err_text.trim())
+        }
+    }
+
+    /// Addresses of a running container, which Proxmox reads without any agent
+    async fn container_ips(&self, location: &VmLocation) -> anyhow::Result<Vec<String>> {
+        let url = format!("{}/api2/json/nodes/{}/lxc/{}/interfaces", self.api_url, location.node, location.vmid);
+        let interfaces: Vec<Value> = self.get_json(&url).await?;
+
+        let mut ips: Vec<String> = Vec::new();
+        for nic in &interfaces {
+            // "inet" and "inet6" are CIDR strings, e.g. "10.0.0.5/24"
+            for cidr in [&nic["inet"], &nic["inet6"]].into_iter().filter_map(|a| a.as_str()) {
+                let ip = cidr.split('/').next().unwrap_or(cidr).to_string();
+                if is_reachable_ip(&ip) && !ips.contains(&ip) {
+                    ips.push(ip);
+                }
             }
         }
-        
-        anyhow::bail!("No nodes found in Proxmox cluster")
+        Ok(ips)
+    }
+
+    /// Press a key combination (QEMU key names, e.g. `ctrl-alt-delete`) on a QEMU guest
+    pub async fn send_key(&self, vm_id: &str, keys: &str) -> anyhow::Result<()> {
+        let VmLocation { node, vm_type, vmid } = self.resolve_vm(vm_id).await?;
+        if vm_type != "qemu" {
+            anyhow::bail!("sendkey is only available for QEMU VMs, not {}", vm_type);
+        }
+
+        let url = format!("{}/api2/json/nodes/{}/qemu/{}/sendkey", self.api_url, node, vmid);
+        let resp = self.client.put(&url).json(&serde_json::json!({ "key": keys })).send().await?;
+
+        if resp.status().is_success() {
+            Ok(())
+        } else {
+            let err_text = resp.text().await.unwrap_or_default();
+            anyhow::bail!("Proxmox sendkey failed: {} - {}", keys, err_text)
+        }
+    }
+
+    /// Free space on the storage holding a disk (`local-lvm:vm-100-disk-0,size=32G`), if known
+    async fn storage_free_gib(&self, node: &str, disk_spec: &str) -> Option<u64> {
+        let (storage, _) = disk_spec.split_once(':')?;
+        let url = format!("{}/api2/json/nodes/{}/storage/{}/status", self.api_url, node, storage);
+        match self.get_json::<Value>(&url).await {
+            Ok(status) => status["avail"].as_u64().map(|bytes| bytes / GIB),
+            Err(e) => {
+                tracing::debug!("No free space for storage {}: {}", storage, e);
+                None
+            }
+        }
+    }
+
+    /// The cluster member media requests go to: `host` if given, else the first one online
+    async fn media_host(&self, host: Option<&str>) -> anyhow::Result<String> {
+        if let Some(host) = host {
+            if !is_path_segment(host) {
+                anyhow::bail!("Invalid Proxmox node name {:?}", host);
+            }
+            return Ok(host.to_string());
+        }
+
+        let nodes: Vec<Value> = self.get_json(&format!("{}/api2/json/nodes", self.api_url)).await?;
+        let mut online: Vec<&str> = nodes.iter()
+            .filter(|n| n["status"] == "online")
+            .filter_map(|n| n["node"].as_str())
+            .collect();
+        online.sort();
+        online.first()
+            .map(|n| n.to_string())
+            .ok_or_else(|| anyhow::anyhow!("No Proxmox node is online"))
+    }
+
+    /// Have a node fetch an ISO itself, verifying `checksum` when given.
+    /// Returns the task id (UPID) to follow with `task_status`.
+    pub async fn download_media(
+        &self,
+        storage: &str,
+        host: Option<&str>,
+        url: &str,
+        filename: &str,
+        checksum: Option<(&str, ChecksumAlgorithm)>,
+        verify_certificates: bool,
+    ) -> anyhow::Result<String> {
+        let host = self.media_host(host).await?;
+        let mut body = serde_json::json!({
+            "content": "iso",
+            "filename": filename,
+            "url": url,
+            "verify-certificates": if verify_certificates { 1 } else { 0 },
+        });
+        if let Some((checksum, algorithm)) = checksum {
+            body["checksum"] = Value::String(checksum.to_string());
+            body["checksum-algorithm"] = serde_json::to_value(algorithm)?;
+        }
+
+        let api_url = format!("{}/api2/json/nodes/{}/storage/{}/download-url", self.api_url, host, storage);
+        let resp = self.client.post(&api_url).json(&body).send().await?;
+        if resp.status().is_success() {
+            let data: Value = resp.json().await?;
+            data["data"].as_str()
+                .map(str::to_string)
+                .ok_or_else(|| anyhow::anyhow!("Proxmox returned no task for the download"))
+        } else {
+            let err_text = resp.text().await.unwrap_or_default();
+            anyhow::bail!("Proxmox download of {} failed: {}", url, err_text)
+        }
+    }
+
+    /// State of a node task such as a download; `exitstatus` is `OK` once it succeeded
+    pub async fn task_status(&self, upid: &str) -> anyhow::Result<Value> {
+        // UPID:<node>:<pid>:...
+        let host = upid.split(':').nth(1)
+            .filter(|host| upid.starts_with("UPID:") && is_path_segment(host))
+            .ok_or_else(|| anyhow::anyhow!("Invalid task id {:?}", upid))?;
+        let url = format!("{}/api2/json/nodes/{}/tasks/{}/status", self.api_url, host, urlencoding::encode(upid));
+        self.get_json(&url).await
+    }
+
+    /// Poll a task until it stops, failing unless it ended with `OK`
+    async fn wait_for_task(&self, upid: &str, timeout: Duration) -> anyhow::Result<()> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            let status = self.task_status(upid).await?;
+            if status["status"] == "stopped" {
+                return match status["exitstatus"].as_str() {
+                    Some("OK") => Ok(()),
+                    other => anyhow::bail!("task {} ended with {}", upid, other.unwrap_or("no exit status")),
+                };
+            }
+            if tokio::time::Instant::now() >= deadline {
+                anyhow::bail!("task {} still running after {:?}", upid, timeout);
+            }
+            tokio::time::sleep(TASK_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Cloud-init drives only exist for QEMU VMs; containers are configured directly
+    async fn resolve_qemu(&self, vm_id: &str) -> anyhow::Result<VmLocation> {
+        let location = self.resolve_vm(vm_id).await?;
+        if location.vm_type != "qemu" {
+            anyhow::bail!("Cloud-init is only available for QEMU VMs, not {}", location.vm_type);
+        }
+        Ok(location)
+    }
+
+    /// Rebuild the cloud-init drive from the current config without waiting for a restart
+    pub async fn regenerate_cloud_init(&self, vm_id: &str) -> anyhow::Result<()> {
+        let VmLocation { node, vmid, .. } = self.resolve_qemu(vm_id).await?;
+
+        let url = format!("{}/api2/json/nodes/{}/qemu/{}/cloudinit", self.api_url, node, vmid);
+        let resp = self.client.put(&url).send().await?;
+
+        if resp.status().is_success() {
+            Ok(())
+        } else {
+            let err_text = resp.text().await.unwrap_or_default();
+            anyhow::bail!("Proxmox cloud-init regeneration failed: {}", err_text)
+        }
     }
 }
 
@@ -112,18 +318,16 @@ impl NodeClient for ProxmoxClient {
     }
 
     async fn vm_power_action(&self, vm_id: &str, action: &str) -> anyhow::Result<()> {
-        // Parse vm_id which might be in format "node/type/vmid" from cluster resources
-        // or just a vmid. If it's just a vmid, we might need more info.
-        // For simplicity, let's assume the frontend passes the path or we assume a default node.
-        
-        let parts: Vec<&str> = vm_id.split('/').collect();
-        let (node, vm_type, vmid) = if parts.len() == 3 {
-            (parts[0], parts[1], parts[2])
-        } else {
-            // Fallback for single node setup if only vmid is provided
-            ("pve", "qemu", vm_id)
-        };
+        let location = self.resolve_vm(vm_id).await?;
 
+        // A responsive guest agent shuts down cleanly even when the guest ignores ACPI
+        if action == "shutdown" && location.vm_type == "qemu" && self.agent_command(&location, reqwest::Method::POST, "ping").await.is_ok() {
+            self.agent_command(&location, reqwest::Method::POST, "shutdown").await?;
+            tracing::info!("🔌 Shut down {} through the guest agent", vm_id);
+            return Ok(());
+        }
+
+        let VmLocation { node, vm_type, vmid } = location;
         let url = format!("{}/api2/json/nodes/{}/{}/{}/status/{}", self.api_url, node, vm_type, vmid, action);
         let resp = self.client.post(&url).send().await?;
 
@@ -135,51 +339,48 @@ impl NodeClient for ProxmoxClient {
         }
     }
 
-    async fn update_vm_config(&self, vm_id: &str, config: Value) -> anyhow::Result<()> {
-        let parts: Vec<&str> = vm_id.split('/').collect();
-        let (node, vm_type, vmid) = if parts.len() == 3 {
-            (parts[0], parts[1], parts[2])
-        } else {
-            ("pve", "qemu", vm_id)
-        };
+    async fn update_vm_config(&self, vm_id: &str, mut config: Value, digest: Option<&str>) -> anyhow::Result<()> {
+        let VmLocation { node, vm_type, vmid } = self.resolve_vm(vm_id).await?;
 
+        if let (Some(digest), Some(params)) = (digest, config.as_object_mut()) {
+            params.insert("digest".to_string(), Value::String(digest.to_string()));
+        }
+
+        // PUT checks the digest before answering; POST would only fail inside a background task
         let url = format!("{}/api2/json/nodes/{}/{}/{}/config", self.api_url, node, vm_type, vmid);
-        let resp = self.client.post(&url).json(&config).send().await?;
+        let resp = self.client.put(&url).json(&config).send().await?;
 
         if resp.status().is_success() {
             Ok(())
         } else {
             let err_text = resp.text().await.unwrap_or_default();
+            if err_text.contains("detected modified configuration") {
+                return Err(ConfigConflict.into());
+            }
             anyhow::bail!("Proxmox config update failed: {}", err_text)
         }
     }
 
-    async fn get_vm_details(&self, vm_id: &str) -> anyhow::Result<Value> {
-        let parts: Vec<&str> = vm_id.split('/').collect();
-        let (node, vm_type, vmid) = if parts.len() == 3 {
-            (parts[0], parts[1], parts[2])
-        } else {
-            ("pve", "qemu", vm_id)
-        };
+    async fn get_vm_details(&self, vm_id: &str) -> anyhow::Result<VmDetails> {
+        let VmLocation { node, vm_type, vmid } = self.resolve_vm(vm_id).await?;
 
         let url = format!("{}/api2/json/nodes/{}/{}/{}/config", self.api_url, node, vm_type, vmid);
         let resp = self.client.get(&url).send().await?;
 
         if resp.status().is_success() {
             let data: Value = resp.json().await?;
-            Ok(data["data"].clone())
+            let config = data["data"].clone();
+            Ok(VmDetails {
+                digest: config["digest"].as_str().map(str::to_string),
+                config,
+            })
         } else {
             anyhow::bail!("Failed to get Proxmox VM details: {}", resp.status())
         }
     }
 
     async fn mount_media(&self, vm_id: &str, iso_path: &str) -> anyhow::Result<()> {
-        let parts: Vec<&str> = vm_id.split('/').collect();
-        let (node, vm_type, vmid) = if parts.len() == 3 {
-            (parts[0], parts[1], parts[2])
-        } else {
-            ("pve", "qemu", vm_id)
-        };
+        let VmLocation { node, vm_type, vmid } = self.resolve_vm(vm_id).await?;
 
         let url = format!("{}/api2/json/nodes/{}/{}/{}/config", self.api_url, node, vm_type, vmid);
         let config = serde_json::json!({
@@ -196,15 +397,143 @@ impl NodeClient for ProxmoxClient {
         }
     }
 
-    async fn get_vnc_info(&self, vm_id: &str) -> anyhow::Result<super::VncInfo> {
-        let parts: Vec<&str> = vm_id.split('/').collect();
-        let (node, vm_type, vmid) = if parts.len() == 3 {
-            (parts[0].to_string(), parts[1], parts[2])
+    async fn eject_media(&self, vm_id: &str) -> anyhow::Result<()> {
+        let VmLocation { node, vm_type, vmid } = self.resolve_vm(vm_id).await?;
+        if vm_type != "qemu" {
+            anyhow::bail!("Only QEMU VMs have a CD drive, not {}", vm_type);
+        }
+
+        // The same drive `mount_media` uses, left in place but empty
+        let url = format!("{}/api2/json/nodes/{}/qemu/{}/config", self.api_url, node, vmid);
+        let resp = self.client.put(&url).json(&serde_json::json!({ "ide2": "none,media=cdrom" })).send().await?;
+
+        if resp.status().is_success() {
+            Ok(())
         } else {
-            // If only VMID is provided, try to resolve the actual node name instead of defaulting to "pve"
-            let resolved_node = self.get_node_name().await.unwrap_or_else(|_| "pve".to_string());
-            (resolved_node.to_string(), "qemu", vm_id)
+            let err_text = resp.text().await.unwrap_or_default();
+            anyhow::bail!("Proxmox media eject failed: {}", err_text)
+        }
+    }
+
+    async fn list_media_storages(&self, host: Option<&str>) -> anyhow::Result<Vec<MediaStorage>> {
+        let host = self.media_host(host).await?;
+        let url = format!("{}/api2/json/nodes/{}/storage?content=iso&enabled=1", self.api_url, host);
+        let storages: Vec<Value> = self.get_json(&url).await?;
+
+        Ok(storages.iter()
+            .filter_map(|s| Some(MediaStorage {
+                name: s["storage"].as_str()?.to_string(),
+                storage_type: s["type"].as_str().unwrap_or_default().to_string(),
+                total: s["total"].as_u64(),
+                available: s["avail"].as_u64(),
+            }))
+            .collect())
+    }
+
+    async fn list_media(&self, storage: Option<&str>, host: Option<&str>) -> anyhow::Result<Vec<MediaItem>> {
+        let host = self.media_host(host).await?;
+        let storages = match storage {
+            Some(storage) => vec![storage.to_string()],
+            None => self.list_media_storages(Some(&host)).await?.into_iter().map(|s| s.name).collect(),
+        };
+
+        let mut items = Vec::new();
+        for storage in storages {
+            let url = format!("{}/api2/json/nodes/{}/storage/{}/content?content=iso", self.api_url, host, storage);
+            let content: Vec<Value> = self.get_json(&url).await?;
+            items.extend(content.iter().filter_map(|c| {
+                // `local:iso/debian-12.iso`
+                let volid = c["volid"].as_str()?;
+                Some(MediaItem {
+                    id: volid.to_string(),
+                    name: volid.split_once("iso/").map_or(volid, |(_, name)| name).to_string(),
+                    kind: MediaKind::Iso,
+                    storage: storage.clone(),
+                    size: c["size"].as_u64(),
+                    created_at: c["ctime"].as_i64().and_then(|t| chrono::DateTime::from_timestamp(t, 0)),
+                })
+            }));
+        }
+        Ok(items)
+    }
+
+    async fn upload_media(&self, storage: &str, host: Option<&str>, filename: &str, size: u64, sha256: Option<&str>, body: MediaStream) -> anyhow::Result<MediaItem> {
+        let host = self.media_host(host).await?;
+
+        // Multipart framing is written by hand so the total length is known up front;
+        // pveproxy spools uploads to disk by Content-Length
+        let boundary = format!("crate-upload-{}", hex::encode(rand::random::<[u8; 12]>()));
+        let field = |name: &str, value: &str| {
+            format!("--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n", boundary, name, value)
         };
+        let mut head = field("content", "iso");
+        // The node verifies the file before moving it into the storage
+        if let Some(sha256) = sha256 {
+            head.push_str(&field("checksum", sha256));
+            head.push_str(&field("checksum-algorithm", "sha256"));
+        }
+        head.push_str(&format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"filename\"; filename=\"{f}\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\n",
+            b = boundary,
+            f = filename,
+        ));
+        let tail = format!("\r\n--{}--\r\n", boundary);
+        let length = head.len() as u64 + size + tail.len() as u64;
+        let stream = futures::stream::once(async move { Ok(bytes::Bytes::from(head)) })
+            .chain(body)
+            .chain(futures::stream::once(async move { Ok(bytes::Bytes::from(tail)) }));
+
+        let url = format!("{}/api2/json/nodes/{}/storage/{}/upload", self.api_url, host, storage);
+        let resp = self.client.post(&url)
+            .header(header::CONTENT_TYPE, format!("multipart/form-data; boundary={}", boundary))
+            .header(header::CONTENT_LENGTH, length)
+            .body(reqwest::Body::wrap_stream(stream))
+            .send()
+            .await?;
+
+        if resp.status().is_success() {
+            // The file is only copied into the storage by a task that runs after the request
+            let data: Value = resp.json().await?;
+            let upid = data["data"].as_str()
+                .ok_or_else(|| anyhow::anyhow!("Proxmox returned no task for the upload"))?;
+            self.wait_for_task(upid, UPLOAD_TASK_TIMEOUT).await
+                .map_err(|e| anyhow::anyhow!("Proxmox upload of {} failed: {}", filename, e))?;
+
+            Ok(MediaItem {
+                id: format!("{}:iso/{}", storage, filename),
+                name: filename.to_string(),
+                kind: MediaKind::Iso,
+                storage: storage.to_string(),
+                size: Some(size),
+                created_at: Some(chrono::Utc::now()),
+            })
+        } else {
+            let err_text = resp.text().await.unwrap_or_default();
+            anyhow::bail!("Proxmox upload of {} failed: {}", filename, err_text)
+        }
+    }
+
+    async fn delete_media(&self, id: &str, host: Option<&str>) -> anyhow::Result<()> {
+        let host = self.media_host(host).await?;
+        let storage = id.split_once(":iso/")
+            .map(|(storage, _)| storage)
+            .filter(|storage| is_path_segment(storage))
+            .ok_or_else(|| anyhow::anyhow!("{:?} is not an ISO volume", id))?;
+
+        let url = format!("{}/api2/json/nodes/{}/storage/{}/content/{}", self.api_url, host, storage, urlencoding::encode(id));
+        let resp = self.client.delete(&url).send().await?;
+
+        if resp.status().is_success() {
+            Ok(())
+        } else {
+            let err_text = resp.text().await.unwrap_or_default();
+            anyhow::bail!("Proxmox delete of {} failed: {}", id, err_text)
+        }
+    }
+
+    async fn get_vnc_info(&self, vm_id: &str) -> anyhow::Result<super::VncInfo> {
+        let VmLocation { node, vm_type, vmid } = self.resolve_vm(vm_id).await?;
 
         // For LXC containers, we use vncproxy (same as QEMU)
         let proxy_url = format!("{}/api2/json/nodes/{}/{}/{}/vncproxy", self.api_url, node, vm_type, vmid);
@@ -267,5 +596,483 @@ impl NodeClient for ProxmoxClient {
             port,
         })
     }
+
+    async fn open_terminal(&self, vm_id: &str, mode: super::TerminalMode, _cols: u16, _rows: u16) -> anyhow::Result<super::TerminalTarget> {
+        if mode == super::TerminalMode::Shell {
+            anyhow::bail!("Proxmox guests only offer a console session; shells require SSH into the guest");
+        }
+
+        let VmLocation { node, vm_type, vmid } = self.resolve_vm(vm_id).await?;
+
+        // termproxy attaches to the container console or the VM's serial port
+        let proxy_url = format!("{}/api2/json/nodes/{}/{}/{}/termproxy", self.api_url, node, vm_type, vmid);
+        let resp = self.client.post(&proxy_url).send().await?;
+
+        let status = resp.status();
+        if !status.is_success() {
+            let err_text = resp.text().await.unwrap_or_default();
+            tracing::error!("❌ Terminal proxy request failed: {} - {}", status, err_text);
+            anyhow::bail!("Failed to get Proxmox terminal proxy: {} - {}", status, err_text);
+        }
+
+        let data: Value = resp.json().await?;
+        let proxy_data = &data["data"];
+        let ticket = proxy_data["ticket"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("No ticket in terminal proxy response"))?;
+        let port = proxy_data["port"]
+            .as_u64()
+            .or_else(|| proxy_data["port"].as_str().and_then(|s| s.parse().ok()))
+            .ok_or_else(|| anyhow::anyhow!("No port in terminal proxy response"))?;
+        let user = proxy_data["user"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("No user in terminal proxy response"))?;
+
+        tracing::info!("Terminal ticket generated for {}:{}/{} (port: {})", node, vm_type, vmid, port);
+
+        let ws_host = self.api_url
+            .replace("https://", "wss://")
+            .replace("http://", "ws://")
+            .trim_end_matches('/')
+            .to_string();
+
+        Ok(super::TerminalTarget::Xterm {
+            url: format!(
+                "{}/api2/json/nodes/{}/{}/{}/vncwebsocket?port={}&vncticket={}",
+                ws_host, node, vm_type, vmid, port, urlencoding::encode(ticket)
+            ),
+            user: user.to_string(),
+            ticket: ticket.to_string(),
+        })
+    }
+
+    /// Cluster name, quorum and members from `/cluster/status` (a standalone host reports itself)
+    async fn cluster_status(&self) -> anyhow::Result<ClusterStatus> {
+        let status: Vec<Value> = self.get_json(&format!("{}/api2/json/cluster/status", self.api_url)).await?;
+
+        let cluster = status.iter().find(|entry| entry["type"] == "cluster");
+        let members = status.iter()
+            .filter(|entry| entry["type"] == "node")
+            .map(|entry| ClusterMember {
+                name: entry["name"].as_str().unwrap_or_default().to_string(),
+                address: entry["ip"].as_str().map(|s| s.to_string()),
+                online: entry["online"].as_u64() == Some(1),
+            })
+            .collect();
+
+        Ok(ClusterStatus {
+            name: cluster.and_then(|c| c["name"].as_str()).map(|s| s.to_string()),
+            // Without a cluster entry the host is standalone and trivially quorate
+            quorate: cluster.map(|c| c["quorate"].as_u64() == Some(1)).unwrap_or(true),
+            members,
+        })
+    }
+
+    async fn vm_counters(&self, vm_id: &str) -> anyhow::Result<VmCounters> {
+        let VmLocation { node, vm_type, vmid } = self.resolve_vm(vm_id).await?;
+        let url = format!("{}/api2/json/nodes/{}/{}/{}/status/current", self.api_url, node, vm_type, vmid);
+        let status: Value = self.get_json(&url).await?;
+
+        Ok(VmCounters {
+            status: status["status"].as_str().unwrap_or("unknown").to_string(),
+            cpu_fraction: status["cpu"].as_f64(),
+            cpu_time_ns: None,
+            cpus: status["cpus"].as_f64().or(status["maxcpu"].as_f64()),
+            mem_used: status["mem"].as_u64().unwrap_or(0),
+            mem_total: status["maxmem"].as_u64().unwrap_or(0),
+            disk_read: status["diskread"].as_u64(),
+            disk_write: status["diskwrite"].as_u64(),
+            net_in: status["netin"].as_u64().unwrap_or(0),
+            net_out: status["netout"].as_u64().unwrap_or(0),
+            uptime: status["uptime"].as_u64(),
+        })
+    }
+
+    async fn guest_info(&self, vm_id: &str) -> anyhow::Result<GuestInfo> {
+        let location = self.resolve_vm(vm_id).await?;
+
+        // Containers have no guest agent; their addresses come from the host
+        if location.vm_type == "lxc" {
+            return Ok(match self.container_ips(&location).await {
+                Ok(ip_addresses) => GuestInfo { agent: false, ip_addresses, ..Default::default() },
+                Err(e) => {
+                    tracing::debug!("No interfaces for container {}: {}", vm_id, e);
+                    GuestInfo::default()
+                }
+            });
+        }
+
+        let interfaces = match self.agent_command(&location, reqwest::Method::GET, "network-get-interfaces").await {
+            Ok(interfaces) => interfaces,
+            Err(e) => {
+                tracing::debug!("Guest agent unavailable for {}: {}", vm_id, e);
+                return Ok(GuestInfo::default());
+            }
+        };
+
+        let mut ip_addresses: Vec<String> = Vec::new();
+        for nic in interfaces.as_array().into_iter().flatten() {
+            for address in nic["ip-addresses"].as_array().into_iter().flatten() {
+                if let Some(ip) = address["ip-address"].as_str().filter(|ip| is_reachable_ip(ip)) {
+                    if !ip_addresses.iter().any(|known| known == ip) {
+                        ip_addresses.push(ip.to_string());
+                    }
+                }
+            }
+        }
+
+        // Older agents (and Windows without the right build) lack get-osinfo
+        let os = self.agent_command(&location, reqwest::Method::GET, "get-osinfo").await.unwrap_or(Value::Null);
+
+        Ok(GuestInfo {
+            agent: true,
+            ip_addresses,
+            os_name: os["pretty-name"].as_str().or(os["name"].as_str()).map(str::to_string),
+            os_version: os["version"].as_str().or(os["version-id"].as_str()).map(str::to_string),
+        })
+    }
+
+    async fn guest_fsfreeze(&self, vm_id: &str, action: FsFreezeAction) -> anyhow::Result<Value> {
+        let location = self.resolve_vm(vm_id).await?;
+        let command = match action {
+            FsFreezeAction::Freeze => "fsfreeze-freeze",
+            FsFreezeAction::Thaw => "fsfreeze-thaw",
+            FsFreezeAction::Status => "fsfreeze-status",
+        };
+        self.agent_command(&location, reqwest::Method::POST, command).await
+    }
+
+    async fn get_cloud_init(&self, vm_id: &str) -> anyhow::Result<CloudInitConfig> {
+        let VmLocation { node, vmid, .. } = self.resolve_qemu(vm_id).await?;
+        let url = format!("{}/api2/json/nodes/{}/qemu/{}/config", self.api_url, node, vmid);
+        let config: Value = self.get_json(&url).await?;
+
+        // sshkeys is stored URL-encoded, one key per line
+        let ssh_keys = config["sshkeys"].as_str()
+            .map(|encoded| urlencoding::decode(encoded).map(|keys| keys.into_owned()).unwrap_or_default())
+            .unwrap_or_default()
+            .lines()
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        let ip_config_count = (0..IP_CONFIG_SLOTS).rev()
+            .find(|i| config.get(format!("ipconfig{}", i)).is_some())
+            .map_or(0, |last| last + 1);
+        let ip_configs = (0..ip_config_count)
+            .map(|i| config[format!("ipconfig{}", i)].as_str().map(parse_ip_config).unwrap_or_default())
+            .collect();
+
+        Ok(CloudInitConfig {
+            user: config["ciuser"].as_str().map(str::to_string),
+            password_set: config["cipassword"].as_str().is_some_and(|p| !p.is_empty()),
+            ssh_keys,
+            ip_configs,
+            nameservers: config["nameserver"].as_str().unwrap_or_default().split_whitespace().map(str::to_string).collect(),
+            search_domain: config["searchdomain"].as_str().map(str::to_string),
+            user_data: None,
+            network_config: None,
+        })
+    }
+
+    async fn update_cloud_init(&self, vm_id: &str, changes: &CloudInitUpdate) -> anyhow::Result<()> {
+        let VmLocation { node, vmid, .. } = self.resolve_qemu(vm_id).await?;
+        let url = format!("{}/api2/json/nodes/{}/qemu/{}/config", self.api_url, node, vmid);
+
+        let mut params = serde_json::Map::new();
+        let mut delete: Vec<String> = Vec::new();
+        let mut set_or_delete = |key: &str, value: Option<String>| match value {
+            Some(value) if !value.is_empty() => {
+                params.insert(key.to_string(), Value::String(value));
+            }
+            _ => delete.push(key.to_string()),
+        };
+
+        if let Some(user) = &changes.user {
+            set_or_delete("ciuser", Some(user.clone()));
+        }
+        if let Some(password) = &changes.password {
+            set_or_delete("cipassword", Some(password.clone()));
+        }
+        if let Some(keys) = &changes.ssh_keys {
+            // Proxmox wants the newline-separated keys percent-encoded (spaces as %20) inside
+            // the request body; the JSON body keeps it from being encoded a second time
+            let joined = keys.iter().map(|k| k.trim()).collect::<Vec<_>>().join("\n");
+            set_or_delete("sshkeys", Some(urlencoding::encode(&joined).into_owned()));
+        }
+        if let Some(configs) = &changes.ip_configs {
+            let current: Value = self.get_json(&url).await?;
+            for i in 0..IP_CONFIG_SLOTS {
+                let key = format!("ipconfig{}", i);
+                match configs.get(i) {
+                    Some(config) => set_or_delete(&key, Some(format_ip_config(config))),
+                    None if current.get(&key).is_some() => set_or_delete(&key, None),
+                    None => {}
+                }
+            }
+        }
+        if let Some(nameservers) = &changes.nameservers {
+            set_or_delete("nameserver", Some(nameservers.join(" ")));
+        }
+        if let Some(domain) = &changes.search_domain {
+            set_or_delete("searchdomain", Some(domain.clone()));
+        }
+
+        if !delete.is_empty() {
+            params.insert("delete".to_string(), Value::String(delete.join(",")));
+        }
+        if params.is_empty() {
+            return Ok(());
+        }
+
+        let resp = self.client.post(&url).json(&params).send().await?;
+        if resp.status().is_success() {
+            Ok(())
+        } else {
+            let err_text = resp.text().await.unwrap_or_default();
+            anyhow::bail!("Proxmox cloud-init update failed: {}", err_text)
+        }
+    }
+
+    async fn resize_limits(&self, vm_id: &str, disk: Option<&str>) -> anyhow::Result<ResizeLimits> {
+        let VmLocation { node, vm_type, vmid } = self.resolve_vm(vm_id).await?;
+        let qemu = vm_type == "qemu";
+        let host: Value = self.get_json(&format!("{}/api2/json/nodes/{}/status", self.api_url, node)).await?;
+        let config: Value = self.get_json(&format!("{}/api2/json/nodes/{}/{}/{}/config", self.api_url, node, vm_type, vmid)).await?;
+        let host_cpus = host["cpuinfo"]["cpus"].as_u64().unwrap_or(1) as u32;
+
+        let disk = match disk {
+            Some(key) => config[key].as_str().filter(|spec| is_resizable_disk(key, spec, qemu)).map(|_| key.to_string()),
+            None => boot_disk(&config, qemu),
+        };
+        let disk_free_gib = match disk.as_deref().and_then(|key| config[key].as_str()) {
+            Some(spec) => self.storage_free_gib(&node, spec).await,
+            None => None,
+        };
+
+        Ok(ResizeLimits {
+            host_cpus,
+            host_memory_mib: host["memory"]["total"].as_u64().unwrap_or(0) / MIB,
+            // Containers without a cores limit may use every host CPU
+            cores: config_number(&config["cores"]).map_or(if qemu { 1 } else { host_cpus }, |c| c as u32),
+            sockets: config_number(&config["sockets"]).unwrap_or(1) as u32,
+            memory_mib: config_number(&config["memory"]).unwrap_or(512),
+            supports_sockets: qemu,
+            supports_balloon: qemu,
+            disk,
+            disk_free_gib,
+        })
+    }
+
+    async fn resize_vm(&self, vm_id: &str, resize: &VmResize) -> anyhow::Result<ResizeOutcome> {
+        let VmLocation { node, vm_type, vmid } = self.resolve_vm(vm_id).await?;
+        let base = format!("{}/api2/json/nodes/{}/{}/{}", self.api_url, node, vm_type, vmid);
+
+        let mut params = serde_json::Map::new();
+        for (key, value) in [
+            ("cores", resize.cores.map(u64::from)),
+            ("sockets", resize.sockets.map(u64::from)),
+            ("memory", resize.memory_mib),
+            ("balloon", resize.balloon_mib),
+        ] {
+            if let Some(value) = value {
+                params.insert(key.to_string(), value.into());
+            }
+        }
+
+        if !params.is_empty() {
+            // PUT applies synchronously, so pending changes can be read right after
+            let resp = self.client.put(format!("{}/config", base)).json(&params).send().await?;
+            if !resp.status().is_success() {
+                let err_text = resp.text().await.unwrap_or_default();
+                anyhow::bail!("Proxmox resize failed: {}", err_text);
+            }
+        }
+
+        if let Some(grow) = resize.disk_grow_gib {
+            let disk = resize.disk.as_deref().ok_or_else(|| anyhow::anyhow!("No disk to grow"))?;
+            let body = serde_json::json!({ "disk": disk, "size": format!("+{}G", grow) });
+            let resp = self.client.put(format!("{}/resize", base)).json(&body).send().await?;
+            if !resp.status().is_success() {
+                let err_text = resp.text().await.unwrap_or_default();
+                anyhow::bail!("Proxmox disk resize of {} failed: {}", disk, err_text);
+            }
+        }
+
+        // Running guests keep changes that cannot be hotplugged as pending until restarted
+        let pending: Vec<Value> = if params.is_empty() {
+            Vec::new()
+        } else {
+            self.get_json(&format!("{}/pending", base)).await?
+        };
+        let reboot_required = pending.iter().any(|entry| {
+            entry["key"].as_str().is_some_and(|key| params.contains_key(key))
+                && (entry.get("pending").is_some() || entry.get("delete").is_some())
+        });
+
+        Ok(ResizeOutcome { reboot_required })
+    }
+
+    async fn discover(&self) -> anyhow::Result<NodeDiscovery> {
+        let version: Value = self.get_json(&format!("{}/api2/json/version", self.api_url)).await?;
+        let cluster = self.cluster_status().await?;
+        // Map of ACL path -> privileges granted to this API token
+        let permissions: Value = self.get_json(&format!("{}/api2/json/access/permissions", self.api_url)).await?;
+
+        Ok(NodeDiscovery {
+            version: version["version"].as_str().unwrap_or("unknown").to_string(),
+            cluster_name: cluster.name,
+            members: cluster.members,
+            permissions,
+        })
+    }
+
+    fn tls_config(&self) -> Arc<rustls::ClientConfig> {
+        self.tls.clone()
+    }
+
+    fn as_proxmox(&self) -> Option<&ProxmoxClient> {
+        Some(self)
+    }
+}
+
+
+/// `ip=10.0.0.5/24,gw=10.0.0.1,ip6=auto` as stored in `ipconfigN`
+fn parse_ip_config(raw: &str) -> IpConfig {
+    let mut config = IpConfig::default();
+    for (key, value) in raw.split(',').filter_map(|part| part.split_once('=')) {
+        let value = Some(value.to_string());
+        match key {
+            "ip" => config.ip = value,
+            "gw" => config.gateway = value,
+            "ip6" => config.ip6 = value,
+            "gw6" => config.gateway6 = value,
+            _ => {}
+        }
+    }
+    config
+}
+
+fn format_ip_config(config: &IpConfig) -> String {
+    [("ip", &config.ip), ("gw", &config.gateway), ("ip6", &config.ip6), ("gw6", &config.gateway6)]
+        .into_iter()
+        .filter_map(|(key, value)| value.as_ref().map(|v| format!("{}={}", key, v)))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Numeric config values, which newer Proxmox may send as strings like `current=4096`
+fn config_number(value: &Value) -> Option<u64> {
+    value.as_u64().or_else(|| {
+        let raw = value.as_str()?;
+        let first = raw.split(',').next()?;
+        first.trim_start_matches("current=").parse().ok()
+    })
+}
+
+/// Guest disks that can grow: QEMU bus disks other than CD drives, container rootfs and mount points
+fn is_resizable_disk(key: &str, spec: &str, qemu: bool) -> bool {
+    let bus = key.trim_end_matches(|c: char| c.is_ascii_digit());
+    let indexed = bus.len() < key.len();
+    let disk = if qemu {
+        indexed && matches!(bus, "scsi" | "virtio" | "sata" | "ide")
+    } else {
+        key == "rootfs" || (indexed && bus == "mp")
+    };
+    disk && spec != "none" && !spec.split(',').any(|option| option == "media=cdrom")
 }
 
+/// The first disk in the boot order, falling back to `bootdisk` or the first disk found
+fn boot_disk(config: &Value, qemu: bool) -> Option<String> {
+    let resizable = |key: &str| config[key].as_str().is_some_and(|spec| is_resizable_disk(key, spec, qemu));
+    if !qemu {
+        return resizable("rootfs").then(|| "rootfs".to_string());
+    }
+
+    // `boot: order=scsi0;ide2;net0`
+    let ordered = config["boot"].as_str()
+        .and_then(|boot| boot.strip_prefix("order="))
+        .into_iter()
+        .flat_map(|order| order.split(';'))
+        .chain(config["bootdisk"].as_str());
+    if let Some(key) = ordered.into_iter().find(|key| resizable(key)) {
+        return Some(key.to_string());
+    }
+
+    let mut keys: Vec<&String> = config.as_object()?.keys().filter(|key| resizable(key)).collect();
+    keys.sort();
+    keys.first().map(|key| key.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::StatusCode, routing::get, Json, Router};
+    use std::sync::Mutex as StdMutex;
+
+    const CONFIG_URL: &str = "/api2/json/nodes/pve1/qemu/100/config";
+
+    /// Canned guest config that, like Proxmox, refuses writes carrying an outdated digest
+    async fn serve_config(digest: &'static str) -> ProxmoxClient {
+        let memory = Arc::new(StdMutex::new(2048_u64));
+        let read = memory.clone();
+        let router = Router::new().route(
+            CONFIG_URL,
+            get(move || async move {
+                Json(serde_json::json!({ "data": { "memory": *read.lock().unwrap(), "digest": digest } }))
+            })
+            .put(move |Json(body): Json<Value>| async move {
+                if body["digest"].as_str().is_some_and(|d| d != digest) {
+                    return Err((StatusCode::INTERNAL_SERVER_ERROR, "detected modified configuration - file changed by other user? Try again."));
+                }
+                if body["memory"].is_string() {
+                    return Err((StatusCode::BAD_REQUEST, "parameter verification failed."));
+                }
+                *memory.lock().unwrap() = body["memory"].as_u64().unwrap_or_default();
+                Ok(Json(serde_json::json!({ "data": null })))
+            }),
+        );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        let tls = rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(rustls::RootCertStore::empty())
+            .with_no_client_auth();
+        ProxmoxClient::new(format!("http://{}", addr), "root@pam!dash".into(), "secret".into(), Arc::new(tls))
+    }
+
+    #[tokio::test]
+    async fn config_details_carry_the_digest() {
+        let client = serve_config("d1").await;
+        let details = client.get_vm_details("pve1/qemu/100").await.unwrap();
+        assert_eq!(details.digest.as_deref(), Some("d1"));
+        assert_eq!(details.config["memory"], 2048);
+    }
+
+    #[tokio::test]
+    async fn current_digest_updates_the_config() {
+        let client = serve_config("d1").await;
+        client.update_vm_config("pve1/qemu/100", serde_json::json!({ "memory": 4096 }), Some("d1")).await.unwrap();
+        assert_eq!(client.get_vm_details("pve1/qemu/100").await.unwrap().config["memory"], 4096);
+    }
+
+    #[tokio::test]
+    async fn stale_digest_is_a_config_conflict() {
+        let client = serve_config("d2").await;
+        let err = client.update_vm_config("pve1/qemu/100", serde_json::json!({ "memory": 4096 }), Some("d1")).await.unwrap_err();
+        assert!(err.is::<ConfigConflict>(), "{}", err);
+        assert_eq!(client.get_vm_details("pve1/qemu/100").await.unwrap().config["memory"], 2048);
+    }
+
+    #[tokio::test]
+    async fn other_failures_are_not_conflicts() {
+        let client = serve_config("d1").await;
+        let err = client.update_vm_config("pve1/qemu/100", serde_json::json!({ "memory": "lots" }), Some("d1")).await.unwrap_err();
+        assert!(!err.is::<ConfigConflict>());
+    }
+}
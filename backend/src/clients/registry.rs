@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use uuid::Uuid;
+use crate::db::DbPool;
+use crate::models::node::{Node, NodeType};
+use super::{proxmox::ProxmoxClient, incus::IncusClient, tls, NodeClient};
+
+pub type SharedClient = Arc<dyn NodeClient + Send + Sync>;
+
+/// Caches one hypervisor client per node so HTTP connection pools are reused across requests.
+///
+/// Entries are built lazily from the node row and must be invalidated whenever the node's
+/// connection settings change or the node is removed.
+#[derive(Clone)]
+pub struct ClientRegistry {
+    pool: DbPool,
+    clients: Arc<RwLock<HashMap<Uuid, SharedClient>>>,
+}
+
+impl ClientRegistry {
+    pub fn new(pool: DbPool) -> Self {
+        Self {
+            pool,
+            clients: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Get the client for a node we already loaded from the database
+    pub fn client_for(&self, node: &Node) -> anyhow::Result<SharedClient> {
+        if let Some(client) = self.clients.read().unwrap().get(&node.id) {
+            return Ok(client.clone());
+        }
+
+        let client = build_client(node)?;
+        Ok(self.clients.write().unwrap()
+            .entry(node.id)
+            .or_insert(client)
+            .clone())
+    }
+
+    /// Get the client for a node id, loading the node row on a cache miss
+    pub async fn get(&self, node_id: Uuid) -> anyhow::Result<SharedClient> {
+        if let Some(client) = self.clients.read().unwrap().get(&node_id) {
+            return Ok(client.clone());
+        }
+
+        let node = sqlx::query_as::<_, Node>(
+            "SELECT id, name, node_type, api_url, api_key, api_secret, status, last_check, created_at, tls_mode, tls_ca_cert, tls_fingerprint, tls_pending_fingerprint FROM nodes WHERE id = $1"
+        )
+        .bind(node_id)
+        .fetch_one(&self.pool)
+        .await?;
+
+        self.client_for(&node)
+    }
+
+    /// Drop the cached client so the next lookup picks up the node's current settings
+    pub fn invalidate(&self, node_id: Uuid) {
+        if self.clients.write().unwrap().remove(&node_id).is_some() {
+            tracing::debug!("Invalidated cached client for node {}", node_id);
+        }
+    }
+}
+
+/// Build a client for a node row; fails when its TLS settings are unusable (e.g. a bad CA bundle)
+pub fn build_client(node: &Node) -> anyhow::Result<SharedClient> {
+    let tls = tls::client_config(node)?;
+    let client: SharedClient = match node.node_type {
+        NodeType::Proxmox => Arc::new(ProxmoxClient::new(
+            node.api_url.clone(),
+            node.api_key.clone(),
+            node.api_secret.clone().unwrap_or_default(),
+            tls,
+        )),
+        NodeType::Incus => Arc::new(IncusClient::new(
+            node.api_url.clone(),
+            node.api_key.clone(),
+            node.api_secret.clone(),
+            tls,
+        )),
+    };
+    Ok(client)
+}
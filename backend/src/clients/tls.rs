@@ -0,0 +1,310 @@
+use std::sync::{Arc, Mutex};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use sha2::{Digest, Sha256};
+use crate::models::node::{Node, NodeTlsMode, NodeType};
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+/// SHA-256 fingerprint of a DER certificate, formatted like Proxmox shows it (`AB:CD:...`)
+pub fn fingerprint(cert: &[u8]) -> String {
+    Sha256::digest(cert)
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Normalize a user-supplied fingerprint, accepting any case with or without colons
+pub fn normalize_fingerprint(input: &str) -> anyhow::Result<String> {
+    let hex_only: String = input.chars().filter(|c| *c != ':' && !c.is_whitespace()).collect();
+    let bytes = hex::decode(&hex_only)
+        .map_err(|_| anyhow::anyhow!("Fingerprint must be hex encoded"))?;
+    if bytes.len() != 32 {
+        anyhow::bail!("Fingerprint must be a SHA-256 digest (32 bytes)");
+    }
+
+    Ok(bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(":"))
+}
+
+/// Parse a PEM bundle into a root store, failing if it contains no certificates
+pub fn parse_ca_bundle(pem: &str) -> anyhow::Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in CertificateDer::pem_slice_iter(pem.as_bytes()) {
+        let cert = cert.map_err(|e| anyhow::anyhow!("Invalid CA bundle: {}", e))?;
+        roots.add(cert)?;
+    }
+
+    if roots.is_empty() {
+        anyhow::bail!("CA bundle contains no certificates");
+    }
+
+    Ok(roots)
+}
+
+fn system_roots() -> RootCertStore {
+    let mut roots = RootCertStore::empty();
+    let loaded = rustls_native_certs::load_native_certs();
+    for err in &loaded.errors {
+        tracing::warn!("Failed to load system certificate: {}", err);
+    }
+    let (added, ignored) = roots.add_parsable_certificates(loaded.certs);
+    tracing::debug!("Loaded {} system root certificates ({} ignored)", added, ignored);
+    roots
+}
+
+/// Incus authenticates API clients by certificate: for Incus nodes `api_key` holds the client
+/// certificate and `api_secret` its private key, both PEM encoded
+fn client_identity(node: &Node) -> anyhow::Result<Option<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)>> {
+    if node.node_type != NodeType::Incus || !node.api_key.contains("-----BEGIN CERTIFICATE-----") {
+        return Ok(None);
+    }
+
+    let certs = CertificateDer::pem_slice_iter(node.api_key.as_bytes())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| anyhow::anyhow!("Invalid Incus client certificate: {}", e))?;
+    let key_pem = node.api_secret.as_deref()
+        .ok_or_else(|| anyhow::anyhow!("Node {} has a client certificate but no private key", node.name))?;
+    let key = PrivateKeyDer::from_pem_slice(key_pem.as_bytes())
+        .map_err(|e| anyhow::anyhow!("Invalid Incus client key: {}", e))?;
+
+    Ok(Some((certs, key)))
+}
+
+/// Build the rustls configuration used for both REST calls and console WebSockets to a node
+pub fn client_config(node: &Node) -> anyhow::Result<Arc<ClientConfig>> {
+    let builder = ClientConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()?;
+
+    let builder = match node.tls_mode {
+        NodeTlsMode::System => builder.with_root_certificates(system_roots()),
+        NodeTlsMode::CustomCa => {
+            let pem = node.tls_ca_cert.as_deref()
+                .ok_or_else(|| anyhow::anyhow!("Node {} uses a custom CA but none is configured", node.name))?;
+            builder.with_root_certificates(parse_ca_bundle(pem)?)
+        }
+        NodeTlsMode::Pinned => builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(PinnedVerifier::new(node.tls_fingerprint.clone()))),
+    };
+
+    let config = match client_identity(node)? {
+        Some((certs, key)) => builder.with_client_auth_cert(certs, key)?,
+        None => builder.with_no_client_auth(),
+    };
+
+    Ok(Arc::new(config))
+}
+
+/// Accepts exactly one leaf certificate, identified by its SHA-256 fingerprint.
+///
+/// With no fingerprint configured every handshake is refused, so credentials are never sent
+/// to a node whose certificate an admin has not confirmed yet.
+#[derive(Debug)]
+struct PinnedVerifier {
+    expected: Option<String>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl PinnedVerifier {
+    fn new(expected: Option<String>) -> Self {
+        Self {
+            expected: expected.and_then(|fp| normalize_fingerprint(&fp).ok()),
+            provider: provider(),
+        }
+    }
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let presented = fingerprint(end_entity);
+        match &self.expected {
+            Some(expected) if *expected == presented => Ok(ServerCertVerified::assertion()),
+            Some(_) => {
+                tracing::error!("❌ TLS fingerprint mismatch: node presented {}", presented);
+                Err(rustls::Error::General("certificate fingerprint does not match pinned value".into()))
+            }
+            None => Err(rustls::Error::General("node certificate has not been trusted yet".into())),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// Records the presented certificate without trusting it; only used for probing
+#[derive(Debug)]
+struct CaptureVerifier {
+    seen: Mutex<Option<String>>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for CaptureVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        *self.seen.lock().unwrap() = Some(fingerprint(end_entity));
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// Split an https:// or wss:// URL into the host and port to open a TLS connection to
+pub fn host_and_port(url: &str) -> anyhow::Result<(String, u16)> {
+    let uri = url.parse::<axum::http::Uri>()?;
+    let host = uri.host()
+        .ok_or_else(|| anyhow::anyhow!("No host in URL {}", url))?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+    let port = uri.port_u16().unwrap_or(match uri.scheme_str() {
+        Some("http") | Some("ws") => 80,
+        _ => 443,
+    });
+    Ok((host, port))
+}
+
+/// Open a TLS connection to the node and return the fingerprint of the certificate it presents.
+///
+/// Nothing is sent after the handshake, so this is safe to call against untrusted endpoints.
+pub async fn probe_fingerprint(api_url: &str) -> anyhow::Result<String> {
+    let (host, port) = host_and_port(api_url)?;
+    let verifier = Arc::new(CaptureVerifier {
+        seen: Mutex::new(None),
+        provider: provider(),
+    });
+
+    let config = ClientConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()?
+        .dangerous()
+        .with_custom_certificate_verifier(verifier.clone())
+        .with_no_client_auth();
+
+    let server_name = ServerName::try_from(host.clone())?;
+    let tcp = tokio::time::timeout(
+        std::time::Duration::from_secs(10),
+        tokio::net::TcpStream::connect((host.as_str(), port)),
+    ).await??;
+    tokio_rustls::TlsConnector::from(Arc::new(config))
+        .connect(server_name, tcp)
+        .await?;
+
+    let seen = verifier.seen.lock().unwrap().clone();
+    seen.ok_or_else(|| anyhow::anyhow!("Node did not present a certificate"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CERT: &[u8] = b"not parsed, only hashed";
+
+    fn verify(verifier: &PinnedVerifier, cert: &[u8]) -> Result<ServerCertVerified, rustls::Error> {
+        let server_name = ServerName::try_from("pve.example").unwrap();
+        verifier.verify_server_cert(&CertificateDer::from(cert), &[], &server_name, &[], UnixTime::now())
+    }
+
+    #[test]
+    fn normalize_fingerprint_accepts_any_case_and_separator() {
+        let canonical = fingerprint(CERT);
+        let bare_lower = canonical.replace(':', "").to_lowercase();
+        assert_eq!(normalize_fingerprint(&bare_lower).unwrap(), canonical);
+        assert_eq!(normalize_fingerprint(&canonical.to_lowercase()).unwrap(), canonical);
+        assert_eq!(normalize_fingerprint(&format!(" {} ", canonical)).unwrap(), canonical);
+    }
+
+    #[test]
+    fn normalize_fingerprint_rejects_wrong_length_and_non_hex() {
+        assert!(normalize_fingerprint("AB:CD:EF").is_err());
+        assert!(normalize_fingerprint(&format!("{}:00", fingerprint(CERT))).is_err());
+        assert!(normalize_fingerprint(&"ZZ".repeat(32)).is_err());
+        assert!(normalize_fingerprint("").is_err());
+    }
+
+    #[test]
+    fn pinned_verifier_accepts_only_the_pinned_certificate() {
+        let pinned = PinnedVerifier::new(Some(fingerprint(CERT).to_lowercase()));
+        assert!(verify(&pinned, CERT).is_ok());
+        assert!(verify(&pinned, b"another certificate").is_err());
+    }
+
+    #[test]
+    fn pinned_verifier_without_fingerprint_refuses_everything() {
+        assert!(verify(&PinnedVerifier::new(None), CERT).is_err());
+        // An unparseable pin must not turn into "no pin, accept"
+        assert!(verify(&PinnedVerifier::new(Some("garbage".into())), CERT).is_err());
+    }
+
+    #[test]
+    fn parse_ca_bundle_rejects_empty_and_garbage_bundles() {
+        assert!(parse_ca_bundle("").is_err());
+        assert!(parse_ca_bundle("not a certificate").is_err());
+        assert!(parse_ca_bundle("-----BEGIN CERTIFICATE-----\n!!!\n-----END CERTIFICATE-----\n").is_err());
+    }
+
+    #[test]
+    fn host_and_port_defaults_by_scheme() {
+        assert_eq!(host_and_port("https://pve.example:8006/api2/json").unwrap(), ("pve.example".into(), 8006));
+        assert_eq!(host_and_port("https://incus.example").unwrap(), ("incus.example".into(), 443));
+        assert_eq!(host_and_port("wss://incus.example/1.0").unwrap(), ("incus.example".into(), 443));
+        assert_eq!(host_and_port("http://10.0.0.5").unwrap(), ("10.0.0.5".into(), 80));
+        assert_eq!(host_and_port("https://[fd00::1]:8443").unwrap(), ("fd00::1".into(), 8443));
+        assert!(host_and_port("/no/host").is_err());
+    }
+}
@@ -0,0 +1,349 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+use crate::db::DbPool;
+use crate::middleware::auth::AuthUserExtension;
+use crate::models::alert::{
+    AlertChannel, AlertChannelRequest, AlertEvent, AlertMetric, AlertRule, AlertRuleRequest,
+    AlertSilence, AlertState, CreateSilenceRequest,
+};
+use crate::services::notifications::{deliver, validate_channel_config, AlertNotification};
+
+fn db_error(e: sqlx::Error) -> StatusCode {
+    tracing::error!("Database error: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+fn validate_rule(payload: &AlertRuleRequest) -> Result<(), StatusCode> {
+    if payload.name.trim().is_empty() || payload.duration_secs < 0 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if payload.metric.uses_threshold() {
+        match payload.threshold {
+            Some(t) if (0.0..=100.0).contains(&t) => {}
+            _ => return Err(StatusCode::BAD_REQUEST),
+        }
+    }
+    if payload.vm_id.is_some() && (payload.metric != AlertMetric::VmStopped || payload.node_id.is_none()) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct ListAlertEventsQuery {
+    pub state: Option<AlertState>,
+    pub rule_id: Option<Uuid>,
+    pub limit: Option<i64>,
+}
+
+/// Fired alerts, newest first
+pub async fn list_alert_events(
+    State(pool): State<DbPool>,
+    Query(query): Query<ListAlertEventsQuery>,
+) -> Result<Json<Vec<AlertEvent>>, StatusCode> {
+    let events = sqlx::query_as::<_, AlertEvent>(
+        r#"
+        SELECT id, rule_id, node_id, subject, state, value, message, silenced, started_at, resolved_at
+        FROM alert_events
+        WHERE ($1::alert_state IS NULL OR state = $1) AND ($2::uuid IS NULL OR rule_id = $2)
+        ORDER BY started_at DESC
+        LIMIT $3
+        "#
+    )
+    .bind(query.state)
+    .bind(query.rule_id)
+    .bind(query.limit.unwrap_or(100).clamp(1, 1000))
+    .fetch_all(&pool)
+    .await
+    .map_err(db_error)?;
+
+    Ok(Json(events))
+}
+
+pub async fn list_alert_rules(
+    State(pool): State<DbPool>,
+) -> Result<Json<Vec<AlertRule>>, StatusCode> {
+    let rules = sqlx::query_as::<_, AlertRule>("SELECT id, name, metric, threshold, duration_secs, node_id, vm_id, channel_ids, enabled, created_at FROM alert_rules ORDER BY created_at DESC")
+        .fetch_all(&pool)
+        .await
+        .map_err(db_error)?;
+
+    Ok(Json(rules))
+}
+
+pub async fn get_alert_rule(
+    State(pool): State<DbPool>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<AlertRule>, StatusCode> {
+    sqlx::query_as::<_, AlertRule>("SELECT id, name, metric, threshold, duration_secs, node_id, vm_id, channel_ids, enabled, created_at FROM alert_rules WHERE id = $1")
+        .bind(id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(db_error)?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+pub async fn create_alert_rule(
+    State(pool): State<DbPool>,
+    Json(payload): Json<AlertRuleRequest>,
+) -> Result<(StatusCode, Json<AlertRule>), StatusCode> {
+    validate_rule(&payload)?;
+
+    let rule = sqlx::query_as::<_, AlertRule>(
+        r#"
+        INSERT INTO alert_rules (name, metric, threshold, duration_secs, node_id, vm_id, channel_ids, enabled)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        RETURNING id, name, metric, threshold, duration_secs, node_id, vm_id, channel_ids, enabled, created_at
+        "#
+    )
+    .bind(payload.name.trim())
+    .bind(payload.metric)
+    .bind(payload.threshold)
+    .bind(payload.duration_secs)
+    .bind(payload.node_id)
+    .bind(payload.vm_id)
+    .bind(payload.channel_ids)
+    .bind(payload.enabled)
+    .fetch_one(&pool)
+    .await
+    .map_err(db_error)?;
+
+    tracing::info!("🔔 Alert rule created: {}", rule.name);
+    Ok((StatusCode::CREATED, Json(rule)))
+}
+
+pub async fn update_alert_rule(
+    State(pool): State<DbPool>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<AlertRuleRequest>,
+) -> Result<Json<AlertRule>, StatusCode> {
+    validate_rule(&payload)?;
+
+    sqlx::query_as::<_, AlertRule>(
+        r#"
+        UPDATE alert_rules
+        SET name = $1, metric = $2, threshold = $3, duration_secs = $4, node_id = $5, vm_id = $6, channel_ids = $7, enabled = $8
+        WHERE id = $9
+        RETURNING id, name, metric, threshold, duration_secs, node_id, vm_id, channel_ids, enabled, created_at
+        "#
+    )
+    .bind(payload.name.trim())
+    .bind(payload.metric)
+    .bind(payload.threshold)
+    .bind(payload.duration_secs)
+    .bind(payload.node_id)
+    .bind(payload.vm_id)
+    .bind(payload.channel_ids)
+    .bind(payload.enabled)
+    .bind(id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(db_error)?
+    .map(Json)
+    .ok_or(StatusCode::NOT_FOUND)
+}
+
+pub async fn delete_alert_rule(
+    State(pool): State<DbPool>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let result = sqlx::query("DELETE FROM alert_rules WHERE id = $1")
+        .bind(id)
+        .execute(&pool)
+        .await
+        .map_err(db_error)?;
+
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn list_alert_channels(
+    State(pool): State<DbPool>,
+) -> Result<Json<Vec<AlertChannel>>, StatusCode> {
+    let channels = sqlx::query_as::<_, AlertChannel>("SELECT id, name, kind, config, enabled, created_at FROM alert_channels ORDER BY created_at DESC")
+        .fetch_all(&pool)
+        .await
+        .map_err(db_error)?;
+
+    Ok(Json(channels))
+}
+
+fn validate_channel(payload: &AlertChannelRequest) -> Result<(), StatusCode> {
+    if payload.name.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    validate_channel_config(payload.kind, &payload.config).map_err(|e| {
+        tracing::warn!("Rejected alert channel config: {}", e);
+        StatusCode::BAD_REQUEST
+    })
+}
+
+pub async fn create_alert_channel(
+    State(pool): State<DbPool>,
+    Json(payload): Json<AlertChannelRequest>,
+) -> Result<(StatusCode, Json<AlertChannel>), StatusCode> {
+    validate_channel(&payload)?;
+
+    let channel = sqlx::query_as::<_, AlertChannel>(
+        "INSERT INTO alert_channels (name, kind, config, enabled) VALUES ($1, $2, $3, $4) RETURNING id, name, kind, config, enabled, created_at"
+    )
+    .bind(payload.name.trim())
+    .bind(payload.kind)
+    .bind(payload.config)
+    .bind(payload.enabled)
+    .fetch_one(&pool)
+    .await
+    .map_err(db_error)?;
+
+    Ok((StatusCode::CREATED, Json(channel)))
+}
+
+pub async fn update_alert_channel(
+    State(pool): State<DbPool>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<AlertChannelRequest>,
+) -> Result<Json<AlertChannel>, StatusCode> {
+    validate_channel(&payload)?;
+
+    sqlx::query_as::<_, AlertChannel>(
+        "UPDATE alert_channels SET name = $1, kind = $2, config = $3, enabled = $4 WHERE id = $5 RETURNING id, name, kind, config, enabled, created_at"
+    )
+    .bind(payload.name.trim())
+    .bind(payload.kind)
+    .bind(payload.config)
+    .bind(payload.enabled)
+    .bind(id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(db_error)?
+    .map(Json)
+    .ok_or(StatusCode::NOT_FOUND)
+}
+
+pub async fn delete_alert_channel(
+    State(pool): State<DbPool>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let result = sqlx::query("DELETE FROM alert_channels WHERE id = $1")
+        .bind(id)
+        .execute(&pool)
+        .await
+        .map_err(db_error)?;
+
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    // Rules keep working without the deleted channel
+    sqlx::query("UPDATE alert_rules SET channel_ids = array_remove(channel_ids, $1)")
+        .bind(id)
+        .execute(&pool)
+        .await
+        .map_err(db_error)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Send a sample notification so a channel can be checked before an alert really fires
+pub async fn test_alert_channel(
+    State(pool): State<DbPool>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let channel = sqlx::query_as::<_, AlertChannel>("SELECT id, name, kind, config, enabled, created_at FROM alert_channels WHERE id = $1")
+        .bind(id)
+        .fetch_optional(&pool)
+        .await
+        .map_err(|e| (db_error(e), String::new()))?
+        .ok_or((StatusCode::NOT_FOUND, "Channel not found".to_string()))?;
+
+    let notification = AlertNotification {
+        event_id: Uuid::nil(),
+        rule_id: Uuid::nil(),
+        rule_name: "Test notification".to_string(),
+        metric: AlertMetric::NodeOffline,
+        state: AlertState::Firing,
+        node_id: Uuid::nil(),
+        node_name: "test".to_string(),
+        vm_id: None,
+        value: None,
+        threshold: None,
+        message: format!("Test notification for channel {}", channel.name),
+        timestamp: chrono::Utc::now(),
+    };
+
+    deliver(&channel, &notification).await.map_err(|e| {
+        tracing::warn!("Test notification to {} failed: {}", channel.name, e);
+        (StatusCode::BAD_GATEWAY, e.to_string())
+    })?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Active and upcoming silences
+pub async fn list_alert_silences(
+    State(pool): State<DbPool>,
+) -> Result<Json<Vec<AlertSilence>>, StatusCode> {
+    let silences = sqlx::query_as::<_, AlertSilence>(
+        "SELECT id, rule_id, node_id, starts_at, ends_at, reason, created_by, created_at FROM alert_silences WHERE ends_at > NOW() ORDER BY starts_at"
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(db_error)?;
+
+    Ok(Json(silences))
+}
+
+pub async fn create_alert_silence(
+    State(pool): State<DbPool>,
+    Extension(AuthUserExtension(user)): Extension<AuthUserExtension>,
+    Json(payload): Json<CreateSilenceRequest>,
+) -> Result<(StatusCode, Json<AlertSilence>), StatusCode> {
+    let starts_at = payload.starts_at.unwrap_or_else(chrono::Utc::now);
+    if payload.ends_at <= starts_at {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let silence = sqlx::query_as::<_, AlertSilence>(
+        r#"
+        INSERT INTO alert_silences (rule_id, node_id, starts_at, ends_at, reason, created_by)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id, rule_id, node_id, starts_at, ends_at, reason, created_by, created_at
+        "#
+    )
+    .bind(payload.rule_id)
+    .bind(payload.node_id)
+    .bind(starts_at)
+    .bind(payload.ends_at)
+    .bind(payload.reason)
+    .bind(&user.username)
+    .fetch_one(&pool)
+    .await
+    .map_err(db_error)?;
+
+    tracing::info!("🔕 Alert silence created by {} until {}", user.username, silence.ends_at);
+    Ok((StatusCode::CREATED, Json(silence)))
+}
+
+pub async fn delete_alert_silence(
+    State(pool): State<DbPool>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let result = sqlx::query("DELETE FROM alert_silences WHERE id = $1")
+        .bind(id)
+        .execute(&pool)
+        .await
+        .map_err(db_error)?;
+
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
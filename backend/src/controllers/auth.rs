@@ -9,7 +9,9 @@ use jsonwebtoken::{encode, decode, Header, EncodingKey, DecodingKey, Validation}
 use chrono::{Utc, Duration};
 use argon2::{Argon2, PasswordHash, PasswordVerifier};
 use crate::db::DbPool;
+use crate::middleware::auth::CurrentUser;
 use crate::models::user::{User, UserRole};
+use crate::services::ws_tickets::{WsTickets, TICKET_TTL};
 
 #[derive(Deserialize)]
 pub struct LoginRequest {
@@ -81,7 +83,7 @@ pub async fn handle_login(
         }
     };
     
-    if let Err(_) = Argon2::default().verify_password(payload.password.as_bytes(), &parsed_hash) {
+    if Argon2::default().verify_password(payload.password.as_bytes(), &parsed_hash).is_err() {
         return Err(StatusCode::UNAUTHORIZED);
     }
 
@@ -226,6 +228,36 @@ pub async fn handle_admin_exists(
     Ok(Json(AdminExistsResponse { exists: admin.is_some() }))
 }
 
+#[derive(Deserialize)]
+pub struct WsTicketRequest {
+    /// Path of the WebSocket the ticket is for, e.g. `/api/v1/vms/console/<node>/<vm>`
+    pub path: String,
+}
+
+#[derive(Serialize)]
+pub struct WsTicketResponse {
+    pub ticket: String,
+    pub expires_in: u64,
+}
+
+/// Exchange the caller's session for a one-time ticket to open one WebSocket
+pub async fn handle_ws_ticket(
+    State(tickets): State<WsTickets>,
+    CurrentUser(user): CurrentUser,
+    Json(payload): Json<WsTicketRequest>,
+) -> Result<Json<WsTicketResponse>, StatusCode> {
+    // Only the WebSocket routes, which cannot carry an Authorization header
+    let route = payload.path.strip_prefix("/api/v1").unwrap_or(&payload.path);
+    if !(route.starts_with("/vms/console/") || route.starts_with("/vms/terminal/") || route == "/metrics") {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    Ok(Json(WsTicketResponse {
+        ticket: tickets.issue(user.id, &payload.path),
+        expires_in: TICKET_TTL.as_secs(),
+    }))
+}
+
 fn generate_token(user: String, minutes: i64) -> anyhow::Result<String> {
     let now = Utc::now();
     let iat = now.timestamp();
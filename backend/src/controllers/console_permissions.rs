@@ -0,0 +1,111 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use uuid::Uuid;
+use crate::db::DbPool;
+use crate::middleware::auth::AuthUserExtension;
+use crate::models::console_permission::{ConsoleAccess, ConsolePermission, ListConsolePermissionsQuery, SetConsolePermissionRequest};
+use crate::services::vnc_sessions::VncSessions;
+
+pub async fn list_console_permissions(
+    State(pool): State<DbPool>,
+    Query(query): Query<ListConsolePermissionsQuery>,
+) -> Result<Json<Vec<ConsolePermission>>, StatusCode> {
+    let permissions = sqlx::query_as::<_, ConsolePermission>(
+        r#"
+        SELECT p.id, p.user_id, u.username, p.node_id, p.vm_id, p.access, p.granted_by, p.created_at
+        FROM vm_console_permissions p
+        JOIN users u ON u.id = p.user_id
+        WHERE ($1::uuid IS NULL OR p.user_id = $1)
+          AND ($2::uuid IS NULL OR p.node_id = $2)
+          AND ($3::text IS NULL OR p.vm_id = $3)
+        ORDER BY u.username, p.node_id, p.vm_id
+        "#
+    )
+    .bind(query.user_id)
+    .bind(query.node_id)
+    .bind(query.vm_id)
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Database error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(permissions))
+}
+
+/// Grant a user view or control access to one VM's console, replacing any earlier grant.
+/// Downgrading to view closes their open sessions so the next one is read-only.
+pub async fn set_console_permission(
+    State(pool): State<DbPool>,
+    State(sessions): State<VncSessions>,
+    Extension(AuthUserExtension(user)): Extension<AuthUserExtension>,
+    Json(payload): Json<SetConsolePermissionRequest>,
+) -> Result<Json<ConsolePermission>, StatusCode> {
+    if payload.vm_id.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let permission = sqlx::query_as::<_, ConsolePermission>(
+        r#"
+        WITH saved AS (
+            INSERT INTO vm_console_permissions (user_id, node_id, vm_id, access, granted_by)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (user_id, node_id, vm_id)
+            DO UPDATE SET access = EXCLUDED.access, granted_by = EXCLUDED.granted_by
+            RETURNING id, user_id, node_id, vm_id, access, granted_by, created_at
+        )
+        SELECT s.id, s.user_id, u.username, s.node_id, s.vm_id, s.access, s.granted_by, s.created_at
+        FROM saved s
+        JOIN users u ON u.id = s.user_id
+        "#
+    )
+    .bind(payload.user_id)
+    .bind(payload.node_id)
+    .bind(&payload.vm_id)
+    .bind(payload.access)
+    .bind(user.id)
+    .fetch_one(&pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(db) if db.is_foreign_key_violation() => StatusCode::NOT_FOUND,
+        e => {
+            tracing::error!("Database error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    if permission.access == ConsoleAccess::View {
+        sessions.terminate_user_vm(permission.user_id, permission.node_id, &permission.vm_id);
+    }
+
+    tracing::info!("🔐 {} gave {} {:?} access to the console of {}", user.username, permission.username, permission.access, permission.vm_id);
+    Ok(Json(permission))
+}
+
+/// Remove a grant and close the user's open sessions on that VM
+pub async fn delete_console_permission(
+    State(pool): State<DbPool>,
+    State(sessions): State<VncSessions>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let removed = sqlx::query_as::<_, (Uuid, Uuid, String)>(
+        "DELETE FROM vm_console_permissions WHERE id = $1 RETURNING user_id, node_id, vm_id"
+    )
+    .bind(id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Database error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .ok_or(StatusCode::NOT_FOUND)?;
+
+    let (user_id, node_id, vm_id) = removed;
+    let closed = sessions.terminate_user_vm(user_id, node_id, &vm_id);
+    tracing::info!("🔐 Console permission {} removed ({} sessions closed)", id, closed);
+    Ok(StatusCode::NO_CONTENT)
+}
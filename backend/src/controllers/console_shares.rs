@@ -0,0 +1,104 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Extension, Json,
+};
+use uuid::Uuid;
+use crate::db::DbPool;
+use crate::middleware::auth::AuthUserExtension;
+use crate::models::console_share::{ConsoleShareLink, CreateConsoleShareRequest, CreatedConsoleShareLink, ListConsoleSharesQuery};
+use crate::services::console_shares::share_token;
+use crate::services::vnc_sessions::VncSessions;
+
+const DEFAULT_EXPIRY_SECS: i64 = 60 * 60;
+const MAX_EXPIRY_SECS: i64 = 7 * 24 * 60 * 60;
+
+pub async fn list_console_shares(
+    State(pool): State<DbPool>,
+    Query(query): Query<ListConsoleSharesQuery>,
+) -> Result<Json<Vec<ConsoleShareLink>>, StatusCode> {
+    let links = sqlx::query_as::<_, ConsoleShareLink>(
+        r#"
+        SELECT id, node_id, vm_id, label, view_only, expires_at, revoked_at, created_by, created_at
+        FROM console_share_links
+        WHERE ($1::uuid IS NULL OR node_id = $1) AND ($2::text IS NULL OR vm_id = $2)
+        ORDER BY created_at DESC
+        "#
+    )
+    .bind(query.node_id)
+    .bind(query.vm_id)
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Database error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(links))
+}
+
+/// Issue a console link for one VM; the returned token is passed as `?share=` to the console WebSocket
+pub async fn create_console_share(
+    State(pool): State<DbPool>,
+    Extension(AuthUserExtension(user)): Extension<AuthUserExtension>,
+    Json(payload): Json<CreateConsoleShareRequest>,
+) -> Result<(StatusCode, Json<CreatedConsoleShareLink>), StatusCode> {
+    if payload.vm_id.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let expires_in = payload.expires_in_secs.unwrap_or(DEFAULT_EXPIRY_SECS);
+    if !(1..=MAX_EXPIRY_SECS).contains(&expires_in) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let link = sqlx::query_as::<_, ConsoleShareLink>(
+        r#"
+        INSERT INTO console_share_links (node_id, vm_id, label, view_only, expires_at, created_by)
+        VALUES ($1, $2, $3, $4, NOW() + make_interval(secs => $5), $6)
+        RETURNING id, node_id, vm_id, label, view_only, expires_at, revoked_at, created_by, created_at
+        "#
+    )
+    .bind(payload.node_id)
+    .bind(&payload.vm_id)
+    .bind(payload.label.filter(|l| !l.trim().is_empty()))
+    .bind(payload.view_only)
+    .bind(expires_in as f64)
+    .bind(user.id)
+    .fetch_one(&pool)
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::Database(db) if db.is_foreign_key_violation() => StatusCode::NOT_FOUND,
+        e => {
+            tracing::error!("Database error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    tracing::info!("🔗 {} shared the console of {} until {} (view only: {})", user.username, link.vm_id, link.expires_at, link.view_only);
+    let token = share_token(link.id);
+    Ok((StatusCode::CREATED, Json(CreatedConsoleShareLink { link, token })))
+}
+
+/// Revoke a link and close any console sessions opened through it
+pub async fn revoke_console_share(
+    State(pool): State<DbPool>,
+    State(sessions): State<VncSessions>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let result = sqlx::query("UPDATE console_share_links SET revoked_at = COALESCE(revoked_at, NOW()) WHERE id = $1")
+        .bind(id)
+        .execute(&pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Database error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let closed = sessions.terminate_share_link(id);
+    tracing::info!("🔗 Console share link {} revoked ({} sessions closed)", id, closed);
+    Ok(StatusCode::NO_CONTENT)
+}
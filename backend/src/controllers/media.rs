@@ -0,0 +1,205 @@
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{header, HeaderMap, StatusCode},
+    Json,
+};
+use futures::StreamExt;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::sync::{Arc, Mutex};
+use crate::clients::{is_path_segment, ChecksumMismatch};
+use crate::clients::registry::ClientRegistry;
+use crate::models::media::{DeleteMediaQuery, DownloadMediaRequest, MediaItem, MediaQuery, MediaStorage, MediaTaskQuery, UploadMediaQuery, UploadedMedia};
+
+/// Proxmox only stores ISOs under these extensions
+fn valid_filename(filename: &str) -> bool {
+    is_path_segment(filename) && (filename.ends_with(".iso") || filename.ends_with(".img"))
+}
+
+fn valid_names(storage: Option<&str>, host: Option<&str>) -> bool {
+    storage.is_none_or(is_path_segment) && host.is_none_or(is_path_segment)
+}
+
+fn is_hex(value: &str, len: usize) -> bool {
+    value.len() == len && value.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+pub async fn list_media_storages(
+    State(clients): State<ClientRegistry>,
+    Query(query): Query<MediaQuery>,
+) -> Result<Json<Vec<MediaStorage>>, StatusCode> {
+    if !valid_names(None, query.host.as_deref()) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let client = clients.get(query.node_id).await.map_err(|_| StatusCode::NOT_FOUND)?;
+
+    let storages = client.list_media_storages(query.host.as_deref()).await.map_err(|e| {
+        tracing::error!("Failed to list media storages: {}", e);
+        StatusCode::BAD_GATEWAY
+    })?;
+    Ok(Json(storages))
+}
+
+/// ISOs in one storage, or in all of them when `storage` is omitted
+pub async fn list_media_library(
+    State(clients): State<ClientRegistry>,
+    Query(query): Query<MediaQuery>,
+) -> Result<Json<Vec<MediaItem>>, StatusCode> {
+    if !valid_names(query.storage.as_deref(), query.host.as_deref()) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let client = clients.get(query.node_id).await.map_err(|_| StatusCode::NOT_FOUND)?;
+
+    let items = client.list_media(query.storage.as_deref(), query.host.as_deref()).await.map_err(|e| {
+        tracing::error!("Failed to list media: {}", e);
+        StatusCode::BAD_GATEWAY
+    })?;
+    Ok(Json(items))
+}
+
+pub async fn delete_media(
+    State(clients): State<ClientRegistry>,
+    Query(query): Query<DeleteMediaQuery>,
+) -> Result<StatusCode, StatusCode> {
+    if !valid_names(None, query.host.as_deref()) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let client = clients.get(query.node_id).await.map_err(|_| StatusCode::NOT_FOUND)?;
+
+    client.delete_media(&query.id, query.host.as_deref()).await.map_err(|e| {
+        tracing::error!("Failed to delete {}: {}", query.id, e);
+        StatusCode::BAD_GATEWAY
+    })?;
+
+    tracing::info!("💿 Deleted {} from the media library", query.id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Stream an ISO from the raw request body to the node, hashing it on the way through.
+/// With `sha256` set, a mismatching upload is rejected by the node (Proxmox) or never
+/// renamed into place (Incus), and answered with 422.
+pub async fn upload_media(
+    State(clients): State<ClientRegistry>,
+    Query(query): Query<UploadMediaQuery>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<(StatusCode, Json<UploadedMedia>), StatusCode> {
+    if !valid_filename(&query.filename) || !valid_names(Some(&query.storage), query.host.as_deref()) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let expected = query.sha256.as_deref().map(str::to_ascii_lowercase);
+    if expected.as_deref().is_some_and(|sha| !is_hex(sha, 64)) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    // Both backends need the size before the first byte arrives
+    let size: u64 = headers.get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+        .ok_or(StatusCode::LENGTH_REQUIRED)?;
+    let client = clients.get(query.node_id).await.map_err(|_| StatusCode::NOT_FOUND)?;
+
+    // Digest state and how many bytes went into it
+    let hasher = Arc::new(Mutex::new((Sha256::new(), 0u64)));
+    let stream = body.into_data_stream()
+        .map({
+            let hasher = hasher.clone();
+            move |chunk| {
+                let chunk = chunk.map_err(std::io::Error::other)?;
+                let mut hasher = hasher.lock().unwrap();
+                hasher.0.update(&chunk);
+                hasher.1 += chunk.len() as u64;
+                Ok(chunk)
+            }
+        })
+        .boxed();
+
+    let uploaded = client.upload_media(&query.storage, query.host.as_deref(), &query.filename, size, expected.as_deref(), stream).await;
+    let (sha256, hashed) = {
+        let hasher = hasher.lock().unwrap();
+        (hex::encode(hasher.0.clone().finalize()), hasher.1)
+    };
+    // Only a complete body says anything about the file's checksum
+    let mismatch = hashed == size && expected.as_ref().is_some_and(|expected| *expected != sha256);
+
+    let item = match uploaded {
+        Ok(item) => item,
+        Err(e) if mismatch || e.is::<ChecksumMismatch>() => {
+            tracing::warn!("💿 Checksum mismatch for {} (got {}), rejected by the node: {}", query.filename, sha256, e);
+            return Err(StatusCode::UNPROCESSABLE_ENTITY);
+        }
+        Err(e) => {
+            tracing::error!("Upload of {} failed: {}", query.filename, e);
+            return Err(StatusCode::BAD_GATEWAY);
+        }
+    };
+
+    // Both backends verify the checksum themselves; this only catches one that did not
+    if mismatch {
+        tracing::warn!("💿 Checksum mismatch for {} (got {}), removing it", item.id, sha256);
+        return match client.delete_media(&item.id, query.host.as_deref()).await {
+            Ok(()) => Err(StatusCode::UNPROCESSABLE_ENTITY),
+            Err(e) => {
+                tracing::error!("Failed to remove mismatching upload {}: {}", item.id, e);
+                Err(StatusCode::BAD_GATEWAY)
+            }
+        };
+    }
+
+    tracing::info!("💿 Uploaded {} ({} bytes, sha256 {})", item.id, size, sha256);
+    Ok((StatusCode::CREATED, Json(UploadedMedia { item, sha256 })))
+}
+
+/// Have a Proxmox node download an ISO itself; follow the returned task with `/media/tasks`
+pub async fn download_media(
+    State(clients): State<ClientRegistry>,
+    Json(payload): Json<DownloadMediaRequest>,
+) -> Result<(StatusCode, Json<Value>), StatusCode> {
+    if !valid_filename(&payload.filename)
+        || !valid_names(Some(&payload.storage), payload.host.as_deref())
+        || !(payload.url.starts_with("https://") || payload.url.starts_with("http://"))
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let checksum = match (payload.checksum.as_deref(), payload.checksum_algorithm) {
+        (Some(checksum), Some(algorithm)) if is_hex(checksum, algorithm.hex_len()) => Some((checksum, algorithm)),
+        (None, None) => None,
+        _ => return Err(StatusCode::BAD_REQUEST),
+    };
+
+    let client = clients.get(payload.node_id).await.map_err(|_| StatusCode::NOT_FOUND)?;
+    // Incus has no server-side download for ISO volumes; upload instead
+    let proxmox = client.as_proxmox().ok_or(StatusCode::UNPROCESSABLE_ENTITY)?;
+
+    let task = proxmox.download_media(
+        &payload.storage,
+        payload.host.as_deref(),
+        &payload.url,
+        &payload.filename,
+        checksum,
+        payload.verify_certificates,
+    )
+    .await
+    .map_err(|e| {
+        tracing::error!("Download of {} failed to start: {}", payload.url, e);
+        StatusCode::BAD_GATEWAY
+    })?;
+
+    tracing::info!("💿 Downloading {} to {} as {}", payload.url, payload.storage, payload.filename);
+    Ok((StatusCode::ACCEPTED, Json(serde_json::json!({ "task": task }))))
+}
+
+/// Progress of a download; a failed checksum shows up as a non-`OK` `exitstatus`
+pub async fn get_media_task(
+    State(clients): State<ClientRegistry>,
+    Query(query): Query<MediaTaskQuery>,
+) -> Result<Json<Value>, StatusCode> {
+    let client = clients.get(query.node_id).await.map_err(|_| StatusCode::NOT_FOUND)?;
+    let proxmox = client.as_proxmox().ok_or(StatusCode::UNPROCESSABLE_ENTITY)?;
+
+    let status = proxmox.task_status(&query.task).await.map_err(|e| {
+        tracing::error!("Failed to get task {}: {}", query.task, e);
+        StatusCode::BAD_GATEWAY
+    })?;
+    Ok(Json(status))
+}
@@ -1,262 +1,207 @@
 use axum::{
-    extract::{ws::{WebSocket, WebSocketUpgrade}, Query, State},
-    http::{StatusCode, header},
+    extract::{ws::{Message, WebSocket, WebSocketUpgrade}, Query, State},
+    http::StatusCode,
     response::IntoResponse,
 };
-use serde::{Serialize, Deserialize};
-use std::time::Duration;
-use tokio::time::sleep;
-use jsonwebtoken::decode;
-use jsonwebtoken::DecodingKey;
-use jsonwebtoken::Validation;
-use crate::controllers::auth::Claims;
-use crate::models::node::{Node, NodeType};
-use crate::clients::proxmox::ProxmoxClient;
-
-#[derive(Serialize)]
-struct MetricUpdate {
-    cpu: f32,
-    ram: f32,
-    disk: Option<f32>,
-    net_in: Option<f32>,
-    net_out: Option<f32>,
-    uptime: Option<u64>,
-    timestamp: u64,
-    node_id: String,
-    node_name: String,
-}
-
-#[derive(Deserialize)]
-struct ProxmoxNodeStatus {
-    cpu: Option<f64>,
-    memory: Option<ProxmoxMemory>,
-    rootfs: Option<ProxmoxRootfs>,
-    uptime: Option<u64>,
-}
-
-#[derive(Deserialize)]
-struct ProxmoxMemory {
-    used: Option<u64>,
-    total: Option<u64>,
-}
-
-#[derive(Deserialize)]
-struct ProxmoxRootfs {
-    used: Option<u64>,
-    total: Option<u64>,
-}
+use futures::stream::{self, BoxStream, StreamExt};
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use crate::middleware::auth::WsUser;
+use crate::models::node::Node;
+use crate::models::user::UserRole;
+use crate::clients::registry::ClientRegistry;
+use crate::services::metrics::{node_history, MetricsCollector};
 
 #[derive(serde::Deserialize)]
 pub struct MetricsQuery {
-    pub token: Option<String>,
     pub node_id: Option<String>,
+    /// Stream a single guest on `node_id` instead of whole-node figures
+    pub vm_id: Option<String>,
 }
 
 pub async fn metrics_handler(
     ws: WebSocketUpgrade,
     Query(query): Query<MetricsQuery>,
-    headers: axum::http::HeaderMap,
+    // Any signed-in user can view node metrics; single guests are admin-only
+    WsUser(user): WsUser,
     State(pool): State<crate::db::DbPool>,
+    State(clients): State<ClientRegistry>,
+    State(collector): State<MetricsCollector>,
 ) -> impl IntoResponse {
-    // Authenticate via token query param, Authorization header, or cookie
-    let mut token_opt = query.token.as_deref().map(|s| s.to_string()).or_else(|| {
-        headers.get(header::AUTHORIZATION)
-            .and_then(|h| h.to_str().ok())
-            .and_then(|h| h.strip_prefix("Bearer "))
-            .map(|s| s.to_string())
-    });
+    let node_id_filter = query.node_id.clone();
+
+    if let Some(vm_id) = query.vm_id {
+        let node_id = match node_id_filter.as_deref().map(uuid::Uuid::parse_str) {
+            Some(Ok(id)) => id,
+            _ => return (StatusCode::BAD_REQUEST, "vm_id requires a valid node_id").into_response(),
+        };
+        if user.role != UserRole::Admin {
+            tracing::warn!("❌ {} tried to stream metrics for VM {}", user.username, vm_id);
+            return StatusCode::FORBIDDEN.into_response();
+        }
+        return ws.on_upgrade(move |socket| handle_vm_socket(socket, node_id, vm_id, clients, collector));
+    }
+
+    ws.on_upgrade(move |socket| handle_socket(socket, node_id_filter, pool, clients, collector))
+}
 
-    // Fallback to cookie if no token in query/header
-    if token_opt.is_none() {
-        if let Some(cookie_header) = headers.get(header::COOKIE).and_then(|h| h.to_str().ok()) {
-            for part in cookie_header.split(';').map(|s| s.trim()) {
-                if part.starts_with("access_token=") {
-                    token_opt = Some(part["access_token=".len()..].to_string());
-                    break;
+async fn handle_vm_socket(mut socket: WebSocket, node_id: uuid::Uuid, vm_id: String, clients: ClientRegistry, collector: MetricsCollector) {
+    tracing::info!("📊 VM metrics WS opened for {}/{}", node_id, vm_id);
+
+    if let Err(e) = clients.get(node_id).await {
+        tracing::error!("Failed to find node {} for VM metrics: {}", node_id, e);
+        return;
+    }
+
+    let mut updates = receiver_stream(collector.subscribe_vm(node_id, &vm_id));
+    loop {
+        tokio::select! {
+            Some(msg) = updates.next() => {
+                if socket.send(Message::Text(msg.to_string())).await.is_err() {
+                    tracing::info!("📊 VM metrics WS client disconnected");
+                    return;
+                }
+            }
+            incoming = socket.recv() => {
+                match incoming {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => {
+                        tracing::info!("📊 VM metrics WS client disconnected");
+                        return;
+                    }
+                    _ => {}
                 }
             }
         }
     }
+}
+
+/// Adapt a broadcast receiver into a stream, skipping over messages lost to lag
+fn receiver_stream<T: Clone + Send + 'static>(rx: broadcast::Receiver<T>) -> BoxStream<'static, T> {
+    stream::unfold(rx, |mut rx| async move {
+        loop {
+            match rx.recv().await {
+                Ok(msg) => return Some((msg, rx)),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::debug!("Metrics subscriber lagged, skipped {} updates", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    })
+    .boxed()
+}
+
+async fn handle_socket(
+    mut socket: WebSocket,
+    node_id_filter: Option<String>,
+    pool: crate::db::DbPool,
+    clients: ClientRegistry,
+    collector: MetricsCollector,
+) {
+    tracing::info!("📊 Metrics WS opened - node_id filter: {:?}", node_id_filter);
 
-    let token = match token_opt.as_deref() {
-        Some(t) => t,
-        None => return (StatusCode::UNAUTHORIZED, "Authentication required").into_response(),
+    // Unfiltered sockets follow every node, including ones added while they are open
+    let mut added = match node_id_filter {
+        None => receiver_stream(collector.subscribe_added()),
+        Some(_) => stream::pending().boxed(),
     };
 
-    // Verify JWT
-    let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "placeholder_secret".to_string());
-    let token_data = match decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(secret.as_ref()),
-        &Validation::default(),
-    ) {
-        Ok(d) => d,
-        Err(e) => {
-            tracing::warn!("❌ Metrics authentication failed: {}", e);
-            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+    // Resolve which nodes this socket follows
+    let nodes_query = if let Some(ref filter_id) = node_id_filter {
+        match uuid::Uuid::parse_str(filter_id) {
+            Ok(uuid) => {
+                sqlx::query_as::<_, Node>(
+                    "SELECT id, name, node_type, api_url, api_key, api_secret, status, last_check, created_at, tls_mode, tls_ca_cert, tls_fingerprint, tls_pending_fingerprint FROM nodes WHERE id = $1"
+                )
+                .bind(uuid)
+                .fetch_all(&pool)
+                .await
+            }
+            Err(_) => {
+                tracing::error!("Invalid node_id UUID: {}", filter_id);
+                return;
+            }
         }
+    } else {
+        sqlx::query_as::<_, Node>(
+            "SELECT id, name, node_type, api_url, api_key, api_secret, status, last_check, created_at, tls_mode, tls_ca_cert, tls_fingerprint, tls_pending_fingerprint FROM nodes"
+        )
+        .fetch_all(&pool)
+        .await
     };
 
-    // Verify user exists (any authenticated user can view metrics)
-    let user_exists = sqlx::query_scalar::<_, bool>(
-        "SELECT EXISTS(SELECT 1 FROM users WHERE username = $1)"
-    )
-    .bind(&token_data.claims.sub)
-    .fetch_one(&pool)
-    .await;
+    let nodes = match nodes_query {
+        Ok(n) => n,
+        Err(e) => {
+            tracing::error!("Failed to fetch nodes for metrics: {}", e);
+            return;
+        }
+    };
 
-    match user_exists {
-        Ok(true) => {
-            // User exists, proceed
+    // Subscribe before backfilling so no live update falls between history and the stream
+    let mut streams: Vec<BoxStream<'static, Arc<str>>> = nodes.iter()
+        .map(|node| receiver_stream(collector.subscribe(node.id)))
+        .collect();
+    // Keeps the merged stream open even when no nodes are configured yet
+    streams.push(stream::pending().boxed());
+    let mut updates = stream::select_all(streams);
+    let mut followed: HashSet<uuid::Uuid> = nodes.iter().map(|n| n.id).collect();
+
+    // Seed the chart from RRD history
+    for node in &nodes {
+        if !send_history(&mut socket, node, &clients).await {
+            tracing::info!("📊 Metrics WS client disconnected");
+            return;
         }
-        _ => return (StatusCode::UNAUTHORIZED, "User not found").into_response(),
     }
 
-    let node_id_filter = query.node_id.clone();
-
-    ws.on_upgrade(move |socket| handle_socket(socket, node_id_filter, pool))
-}
-
-async fn handle_socket(mut socket: WebSocket, node_id_filter: Option<String>, pool: crate::db::DbPool) {
-    tracing::info!("📊 Metrics WS opened - node_id filter: {:?}", node_id_filter);
-    
     loop {
-        // Fetch all nodes or specific node
-        let nodes_query = if let Some(ref filter_id) = node_id_filter {
-            match uuid::Uuid::parse_str(filter_id) {
-                Ok(uuid) => {
-                    sqlx::query_as::<_, Node>(
-                        "SELECT id, name, node_type, api_url, api_key, api_secret, status, last_check, created_at FROM nodes WHERE id = $1"
-                    )
-                    .bind(uuid)
-                    .fetch_all(&pool)
-                    .await
+        tokio::select! {
+            Some(node_id) = added.next() => {
+                if !followed.insert(node_id) {
+                    continue;
                 }
-                Err(_) => {
-                    tracing::error!("Invalid node_id UUID: {}", filter_id);
-                    break;
+                updates.push(receiver_stream(collector.subscribe(node_id)));
+                let node = sqlx::query_as::<_, Node>(
+                    "SELECT id, name, node_type, api_url, api_key, api_secret, status, last_check, created_at, tls_mode, tls_ca_cert, tls_fingerprint, tls_pending_fingerprint FROM nodes WHERE id = $1"
+                )
+                .bind(node_id)
+                .fetch_optional(&pool)
+                .await;
+                if let Ok(Some(node)) = node {
+                    if !send_history(&mut socket, &node, &clients).await {
+                        tracing::info!("📊 Metrics WS client disconnected");
+                        return;
+                    }
                 }
             }
-        } else {
-            sqlx::query_as::<_, Node>(
-                "SELECT id, name, node_type, api_url, api_key, api_secret, status, last_check, created_at FROM nodes"
-            )
-            .fetch_all(&pool)
-            .await
-        };
-
-        let nodes = match nodes_query {
-            Ok(n) => n,
-            Err(e) => {
-                tracing::error!("Failed to fetch nodes for metrics: {}", e);
-                sleep(Duration::from_secs(5)).await;
-                continue;
-            }
-        };
-
-        // Fetch metrics from each node
-        for node in nodes {
-            let metrics = fetch_node_metrics(&node).await;
-            
-            if let Some(update) = metrics {
-                let msg = serde_json::to_string(&update).unwrap();
-                if socket.send(axum::extract::ws::Message::Text(msg)).await.is_err() {
+            Some(msg) = updates.next() => {
+                if socket.send(Message::Text(msg.to_string())).await.is_err() {
                     tracing::info!("📊 Metrics WS client disconnected");
                     return;
                 }
             }
+            incoming = socket.recv() => {
+                match incoming {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => {
+                        tracing::info!("📊 Metrics WS client disconnected");
+                        return;
+                    }
+                    _ => {}
+                }
+            }
         }
-
-        sleep(Duration::from_secs(3)).await;
     }
 }
 
-async fn fetch_node_metrics(node: &Node) -> Option<MetricUpdate> {
-    match node.node_type {
-        NodeType::Proxmox => {
-            let client = ProxmoxClient::new(
-                node.api_url.clone(),
-                node.api_key.clone(),
-                node.api_secret.clone().unwrap_or_default(),
-            );
-
-            // Get the actual Proxmox node name from the cluster
-            let proxmox_node = match client.get_node_name().await {
-                Ok(name) => name,
-                Err(e) => {
-                    tracing::warn!("Failed to get Proxmox node name for {}: {}", node.name, e);
-                    return None;
-                }
-            };
-
-            // Fetch node status from Proxmox API
-            // GET /api2/json/nodes/{node}/status
-            let status_url = format!("{}/api2/json/nodes/{}/status", node.api_url, proxmox_node);
-            tracing::debug!("Fetching metrics from: {}", status_url);
-            
-            match client.get_json::<ProxmoxNodeStatus>(&status_url).await {
-                Ok(status) => {
-                    let cpu_percent = status.cpu.unwrap_or(0.0) * 100.0;
-                    let ram_percent = if let Some(mem) = status.memory {
-                        if let (Some(used), Some(total)) = (mem.used, mem.total) {
-                            if total > 0 {
-                                (used as f64 / total as f64) * 100.0
-                            } else {
-                                0.0
-                            }
-                        } else {
-                            0.0
-                        }
-                    } else {
-                        0.0
-                    };
-
-                    let disk_percent = if let Some(rootfs) = status.rootfs {
-                        if let (Some(used), Some(total)) = (rootfs.used, rootfs.total) {
-                            if total > 0 {
-                                Some((used as f64 / total as f64 * 100.0) as f32)
-                            } else {
-                                None
-                            }
-                        } else {
-                            None
-                        }
-                    } else {
-                        None
-                    };
-
-                    Some(MetricUpdate {
-                        cpu: cpu_percent as f32,
-                        ram: ram_percent as f32,
-                        disk: disk_percent,
-                        net_in: None,  // Would need /api2/json/nodes/{node}/rrddata for network stats
-                        net_out: None,
-                        uptime: status.uptime,
-                        timestamp: chrono::Utc::now().timestamp_millis() as u64,
-                        node_id: node.id.to_string(),
-                        node_name: node.name.clone(),
-                    })
-                }
-                Err(e) => {
-                    tracing::warn!("Failed to fetch metrics from {}: {}", node.name, e);
-                    None
-                }
-            }
-        }
-        NodeType::Incus => {
-            // Incus metrics would use different API endpoints
-            // For now, return placeholder
-            Some(MetricUpdate {
-                cpu: 0.0,
-                ram: 0.0,
-                disk: None,
-                net_in: None,
-                net_out: None,
-                uptime: None,
-                timestamp: chrono::Utc::now().timestamp_millis() as u64,
-                node_id: node.id.to_string(),
-                node_name: node.name.clone(),
-            })
+/// Replay a node's RRD history; false once the socket is gone
+async fn send_history(socket: &mut WebSocket, node: &Node, clients: &ClientRegistry) -> bool {
+    let Ok(client) = clients.client_for(node) else { return true };
+    for msg in node_history(node, client).await {
+        if socket.send(Message::Text(msg)).await.is_err() {
+            return false;
         }
     }
+    true
 }
@@ -4,3 +4,9 @@ pub mod vnc;
 pub mod metrics;
 pub mod support;
 pub mod auth;
+pub mod prometheus;
+pub mod alerts;
+pub mod webhooks;
+pub mod console_shares;
+pub mod console_permissions;
+pub mod media;
@@ -2,18 +2,60 @@ use axum::{
     extract::{State, Path},
     Json,
     http::StatusCode,
+    response::{IntoResponse, Response},
 };
 use crate::db::DbPool;
-use crate::models::node::{Node, CreateNodeRequest, NodeStatus, NodeType};
-use crate::clients::{proxmox::ProxmoxClient, incus::IncusClient, NodeClient};
+use crate::models::node::{Node, CreateNodeRequest, NodeConnectionParams, NodeStatus, NodeTlsMode};
+use crate::services::nodes::{record_node_status, sync_cluster_members, test_connection, NodeTestReport};
+use crate::services::metrics::MetricsCollector;
+use crate::services::webhooks::WebhookDispatcher;
+use crate::clients::{registry::ClientRegistry, tls};
 use serde_json::Value;
 
+/// Validate TLS settings from a create/update request, returning the normalized pinned fingerprint
+fn validate_tls_settings(
+    mode: NodeTlsMode,
+    ca_cert: Option<&str>,
+    fingerprint: Option<&str>,
+) -> Result<Option<String>, StatusCode> {
+    if mode == NodeTlsMode::CustomCa {
+        let pem = ca_cert.ok_or(StatusCode::BAD_REQUEST)?;
+        tls::parse_ca_bundle(pem).map_err(|e| {
+            tracing::warn!("Rejected CA bundle: {}", e);
+            StatusCode::BAD_REQUEST
+        })?;
+    }
+
+    fingerprint
+        .map(tls::normalize_fingerprint)
+        .transpose()
+        .map_err(|e| {
+            tracing::warn!("Rejected TLS fingerprint: {}", e);
+            StatusCode::BAD_REQUEST
+        })
+}
+
+/// Trust-on-first-use: read the certificate a pinned node presents and record it as pending
+/// unless it is the one already trusted
+async fn capture_pending_fingerprint(pool: &DbPool, node: &Node) -> anyhow::Result<String> {
+    let presented = tls::probe_fingerprint(&node.api_url).await?;
+    let pending = (node.tls_fingerprint.as_deref() != Some(presented.as_str())).then_some(presented.as_str());
+
+    sqlx::query("UPDATE nodes SET tls_pending_fingerprint = $1 WHERE id = $2")
+        .bind(pending)
+        .bind(node.id)
+        .execute(pool)
+        .await?;
+
+    Ok(presented)
+}
+
 pub async fn list_nodes(
     State(pool): State<DbPool>,
 ) -> Result<Json<Vec<Node>>, StatusCode> {
     let nodes = sqlx::query_as::<_, Node>(
         r#"
-        SELECT id, name, node_type, api_url, api_key, api_secret, status, last_check, created_at
+        SELECT id, name, node_type, api_url, api_key, api_secret, status, last_check, created_at, tls_mode, tls_ca_cert, tls_fingerprint, tls_pending_fingerprint
         FROM nodes
         ORDER BY created_at DESC
         "#
@@ -28,35 +70,86 @@ pub async fn list_nodes(
     Ok(Json(nodes))
 }
 
+/// Dry-run connection test used by the "add node" form before anything is saved
+pub async fn test_node(
+    Json(payload): Json<NodeConnectionParams>,
+) -> Result<Json<NodeTestReport>, StatusCode> {
+    validate_tls_settings(
+        payload.tls_mode,
+        payload.tls_ca_cert.as_deref(),
+        payload.tls_fingerprint.as_deref(),
+    )?;
+
+    Ok(Json(test_connection(&payload).await))
+}
+
 pub async fn create_node(
     State(pool): State<DbPool>,
+    State(collector): State<MetricsCollector>,
     Json(payload): Json<CreateNodeRequest>,
-) -> Result<(StatusCode, Json<Node>), StatusCode> {
+) -> Result<(StatusCode, Json<Node>), Response> {
+    let conn = payload.connection;
+    let fingerprint = validate_tls_settings(
+        conn.tls_mode,
+        conn.tls_ca_cert.as_deref(),
+        conn.tls_fingerprint.as_deref(),
+    ).map_err(IntoResponse::into_response)?;
+
+    // Refuse to save nodes we cannot actually talk to; unconfirmed pins are only probed
+    let report = test_connection(&conn).await;
+    let node_type = match report.node_type {
+        Some(t) if report.authenticated || report.pending_trust => t,
+        _ => {
+            tracing::warn!("Rejected node {}: {:?}", payload.name, report.error);
+            return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(report)).into_response());
+        }
+    };
+
+    // Pinned nodes without a fingerprint stay offline until an admin trusts the one we saw
+    let pending_fingerprint = report.presented_fingerprint.clone().filter(|_| report.pending_trust);
+    let status = if report.pending_trust { NodeStatus::Offline } else { NodeStatus::Online };
+
     let node = sqlx::query_as::<_, Node>(
         r#"
-        INSERT INTO nodes (name, node_type, api_url, api_key, api_secret, status)
-        VALUES ($1, $2, $3, $4, $5, $6)
-        RETURNING id, name, node_type, api_url, api_key, api_secret, status, last_check, created_at
+        INSERT INTO nodes (name, node_type, api_url, api_key, api_secret, status, tls_mode, tls_ca_cert, tls_fingerprint, tls_pending_fingerprint)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        RETURNING id, name, node_type, api_url, api_key, api_secret, status, last_check, created_at, tls_mode, tls_ca_cert, tls_fingerprint, tls_pending_fingerprint
         "#
     )
     .bind(payload.name)
-    .bind(payload.node_type)
-    .bind(payload.api_url)
-    .bind(payload.api_key)
-    .bind(payload.api_secret)
-    .bind(NodeStatus::Offline)
+    .bind(node_type)
+    .bind(conn.api_url.trim_end_matches('/'))
+    .bind(conn.api_key)
+    .bind(conn.api_secret)
+    .bind(status)
+    .bind(conn.tls_mode)
+    .bind(conn.tls_ca_cert)
+    .bind(fingerprint)
+    .bind(pending_fingerprint)
     .fetch_one(&pool)
     .await
     .map_err(|e| {
         tracing::error!("Database error: {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
+        StatusCode::INTERNAL_SERVER_ERROR.into_response()
     })?;
 
+    if report.pending_trust {
+        tracing::info!("🔒 Node {} is offline until its certificate is trusted", node.name);
+    }
+
+    if let Some(discovery) = &report.discovery {
+        if let Err(e) = sync_cluster_members(&pool, node.id, &discovery.members).await {
+            tracing::warn!("Failed to record cluster members for {}: {}", node.name, e);
+        }
+    }
+    collector.node_added(node.id);
+
     Ok((StatusCode::CREATED, Json(node)))
 }
 
 pub async fn delete_node(
     State(pool): State<DbPool>,
+    State(clients): State<ClientRegistry>,
     Path(id): Path<uuid::Uuid>,
 ) -> Result<StatusCode, StatusCode> {
     sqlx::query("DELETE FROM nodes WHERE id = $1")
@@ -68,16 +161,20 @@ pub async fn delete_node(
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
 
+    clients.invalidate(id);
+
     Ok(StatusCode::NO_CONTENT)
 }
 
 pub async fn get_node_details(
     State(pool): State<DbPool>,
+    State(clients): State<ClientRegistry>,
+    State(webhooks): State<WebhookDispatcher>,
     Path(id): Path<uuid::Uuid>,
 ) -> Result<Json<Value>, StatusCode> {
     let node = sqlx::query_as::<_, Node>(
         r#"
-        SELECT id, name, node_type, api_url, api_key, api_secret, status, last_check, created_at
+        SELECT id, name, node_type, api_url, api_key, api_secret, status, last_check, created_at, tls_mode, tls_ca_cert, tls_fingerprint, tls_pending_fingerprint
         FROM nodes
         WHERE id = $1
         "#
@@ -88,24 +185,11 @@ pub async fn get_node_details(
     .map_err(|_| StatusCode::NOT_FOUND)?;
 
     // Perform a real-time health check
-    let status = match node.node_type {
-        NodeType::Proxmox => {
-            let client = ProxmoxClient::new(
-                node.api_url.clone(),
-                node.api_key.clone(),
-                node.api_secret.clone().unwrap_or_default(),
-            );
-            client.check_health().await.unwrap_or(NodeStatus::Offline)
-        }
-        NodeType::Incus => {
-            let client = IncusClient::new(
-                node.api_url.clone(),
-                node.api_key.clone(),
-                node.api_secret.clone(),
-            );
-            client.check_health().await.unwrap_or(NodeStatus::Offline)
-        }
+    let status = match clients.client_for(&node) {
+        Ok(client) => client.check_health().await.unwrap_or(NodeStatus::Offline),
+        Err(_) => NodeStatus::Error,
     };
+    record_node_status(&pool, &webhooks, &node, status).await;
 
     let mut response = serde_json::to_value(&node).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
     if let Some(obj) = response.as_object_mut() {
@@ -121,43 +205,255 @@ pub struct UpdateNodeRequest {
     pub api_url: Option<String>,
     pub api_key: Option<String>,
     pub api_secret: Option<String>,
+    pub tls_mode: Option<NodeTlsMode>,
+    pub tls_ca_cert: Option<String>,
+    pub tls_fingerprint: Option<String>,
 }
 
 pub async fn update_node(
     axum::extract::State(pool): axum::extract::State<crate::db::DbPool>,
+    axum::extract::State(clients): axum::extract::State<ClientRegistry>,
+    axum::extract::State(webhooks): axum::extract::State<WebhookDispatcher>,
     axum::extract::Path(id): axum::extract::Path<uuid::Uuid>,
     axum::Json(payload): axum::Json<UpdateNodeRequest>,
-) -> Result<axum::Json<crate::models::node::Node>, axum::http::StatusCode> {
-    let node = sqlx::query_as::<_, crate::models::node::Node>("SELECT id, name, node_type, api_url, api_key, api_secret, status, last_check, created_at FROM nodes WHERE id = $1")
+) -> Result<axum::Json<crate::models::node::Node>, Response> {
+    let node = sqlx::query_as::<_, crate::models::node::Node>("SELECT id, name, node_type, api_url, api_key, api_secret, status, last_check, created_at, tls_mode, tls_ca_cert, tls_fingerprint, tls_pending_fingerprint FROM nodes WHERE id = $1")
         .bind(id)
         .fetch_one(&pool)
         .await
-        .map_err(|_| axum::http::StatusCode::NOT_FOUND)?;
+        .map_err(|_| axum::http::StatusCode::NOT_FOUND.into_response())?;
+
+    let name = payload.name.unwrap_or(node.name.clone());
+    let api_url = payload.api_url.map(|u| u.trim_end_matches('/').to_string()).unwrap_or(node.api_url.clone());
+    let api_key = payload.api_key.unwrap_or(node.api_key.clone());
+    let api_secret = payload.api_secret.or(node.api_secret.clone());
+    let tls_mode = payload.tls_mode.unwrap_or(node.tls_mode);
+    let tls_ca_cert = payload.tls_ca_cert.or(node.tls_ca_cert.clone());
+
+    // A pin belongs to one certificate on one host, so it does not survive a new URL or mode.
+    // An empty `tls_fingerprint` clears it, sending a pinned node back through `/tls/trust`.
+    let settings_changed = api_url != node.api_url || tls_mode != node.tls_mode;
+    let (tls_fingerprint, mut tls_pending_fingerprint) = match payload.tls_fingerprint.as_deref().map(str::trim) {
+        Some("") => (None, None),
+        Some(fingerprint) => (validate_tls_settings(tls_mode, tls_ca_cert.as_deref(), Some(fingerprint)).map_err(IntoResponse::into_response)?, None),
+        None if settings_changed => (validate_tls_settings(tls_mode, tls_ca_cert.as_deref(), None).map_err(IntoResponse::into_response)?, None),
+        None => (
+            validate_tls_settings(tls_mode, tls_ca_cert.as_deref(), None).map_err(IntoResponse::into_response)?.or(node.tls_fingerprint.clone()),
+            node.tls_pending_fingerprint.clone(),
+        ),
+    };
 
-    let name = payload.name.unwrap_or(node.name);
-    let api_url = payload.api_url.unwrap_or(node.api_url);
-    let api_key = payload.api_key.unwrap_or(node.api_key);
-    let api_secret = payload.api_secret.or(node.api_secret);
+    // New connection settings get the same dry run as a new node before they replace working ones
+    let mut status = None;
+    let connection_changed = settings_changed
+        || api_key != node.api_key
+        || api_secret != node.api_secret
+        || tls_ca_cert != node.tls_ca_cert
+        || tls_fingerprint != node.tls_fingerprint;
+    if connection_changed {
+        let report = test_connection(&NodeConnectionParams {
+            node_type: Some(node.node_type),
+            api_url: api_url.clone(),
+            api_key: api_key.clone(),
+            api_secret: api_secret.clone(),
+            tls_mode,
+            tls_ca_cert: tls_ca_cert.clone(),
+            tls_fingerprint: tls_fingerprint.clone(),
+        }).await;
+        if !(report.authenticated || report.pending_trust) {
+            tracing::warn!("Rejected new settings for node {}: {:?}", node.name, report.error);
+            return Err((StatusCode::UNPROCESSABLE_ENTITY, Json(report)).into_response());
+        }
+
+        if report.pending_trust {
+            tls_pending_fingerprint = report.presented_fingerprint.clone();
+        }
+        status = Some(if report.pending_trust { NodeStatus::Offline } else { NodeStatus::Online });
+        if let Some(discovery) = &report.discovery {
+            if let Err(e) = sync_cluster_members(&pool, node.id, &discovery.members).await {
+                tracing::warn!("Failed to record cluster members for {}: {}", node.name, e);
+            }
+        }
+    }
 
-    let updated_node = sqlx::query_as::<_, crate::models::node::Node>(
+    let mut updated_node = sqlx::query_as::<_, crate::models::node::Node>(
         r#"
         UPDATE nodes 
-        SET name = $1, api_url = $2, api_key = $3, api_secret = $4, last_check = NOW()
-        WHERE id = $5
-        RETURNING id, name, node_type, api_url, api_key, api_secret, status, last_check, created_at
+        SET name = $1, api_url = $2, api_key = $3, api_secret = $4, tls_mode = $5, tls_ca_cert = $6, tls_fingerprint = $7, tls_pending_fingerprint = $8, last_check = NOW()
+        WHERE id = $9
+        RETURNING id, name, node_type, api_url, api_key, api_secret, status, last_check, created_at, tls_mode, tls_ca_cert, tls_fingerprint, tls_pending_fingerprint
         "#
     )
     .bind(name)
     .bind(api_url)
     .bind(api_key)
     .bind(api_secret)
+    .bind(tls_mode)
+    .bind(tls_ca_cert)
+    .bind(tls_fingerprint)
+    .bind(tls_pending_fingerprint)
     .bind(id)
     .fetch_one(&pool)
     .await
     .map_err(|e| {
         tracing::error!("Update error: {}", e);
-        axum::http::StatusCode::INTERNAL_SERVER_ERROR
+        axum::http::StatusCode::INTERNAL_SERVER_ERROR.into_response()
     })?;
 
+    // Connection settings may have changed; rebuild the client on next use
+    clients.invalidate(id);
+    if let Some(status) = status {
+        record_node_status(&pool, &webhooks, &updated_node, status).await;
+        updated_node.status = status;
+    }
+
+    // Credentials stay out of the event payload
+    webhooks.emit("node.updated", serde_json::json!({
+        "node_id": updated_node.id,
+        "node_name": updated_node.name,
+        "node_type": updated_node.node_type,
+        "api_url": updated_node.api_url,
+        "tls_mode": updated_node.tls_mode,
+    })).await;
+
     Ok(axum::Json(updated_node))
 }
+
+#[derive(serde::Serialize)]
+pub struct NodeTlsStatus {
+    pub tls_mode: NodeTlsMode,
+    pub trusted_fingerprint: Option<String>,
+    /// Seen on a probe and waiting for `/tls/trust`
+    pub pending_fingerprint: Option<String>,
+    /// What the node presents right now; only filled in by `/tls/probe`
+    pub presented_fingerprint: Option<String>,
+    pub trusted: Option<bool>,
+}
+
+async fn fetch_node(pool: &DbPool, id: uuid::Uuid) -> Result<Node, StatusCode> {
+    sqlx::query_as::<_, Node>(
+        "SELECT id, name, node_type, api_url, api_key, api_secret, status, last_check, created_at, tls_mode, tls_ca_cert, tls_fingerprint, tls_pending_fingerprint FROM nodes WHERE id = $1"
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Database error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Stored TLS settings of a node; does not contact it
+pub async fn get_node_tls(
+    State(pool): State<DbPool>,
+    Path(id): Path<uuid::Uuid>,
+) -> Result<Json<NodeTlsStatus>, StatusCode> {
+    let node = fetch_node(&pool, id).await?;
+
+    Ok(Json(NodeTlsStatus {
+        tls_mode: node.tls_mode,
+        trusted_fingerprint: node.tls_fingerprint,
+        pending_fingerprint: node.tls_pending_fingerprint,
+        presented_fingerprint: None,
+        trusted: None,
+    }))
+}
+
+/// Read the certificate a pinned node presents now; a new one is recorded as pending for
+/// `/tls/trust`. Other modes are verified on every connection and have nothing to confirm.
+pub async fn probe_node_tls(
+    State(pool): State<DbPool>,
+    Path(id): Path<uuid::Uuid>,
+) -> Result<Json<NodeTlsStatus>, StatusCode> {
+    let node = fetch_node(&pool, id).await?;
+    if node.tls_mode != NodeTlsMode::Pinned {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    let presented = capture_pending_fingerprint(&pool, &node).await.map_err(|e| {
+        tracing::warn!("Could not read TLS certificate from {}: {}", node.name, e);
+        StatusCode::BAD_GATEWAY
+    })?;
+    let trusted = node.tls_fingerprint.as_deref() == Some(presented.as_str());
+
+    Ok(Json(NodeTlsStatus {
+        tls_mode: node.tls_mode,
+        trusted_fingerprint: node.tls_fingerprint,
+        pending_fingerprint: (!trusted).then(|| presented.clone()),
+        presented_fingerprint: Some(presented),
+        trusted: Some(trusted),
+    }))
+}
+
+#[derive(serde::Deserialize)]
+pub struct TrustFingerprintRequest {
+    pub fingerprint: String,
+}
+
+/// Admin confirmation step of trust-on-first-use: pin the fingerprint captured on first contact
+pub async fn trust_node_fingerprint(
+    State(pool): State<DbPool>,
+    State(clients): State<ClientRegistry>,
+    Path(id): Path<uuid::Uuid>,
+    Json(payload): Json<TrustFingerprintRequest>,
+) -> Result<Json<Node>, StatusCode> {
+    let fingerprint = tls::normalize_fingerprint(&payload.fingerprint)
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    // Only the fingerprint we actually observed can be confirmed, so a typo cannot pin a wrong cert
+    let node = sqlx::query_as::<_, Node>(
+        r#"
+        UPDATE nodes
+        SET tls_mode = 'pinned', tls_fingerprint = tls_pending_fingerprint, tls_pending_fingerprint = NULL
+        WHERE id = $1 AND tls_pending_fingerprint = $2
+        RETURNING id, name, node_type, api_url, api_key, api_secret, status, last_check, created_at, tls_mode, tls_ca_cert, tls_fingerprint, tls_pending_fingerprint
+        "#
+    )
+    .bind(id)
+    .bind(&fingerprint)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Database error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .ok_or(StatusCode::CONFLICT)?;
+
+    clients.invalidate(id);
+    tracing::info!("🔒 Pinned TLS certificate {} for node {}", fingerprint, node.name);
+
+    Ok(Json(node))
+}
+
+#[derive(serde::Serialize)]
+pub struct NodeClusterResponse {
+    pub cluster_name: Option<String>,
+    pub quorate: bool,
+    pub members: Vec<crate::models::node::ClusterMemberRecord>,
+}
+
+/// Refresh and return the cluster members and quorum state behind a dashboard node
+pub async fn get_node_cluster(
+    State(pool): State<DbPool>,
+    State(clients): State<ClientRegistry>,
+    Path(id): Path<uuid::Uuid>,
+) -> Result<Json<NodeClusterResponse>, StatusCode> {
+    let client = clients.get(id).await.map_err(|_| StatusCode::NOT_FOUND)?;
+
+    let cluster = client.cluster_status().await.map_err(|e| {
+        tracing::error!("Failed to query cluster status for node {}: {}", id, e);
+        StatusCode::BAD_GATEWAY
+    })?;
+
+    let members = sync_cluster_members(&pool, id, &cluster.members).await.map_err(|e| {
+        tracing::error!("Database error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(NodeClusterResponse {
+        cluster_name: cluster.name,
+        quorate: cluster.quorate,
+        members,
+    }))
+}
@@ -0,0 +1,217 @@
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use futures::future::join_all;
+use serde_json::Value;
+use std::time::Duration;
+use crate::clients::registry::{ClientRegistry, SharedClient};
+use crate::db::DbPool;
+use crate::models::node::{Node, NodeType};
+use crate::services::metrics::{MetricsCollector, NodeSample, NodeSnapshot};
+use crate::services::telemetry::{Exposition, Telemetry};
+use crate::services::vms::internal_vm_id;
+
+/// Guest listings that take longer are left out of the scrape rather than holding it up
+const LIST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Everything learned about one node during a scrape
+struct NodeScrape {
+    node: Node,
+    up: bool,
+    health_seconds: f64,
+    sample: Option<NodeSample>,
+    vms: Vec<Value>,
+}
+
+/// Compare secrets without short-circuiting on the first differing byte
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Node figures come from the metrics collector; only the guest listing is fetched live
+async fn scrape_node(node: Node, client: SharedClient, snapshot: NodeSnapshot) -> NodeScrape {
+    let NodeSnapshot { up, health_seconds, sample, .. } = snapshot;
+    if !up {
+        return NodeScrape { node, up, health_seconds, sample: None, vms: Vec::new() };
+    }
+
+    let vms = match tokio::time::timeout(LIST_TIMEOUT, list_vms(&node, client)).await {
+        Ok(vms) => vms,
+        Err(_) => {
+            tracing::warn!("Prometheus scrape timed out listing VMs on {}", node.name);
+            Vec::new()
+        }
+    };
+
+    NodeScrape { node, up, health_seconds, sample, vms }
+}
+
+async fn list_vms(node: &Node, client: SharedClient) -> Vec<Value> {
+    let vms = match client.list_vms().await {
+        Ok(vms) => vms,
+        Err(e) => {
+            tracing::warn!("Prometheus scrape could not list VMs on {}: {}", node.name, e);
+            Vec::new()
+        }
+    };
+
+    // Incus listings carry no traffic or CPU time counters, so fetch them per instance
+    if node.node_type == NodeType::Incus {
+        join_all(vms.into_iter().map(|vm| {
+            let client = client.clone();
+            async move {
+                let name = vm["name"].as_str().unwrap_or_default().to_string();
+                let counters = client.vm_counters(&name).await.ok();
+                serde_json::json!({
+                    "name": name,
+                    "status": counters.as_ref().map(|c| c.status.clone()).unwrap_or_else(|| vm["status"].as_str().unwrap_or("unknown").to_lowercase()),
+                    "mem": counters.as_ref().map(|c| c.mem_used),
+                    "maxmem": counters.as_ref().map(|c| c.mem_total),
+                    "netin": counters.as_ref().map(|c| c.net_in),
+                    "netout": counters.as_ref().map(|c| c.net_out),
+                    "cpu_time_ns": counters.as_ref().and_then(|c| c.cpu_time_ns),
+                })
+            }
+        }))
+        .await
+    } else {
+        vms
+    }
+}
+
+fn render_node(out: &mut Exposition, scrape: &NodeScrape) {
+    let node_id = scrape.node.id.to_string();
+    let labels = [("node_id", node_id.as_str()), ("node", scrape.node.name.as_str())];
+
+    out.sample("fossvps_node_up", "Whether the node answered its last health check.", "gauge", &labels, if scrape.up { 1.0 } else { 0.0 });
+    out.sample("fossvps_node_health_check_seconds", "Latency of the last node health check.", "gauge", &labels, scrape.health_seconds);
+
+    if let Some(sample) = &scrape.sample {
+        out.sample("fossvps_node_cpu_usage_ratio", "Node CPU usage across all members (0-1).", "gauge", &labels, sample.cpu as f64 / 100.0);
+        out.sample("fossvps_node_memory_usage_ratio", "Node memory usage (0-1).", "gauge", &labels, sample.ram as f64 / 100.0);
+        if let Some(disk) = sample.disk {
+            out.sample("fossvps_node_disk_usage_ratio", "Node root filesystem usage (0-1).", "gauge", &labels, disk as f64 / 100.0);
+        }
+        if let Some(net_in) = sample.net_in {
+            out.sample("fossvps_node_network_receive_bytes_per_second", "Node inbound network throughput.", "gauge", &labels, net_in as f64);
+        }
+        if let Some(net_out) = sample.net_out {
+            out.sample("fossvps_node_network_transmit_bytes_per_second", "Node outbound network throughput.", "gauge", &labels, net_out as f64);
+        }
+        if let Some(read) = sample.disk_read {
+            out.sample("fossvps_node_disk_read_bytes_per_second", "Guest disk reads on the node.", "gauge", &labels, read as f64);
+        }
+        if let Some(write) = sample.disk_write {
+            out.sample("fossvps_node_disk_write_bytes_per_second", "Guest disk writes on the node.", "gauge", &labels, write as f64);
+        }
+        if let Some([load1, ..]) = sample.load {
+            out.sample("fossvps_node_load1", "Node 1-minute load average, averaged over members.", "gauge", &labels, load1 as f64);
+        }
+        if let Some(uptime) = sample.uptime {
+            out.sample("fossvps_node_uptime_seconds", "Longest member uptime.", "gauge", &labels, uptime as f64);
+        }
+        if let Some(quorate) = sample.quorate {
+            out.sample("fossvps_node_cluster_quorate", "Whether the node's cluster has quorum.", "gauge", &labels, if quorate { 1.0 } else { 0.0 });
+        }
+    }
+
+    for vm in &scrape.vms {
+        let Some(vm_id) = internal_vm_id(scrape.node.node_type, vm) else { continue };
+        let name = vm["name"].as_str().unwrap_or(&vm_id).to_string();
+        let status = vm["status"].as_str().unwrap_or("unknown").to_string();
+        let labels = [("node_id", node_id.as_str()), ("node", scrape.node.name.as_str()), ("vm_id", vm_id.as_str()), ("vm_name", name.as_str())];
+
+        let mut status_labels = labels.to_vec();
+        status_labels.push(("status", status.as_str()));
+        out.sample("fossvps_vm_status", "Current guest status; always 1, the status is in the label.", "gauge", &status_labels, 1.0);
+
+        if let Some(cpu) = vm["cpu"].as_f64() {
+            out.sample("fossvps_vm_cpu_usage_ratio", "Guest CPU usage as a fraction of its vCPUs (0-1).", "gauge", &labels, cpu);
+        }
+        if let Some(ns) = vm["cpu_time_ns"].as_u64() {
+            out.sample("fossvps_vm_cpu_seconds_total", "Cumulative guest CPU time.", "counter", &labels, ns as f64 / 1e9);
+        }
+        if let Some(mem) = vm["mem"].as_u64() {
+            out.sample("fossvps_vm_memory_used_bytes", "Guest memory in use.", "gauge", &labels, mem as f64);
+        }
+        if let Some(maxmem) = vm["maxmem"].as_u64() {
+            out.sample("fossvps_vm_memory_total_bytes", "Guest memory allocation.", "gauge", &labels, maxmem as f64);
+        }
+        if let Some(disk) = vm["disk"].as_u64() {
+            out.sample("fossvps_vm_disk_used_bytes", "Guest disk usage, where the hypervisor reports it.", "gauge", &labels, disk as f64);
+        }
+        if let Some(maxdisk) = vm["maxdisk"].as_u64() {
+            out.sample("fossvps_vm_disk_total_bytes", "Guest disk allocation.", "gauge", &labels, maxdisk as f64);
+        }
+        if let Some(netin) = vm["netin"].as_u64() {
+            out.sample("fossvps_vm_network_receive_bytes_total", "Bytes received by the guest.", "counter", &labels, netin as f64);
+        }
+        if let Some(netout) = vm["netout"].as_u64() {
+            out.sample("fossvps_vm_network_transmit_bytes_total", "Bytes sent by the guest.", "counter", &labels, netout as f64);
+        }
+    }
+}
+
+/// Prometheus scrape target; enabled by setting PROMETHEUS_SCRAPE_TOKEN
+pub async fn prometheus_handler(
+    headers: HeaderMap,
+    State(pool): State<DbPool>,
+    State(clients): State<ClientRegistry>,
+    State(collector): State<MetricsCollector>,
+    State(telemetry): State<Telemetry>,
+) -> Response {
+    // Hide the endpoint entirely unless a scrape token is configured
+    let expected = match std::env::var("PROMETHEUS_SCRAPE_TOKEN") {
+        Ok(t) if !t.is_empty() => t,
+        _ => return StatusCode::NOT_FOUND.into_response(),
+    };
+
+    let provided = headers.get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    if !constant_time_eq(provided.as_bytes(), expected.as_bytes()) {
+        tracing::warn!("❌ Prometheus scrape rejected: invalid token");
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    let nodes = match sqlx::query_as::<_, Node>(
+        "SELECT id, name, node_type, api_url, api_key, api_secret, status, last_check, created_at, tls_mode, tls_ca_cert, tls_fingerprint, tls_pending_fingerprint FROM nodes ORDER BY name"
+    )
+    .fetch_all(&pool)
+    .await
+    {
+        Ok(n) => n,
+        Err(e) => {
+            tracing::error!("Failed to fetch nodes for Prometheus scrape: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let mut out = Exposition::new();
+    let mut pending = Vec::new();
+    for node in nodes {
+        // Not reached by the collector yet, e.g. right after startup
+        let Some(snapshot) = collector.snapshot(node.id) else { continue };
+        match clients.client_for(&node) {
+            Ok(client) => pending.push(scrape_node(node, client, snapshot)),
+            Err(e) => {
+                tracing::warn!("Prometheus scrape skipping {}: {}", node.name, e);
+                let node_id = node.id.to_string();
+                out.sample("fossvps_node_up", "Whether the node answered its last health check.", "gauge", &[("node_id", &node_id), ("node", &node.name)], 0.0);
+            }
+        }
+    }
+
+    for scrape in join_all(pending).await {
+        render_node(&mut out, &scrape);
+    }
+    telemetry.render(&mut out);
+
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        out.finish(),
+    ).into_response()
+}
@@ -4,30 +4,34 @@ use axum::{
     extract::State,
 };
 use crate::db::DbPool;
-use crate::models::support::SupportMessageRequest;
+use crate::models::support::{SupportMessageRequest, SupportTicket};
+use crate::services::webhooks::WebhookDispatcher;
 
 pub async fn handle_support_message(
     State(pool): State<DbPool>,
+    State(webhooks): State<WebhookDispatcher>,
     Json(payload): Json<SupportMessageRequest>,
 ) -> Result<StatusCode, StatusCode> {
-    sqlx::query("INSERT INTO support_tickets (subject, message, priority) VALUES ($1, $2, $3)")
+    let ticket = sqlx::query_as::<_, SupportTicket>("INSERT INTO support_tickets (subject, message, priority) VALUES ($1, $2, $3) RETURNING id, subject, message, priority, status, created_at")
         .bind(payload.subject)
         .bind(payload.message)
         .bind(payload.priority)
-        .execute(&pool)
+        .fetch_one(&pool)
         .await
         .map_err(|e| {
             tracing::error!("Failed to insert support ticket: {}", e);
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
+
+    webhooks.emit("support.ticket_created", &ticket).await;
     
     Ok(StatusCode::OK)
 }
 
 pub async fn handle_support_history(
     State(pool): State<DbPool>,
-) -> Result<Json<Vec<crate::models::support::SupportTicket>>, StatusCode> {
-    let tickets = sqlx::query_as::<_, crate::models::support::SupportTicket>("SELECT id, subject, message, priority, status, created_at FROM support_tickets ORDER BY created_at DESC")
+) -> Result<Json<Vec<SupportTicket>>, StatusCode> {
+    let tickets = sqlx::query_as::<_, SupportTicket>("SELECT id, subject, message, priority, status, created_at FROM support_tickets ORDER BY created_at DESC")
         .fetch_all(&pool)
         .await
         .map_err(|e| {
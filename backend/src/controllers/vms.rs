@@ -1,13 +1,36 @@
 use axum::{
     extract::State,
     Json,
-    http::StatusCode,
+    http::{header, HeaderMap, StatusCode},
+    response::{sse::{Event, KeepAlive, Sse}, IntoResponse, Response},
 };
-use crate::db::DbPool;
-use crate::services::vms::{list_all_vms, perform_vm_power_action};
+use futures::stream::{self, Stream, StreamExt};
+use std::convert::Infallible;
+use tokio::sync::broadcast;
+use crate::state::AppState;
+use crate::clients::{ConfigConflict, FsFreezeAction, GuestInfo, VmDetails};
+use crate::models::cloud_init::{CloudInitConfig, RegenerateCloudInitRequest, UpdateCloudInitRequest};
+use crate::models::media::EjectMediaRequest;
+use crate::models::resize::{ResizeOutcome, ResizeVmRequest};
+use crate::services::inventory::{InventoryEvent, InventoryWatcher};
+use crate::services::vms::{list_all_vms, perform_vm_power_action, filter_and_sort_vms, paginate, GuestType, VmFilter, VmPage, VmSortKey};
 use serde_json::Value;
 use serde::Deserialize;
 
+#[derive(Deserialize)]
+pub struct ListVmsQuery {
+    pub status: Option<String>,
+    pub node_id: Option<String>,
+    #[serde(rename = "type")]
+    pub vm_type: Option<String>,
+    pub name: Option<String>,
+    pub tag: Option<String>,
+    pub sort: Option<String>,
+    pub order: Option<String>,
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
 #[derive(Deserialize)]
 pub struct PowerActionRequest {
     pub node_id: String,
@@ -20,6 +43,15 @@ pub struct UpdateConfigRequest {
     pub node_id: String,
     pub vm_id: String,
     pub config: Value,
+    /// From `/vms/details`; may be sent as `If-Match` instead
+    pub digest: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct FsFreezeRequest {
+    pub node_id: String,
+    pub vm_id: String,
+    pub action: FsFreezeAction,
 }
 
 #[derive(Deserialize)]
@@ -30,38 +62,205 @@ pub struct MediaRequest {
 }
 
 pub async fn list_vms(
-    State(pool): State<DbPool>,
-) -> Result<Json<Vec<Value>>, StatusCode> {
-    let vms = list_all_vms(&pool).await.map_err(|e| {
+    State(state): State<AppState>,
+    axum::extract::Query(query): axum::extract::Query<ListVmsQuery>,
+) -> Result<Json<VmPage>, StatusCode> {
+    let sort = query.sort.as_deref()
+        .map(|s| s.parse::<VmSortKey>())
+        .transpose()
+        .map_err(|e| {
+            tracing::warn!("Rejected VM list query: {}", e);
+            StatusCode::BAD_REQUEST
+        })?;
+
+    let guest_type = query.vm_type.as_deref()
+        .map(|t| t.parse::<GuestType>())
+        .transpose()
+        .map_err(|e| {
+            tracing::warn!("Rejected VM list query: {}", e);
+            StatusCode::BAD_REQUEST
+        })?;
+
+    let descending = match query.order.as_deref() {
+        None | Some("asc") => false,
+        Some("desc") => true,
+        Some(_) => return Err(StatusCode::BAD_REQUEST),
+    };
+
+    let filter = VmFilter {
+        status: query.status,
+        node_id: query.node_id,
+        guest_type,
+        name: query.name,
+        tag: query.tag,
+    };
+
+    let vms = list_all_vms(&state).await.map_err(|e| {
         tracing::error!("Failed to list VMs: {}", e);
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
 
-    Ok(Json(vms))
+    let matched = filter_and_sort_vms(vms, &filter, sort, descending);
+    Ok(Json(paginate(matched, query.offset.unwrap_or(0), query.limit)))
+}
+
+/// The raw config with its version as `digest` in the body and as the `ETag` header
+fn details_response(status: StatusCode, details: VmDetails) -> Response {
+    let VmDetails { mut config, digest } = details;
+    let Some(digest) = digest else {
+        return (status, Json(config)).into_response();
+    };
+
+    if let Some(obj) = config.as_object_mut() {
+        obj.insert("digest".to_string(), Value::String(digest.clone()));
+    }
+    let etag = format!("\"{}\"", digest.trim_matches('"'));
+    (status, [(header::ETAG, etag)], Json(config)).into_response()
 }
 
 pub async fn handle_get_vm_details(
-    State(pool): State<DbPool>,
+    State(state): State<AppState>,
     axum::extract::Query(params): axum::extract::Query<std::collections::HashMap<String, String>>,
-) -> Result<Json<Value>, StatusCode> {
+) -> Result<Response, StatusCode> {
     let node_id = params.get("node_id").ok_or(StatusCode::BAD_REQUEST)?;
     let vm_id = params.get("vm_id").ok_or(StatusCode::BAD_REQUEST)?;
 
-    let details = crate::services::vms::get_vm_info(&pool, node_id, vm_id)
+    let details = crate::services::vms::get_vm_info(&state, node_id, vm_id)
         .await
         .map_err(|e| {
             tracing::error!("Failed to get VM details: {}", e);
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
 
-    Ok(Json(details))
+    Ok(details_response(StatusCode::OK, details))
+}
+
+/// IPs and OS reported by the guest agent (Proxmox) or instance state (Incus)
+pub async fn handle_get_guest_info(
+    State(state): State<AppState>,
+    axum::extract::Query(params): axum::extract::Query<std::collections::HashMap<String, String>>,
+) -> Result<Json<GuestInfo>, StatusCode> {
+    let node_id = params.get("node_id").ok_or(StatusCode::BAD_REQUEST)?;
+    let vm_id = params.get("vm_id").ok_or(StatusCode::BAD_REQUEST)?;
+
+    let info = crate::services::vms::get_guest_info(&state, node_id, vm_id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to get guest info: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(info))
+}
+
+/// Freeze or thaw guest filesystems (e.g. around an external snapshot), or query their state
+pub async fn handle_guest_fsfreeze(
+    State(state): State<AppState>,
+    Json(payload): Json<FsFreezeRequest>,
+) -> Result<Json<Value>, StatusCode> {
+    let result = crate::services::vms::guest_fsfreeze(&state, &payload.node_id, &payload.vm_id, payload.action)
+        .await
+        .map_err(|e| {
+            tracing::error!("Guest filesystem {:?} failed: {}", payload.action, e);
+            StatusCode::BAD_GATEWAY
+        })?;
+
+    Ok(Json(serde_json::json!({ "result": result })))
+}
+
+pub async fn handle_get_cloud_init(
+    State(state): State<AppState>,
+    axum::extract::Query(params): axum::extract::Query<std::collections::HashMap<String, String>>,
+) -> Result<Json<CloudInitConfig>, StatusCode> {
+    let node_id = params.get("node_id").ok_or(StatusCode::BAD_REQUEST)?;
+    let vm_id = params.get("vm_id").ok_or(StatusCode::BAD_REQUEST)?;
+
+    let config = crate::services::vms::get_cloud_init(&state, node_id, vm_id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to get cloud-init config: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(config))
+}
+
+/// Update cloud-init settings: structured fields on Proxmox, raw YAML on Incus
+pub async fn handle_update_cloud_init(
+    State(state): State<AppState>,
+    Json(payload): Json<UpdateCloudInitRequest>,
+) -> Result<StatusCode, StatusCode> {
+    let node_id = uuid::Uuid::parse_str(&payload.node_id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let client = state.clients.get(node_id).await.map_err(|_| StatusCode::NOT_FOUND)?;
+
+    if let Err(e) = crate::services::cloud_init::validate(&payload.changes, client.as_proxmox().is_some()) {
+        tracing::warn!("☁️ Rejected cloud-init update for {}: {}", payload.vm_id, e);
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    crate::services::vms::update_cloud_init(&state, &client, node_id, &payload.vm_id, &payload.changes)
+        .await
+        .map_err(|e| {
+            tracing::error!("Cloud-init update failed: {}", e);
+            StatusCode::BAD_GATEWAY
+        })?;
+
+    Ok(StatusCode::OK)
+}
+
+/// Rebuild a Proxmox VM's cloud-init drive; Incus builds it at every start
+pub async fn handle_regenerate_cloud_init(
+    State(state): State<AppState>,
+    Json(payload): Json<RegenerateCloudInitRequest>,
+) -> Result<StatusCode, StatusCode> {
+    let node_id = uuid::Uuid::parse_str(&payload.node_id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let client = state.clients.get(node_id).await.map_err(|_| StatusCode::NOT_FOUND)?;
+    let proxmox = client.as_proxmox().ok_or(StatusCode::UNPROCESSABLE_ENTITY)?;
+
+    proxmox.regenerate_cloud_init(&payload.vm_id).await.map_err(|e| {
+        tracing::error!("Cloud-init regeneration failed: {}", e);
+        StatusCode::BAD_GATEWAY
+    })?;
+
+    tracing::info!("☁️ Regenerated cloud-init drive of {}", payload.vm_id);
+    Ok(StatusCode::OK)
+}
+
+fn sse_event(event: &InventoryEvent) -> Event {
+    Event::default().event(event.name).data(&*event.data)
+}
+
+/// Server-sent inventory changes: a `snapshot` event with every VM, then `diff` events listing
+/// guests that were added, removed, or changed status, resources or host
+pub async fn vm_events(
+    State(watcher): State<InventoryWatcher>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let (snapshot, rx) = watcher.subscribe();
+    let initial = stream::iter(snapshot.as_ref().map(sse_event));
+
+    let updates = stream::unfold((rx, watcher), |(mut rx, watcher)| async move {
+        loop {
+            match rx.recv().await {
+                Ok(event) => return Some((sse_event(&event), (rx, watcher))),
+                // Missed diffs cannot be replayed; resend the whole inventory instead
+                Err(broadcast::error::RecvError::Lagged(_)) => {
+                    if let Some(snapshot) = watcher.latest() {
+                        return Some((sse_event(&snapshot), (rx, watcher)));
+                    }
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    Sse::new(initial.chain(updates).map(Ok)).keep_alive(KeepAlive::default())
 }
 
 pub async fn handle_vm_power_action(
-    State(pool): State<DbPool>,
+    State(state): State<AppState>,
     Json(payload): Json<PowerActionRequest>,
 ) -> Result<StatusCode, StatusCode> {
-    perform_vm_power_action(&pool, &payload.node_id, &payload.vm_id, &payload.action)
+    perform_vm_power_action(&state, &payload.node_id, &payload.vm_id, &payload.action)
         .await
         .map_err(|e| {
             tracing::error!("Power action failed: {}", e);
@@ -71,25 +270,80 @@ pub async fn handle_vm_power_action(
     Ok(StatusCode::OK)
 }
 
+/// Raw config passthrough; `/vms/resize` is the checked way to change CPU, memory and disks.
+/// Requires the digest the config was read at and answers 409 with the current config when
+/// someone else changed it in the meantime.
 pub async fn handle_update_vm_config(
-    State(pool): State<DbPool>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
     Json(payload): Json<UpdateConfigRequest>,
-) -> Result<StatusCode, StatusCode> {
-    crate::services::vms::update_vm_resources(&pool, &payload.node_id, &payload.vm_id, payload.config)
+) -> Result<StatusCode, Response> {
+    let if_match = headers.get(header::IF_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim_start_matches("W/").trim_matches('"').to_string());
+    let digest = payload.digest.clone().or(if_match).filter(|d| !d.is_empty())
+        .ok_or_else(|| StatusCode::PRECONDITION_REQUIRED.into_response())?;
+
+    let result = crate::services::vms::update_vm_resources(&state, &payload.node_id, &payload.vm_id, payload.config, &digest).await;
+    match result {
+        Ok(()) => Ok(StatusCode::OK),
+        Err(e) if e.is::<ConfigConflict>() => {
+            tracing::warn!("⚠️ Config of {} changed since digest {}", payload.vm_id, digest);
+            let current = crate::services::vms::get_vm_info(&state, &payload.node_id, &payload.vm_id)
+                .await
+                .map_err(|e| {
+                    tracing::error!("Failed to reload VM details: {}", e);
+                    StatusCode::CONFLICT.into_response()
+                })?;
+            Err(details_response(StatusCode::CONFLICT, current))
+        }
+        Err(e) => {
+            tracing::error!("Config update failed: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+        }
+    }
+}
+
+/// Change cores, sockets, memory, balloon or disk size, checked against the host first.
+/// The response says whether the guest must restart for everything to apply.
+pub async fn handle_resize_vm(
+    State(state): State<AppState>,
+    Json(mut payload): Json<ResizeVmRequest>,
+) -> Result<Json<ResizeOutcome>, StatusCode> {
+    if payload.resize.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let node_id = uuid::Uuid::parse_str(&payload.node_id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let client = state.clients.get(node_id).await.map_err(|_| StatusCode::NOT_FOUND)?;
+
+    let limits = client.resize_limits(&payload.vm_id, payload.resize.disk.as_deref())
         .await
         .map_err(|e| {
-            tracing::error!("Config update failed: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
+            tracing::error!("Failed to read resize limits: {}", e);
+            StatusCode::BAD_GATEWAY
         })?;
+    if let Err(e) = crate::services::resize::validate(&payload.resize, &limits) {
+        tracing::warn!("📐 Rejected resize of {}: {}", payload.vm_id, e);
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+    // Grow the disk the limits were checked for, which may be the default boot disk
+    payload.resize.disk = limits.disk;
 
-    Ok(StatusCode::OK)
+    let outcome = crate::services::vms::resize_vm(&state, &client, node_id, &payload.vm_id, &payload.resize)
+        .await
+        .map_err(|e| {
+            tracing::error!("Resize failed: {}", e);
+            StatusCode::BAD_GATEWAY
+        })?;
+
+    Ok(Json(outcome))
 }
 
 pub async fn handle_mount_media(
-    State(pool): State<DbPool>,
+    State(state): State<AppState>,
     Json(payload): Json<MediaRequest>,
 ) -> Result<StatusCode, StatusCode> {
-    crate::services::vms::perform_media_action(&pool, &payload.node_id, &payload.vm_id, &payload.iso_path)
+    crate::services::vms::perform_media_action(&state, &payload.node_id, &payload.vm_id, &payload.iso_path)
         .await
         .map_err(|e| {
             tracing::error!("Media action failed: {}", e);
@@ -98,3 +352,17 @@ pub async fn handle_mount_media(
 
     Ok(StatusCode::OK)
 }
+
+pub async fn handle_eject_media(
+    State(state): State<AppState>,
+    Json(payload): Json<EjectMediaRequest>,
+) -> Result<StatusCode, StatusCode> {
+    crate::services::vms::perform_media_eject(&state, &payload.node_id, &payload.vm_id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Media eject failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(StatusCode::OK)
+}
@@ -1,13 +1,27 @@
 use axum::{
-    extract::{ws::WebSocketUpgrade, Path, State},
+    extract::{ws::WebSocketUpgrade, ConnectInfo, Path, State},
     response::{Response, IntoResponse},
     http::{StatusCode, header},
+    Json,
 };
+use std::net::SocketAddr;
+use std::time::Duration;
 use crate::db::DbPool;
+use crate::clients::registry::ClientRegistry;
+use crate::clients::TerminalMode;
+use crate::services::terminal::proxy_terminal;
 use crate::services::vnc::proxy_vnc;
-use jsonwebtoken::{decode, DecodingKey, Validation};
-use crate::controllers::auth::Claims;
-use crate::models::user::User;
+use crate::services::vnc_recording::{play, recordings_dir, Recording};
+use crate::services::vnc_sessions::{InjectError, LimitExceeded, SessionOwner, VncSessionInfo, VncSessions};
+use crate::services::console_input::{qemu_keys, send_combo, type_text, untypeable};
+use crate::models::console_input::{KeyCombo, SendKeysRequest, TypeTextRequest};
+use crate::services::console_shares::verify_share_token;
+use crate::models::console_share::ConsoleShareLink;
+use crate::models::recording::{ListRecordingsQuery, VncRecording};
+use crate::services::telemetry::Telemetry;
+use crate::middleware::auth::WsUser;
+use crate::models::console_permission::ConsoleAccess;
+use crate::models::user::{User, UserRole};
 
 use axum::extract::Query;
 use serde::Serialize;
@@ -20,42 +34,30 @@ pub struct VncTicketResponse {
 
 #[derive(serde::Deserialize)]
 pub struct VncQuery {
-    pub ticket: Option<String>,
-    pub port: Option<u64>,
-    pub token: Option<String>,  // JWT token for auth
+    pub share: Option<String>,  // Console share link token, instead of a dashboard login
+    #[serde(default)]
+    pub view_only: bool,  // Watch without input even when the user may control the VM
 }
 
+/// Incus serves the graphical console of VMs as SPICE, which the noVNC viewer cannot display
+const INCUS_VGA_UNSUPPORTED: &str = "Incus VMs only offer a SPICE console, which the VNC viewer cannot display; use the text console instead";
+
 pub async fn get_vnc_ticket_handler(
     Path((node_id, vm_id)): Path<(String, String)>,
-    State(pool): State<DbPool>,
+    State(clients): State<ClientRegistry>,
 ) -> Response {
     let node_uuid = match uuid::Uuid::parse_str(&node_id) {
         Ok(u) => u,
         Err(_) => return Response::builder().status(400).body("Invalid node ID".into()).unwrap(),
     };
 
-    let node = match sqlx::query_as::<_, crate::models::node::Node>(
-        "SELECT id, name, node_type, api_url, api_key, api_secret, status, last_check, created_at FROM nodes WHERE id = $1"
-    )
-    .bind(node_uuid)
-    .fetch_one(&pool)
-    .await {
-        Ok(n) => n,
+    let client = match clients.get(node_uuid).await {
+        Ok(c) => c,
         Err(_) => return Response::builder().status(404).body("Node not found".into()).unwrap(),
     };
-
-    let client: Box<dyn crate::clients::NodeClient + Send + Sync> = match node.node_type {
-        crate::models::node::NodeType::Proxmox => Box::new(crate::clients::proxmox::ProxmoxClient::new(
-            node.api_url,
-            node.api_key,
-            node.api_secret.unwrap_or_default(),
-        )),
-        crate::models::node::NodeType::Incus => Box::new(crate::clients::incus::IncusClient::new(
-            node.api_url,
-            node.api_key,
-            node.api_secret,
-        )),
-    };
+    if client.as_proxmox().is_none() {
+        return (StatusCode::UNPROCESSABLE_ENTITY, INCUS_VGA_UNSUPPORTED).into_response();
+    }
 
     let vm_id_path = match urlencoding::decode(&vm_id) {
         Ok(decoded) => decoded.into_owned(),
@@ -70,132 +72,179 @@ pub async fn get_vnc_ticket_handler(
     }
 }
 
+/// Resolve a console share token to its link, which must be live and issued for this VM
+async fn validate_share_link(
+    pool: &DbPool,
+    token: &str,
+    node_id: uuid::Uuid,
+    vm_id: &str,
+) -> Result<ConsoleShareLink, Response> {
+    let Some(link_id) = verify_share_token(token) else {
+        tracing::warn!("❌ Console share token with invalid signature");
+        return Err((StatusCode::UNAUTHORIZED, "Invalid share link").into_response());
+    };
+
+    let link = sqlx::query_as::<_, ConsoleShareLink>(
+        r#"
+        SELECT id, node_id, vm_id, label, view_only, expires_at, revoked_at, created_by, created_at
+        FROM console_share_links
+        WHERE id = $1 AND revoked_at IS NULL AND expires_at > NOW()
+        "#
+    )
+    .bind(link_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Database error during share link lookup: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    })?
+    .ok_or_else(|| (StatusCode::UNAUTHORIZED, "Share link expired or revoked").into_response())?;
+
+    if link.node_id != node_id || link.vm_id != vm_id {
+        tracing::warn!("❌ Share link {} used for another VM ({})", link.id, vm_id);
+        return Err(StatusCode::FORBIDDEN.into_response());
+    }
+
+    Ok(link)
+}
+
+/// A user's console access to one VM: admins control every VM, other users need a grant
+async fn console_access(pool: &DbPool, user: &User, node_id: uuid::Uuid, vm_id: &str) -> Result<ConsoleAccess, Response> {
+    if user.role == UserRole::Admin {
+        return Ok(ConsoleAccess::Control);
+    }
+
+    sqlx::query_scalar::<_, ConsoleAccess>(
+        "SELECT access FROM vm_console_permissions WHERE user_id = $1 AND node_id = $2 AND vm_id = $3"
+    )
+    .bind(user.id)
+    .bind(node_id)
+    .bind(vm_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Database error during console permission lookup: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR.into_response()
+    })?
+    .ok_or_else(|| {
+        tracing::warn!("❌ {} has no console access to {}", user.username, vm_id);
+        StatusCode::FORBIDDEN.into_response()
+    })
+}
+
+/// Browser address for the session registry; the forwarded headers are set by the reverse proxy
+fn client_ip(headers: &axum::http::HeaderMap, peer: SocketAddr) -> String {
+    headers.get("x-forwarded-for")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.split(',').next())
+        .or_else(|| headers.get("x-real-ip").and_then(|h| h.to_str().ok()))
+        .map(|ip| ip.trim().to_string())
+        .unwrap_or_else(|| peer.ip().to_string())
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn vnc_handler(
     ws: WebSocketUpgrade,
     Path((node_id, vm_id)): Path<(String, String)>,
     State(pool): State<DbPool>,
+    State(clients): State<ClientRegistry>,
+    State(telemetry): State<Telemetry>,
+    State(sessions): State<VncSessions>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
     Query(query): Query<VncQuery>,
     headers: axum::http::HeaderMap,
+    user: Result<WsUser, Response>,
 ) -> Response {
-    // Authenticate: check JWT token from query param, Authorization header or cookie
-    let mut token_opt = query.token.as_deref().map(|s| s.to_string()).or_else(|| {
-        headers.get(header::AUTHORIZATION)
-            .and_then(|h| h.to_str().ok())
-            .and_then(|h| h.strip_prefix("Bearer "))
-            .map(|s| s.to_string())
-    });
-
-    if token_opt.is_none() {
-        if let Some(cookie_header) = headers.get(header::COOKIE).and_then(|h| h.to_str().ok()) {
-            for part in cookie_header.split(';').map(|s| s.trim()) {
-                if part.starts_with("access_token=") {
-                    token_opt = Some(part["access_token=".len()..].to_string());
-                    break;
-                }
-            }
+    let node_uuid = match uuid::Uuid::parse_str(&node_id) {
+        Ok(u) => u,
+        Err(_) => {
+            tracing::error!("Invalid node ID: {}", node_id);
+            return (StatusCode::BAD_REQUEST, "Invalid node ID").into_response();
         }
-    }
+    };
+
+    // Decode vm_id (percent-encoded) into path format
+    let vm_id_path = match urlencoding::decode(&vm_id) {
+        Ok(decoded) => decoded.into_owned(),
+        Err(_) => vm_id.clone(),
+    };
 
-    let token = match token_opt.as_deref() {
-        Some(t) => t,
+    // A share link stands in for a dashboard login, but only for the VM it was issued for
+    let (owner, view_only, expires_at) = match query.share.as_deref() {
+        Some(share) => match validate_share_link(&pool, share, node_uuid, &vm_id_path).await {
+            Ok(link) => (SessionOwner::share_link(link.id, link.label.as_deref()), link.view_only, Some(link.expires_at)),
+            Err(response) => return response,
+        },
         None => {
-            tracing::warn!("❌ VNC request without authentication");
-            return (StatusCode::UNAUTHORIZED, "Authentication required").into_response();
+            let user = match user {
+                Ok(WsUser(user)) => user,
+                Err(response) => return response,
+            };
+            // Users with view access always get the read-only proxy; others may opt into it
+            let view_only = match console_access(&pool, &user, node_uuid, &vm_id_path).await {
+                Ok(access) => access == ConsoleAccess::View || query.view_only,
+                Err(response) => return response,
+            };
+            (SessionOwner::user(&user), view_only, None)
         }
     };
+    let owner_name = owner.name.clone();
 
-    // Verify JWT
-    let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "placeholder_secret".to_string());
-    let token_data = match decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(secret.as_ref()),
-        &Validation::default(),
-    ) {
-        Ok(data) => data,
-        Err(e) => {
-            tracing::warn!("❌ VNC authentication failed: {}", e);
-            return (StatusCode::UNAUTHORIZED, "Invalid token").into_response();
+    // Refused before upgrading so the browser gets the reason
+    match clients.get(node_uuid).await {
+        Ok(client) if client.as_proxmox().is_none() => {
+            return (StatusCode::UNPROCESSABLE_ENTITY, INCUS_VGA_UNSUPPORTED).into_response();
         }
-    };
+        Ok(_) => {}
+        Err(_) => return (StatusCode::NOT_FOUND, "Node not found").into_response(),
+    }
 
-    // Verify user still exists (mirrors HTTP middleware behavior)
-    if let Err(e) = sqlx::query_as::<_, User>(
-        "SELECT id, username, email, password_hash, role, created_at FROM users WHERE username = $1"
-    )
-    .bind(&token_data.claims.sub)
-    .fetch_optional(&pool)
-    .await
-    .map_err(|err| {
-        tracing::error!("Database error during VNC auth lookup: {}", err);
-        err
-    })
-    .and_then(|user_opt| {
-        user_opt.ok_or_else(|| {
-            tracing::warn!("❌ VNC auth user not found: {}", token_data.claims.sub);
-            sqlx::Error::RowNotFound
-        })
-    })
-    {
-        let _ = e;
-        return (StatusCode::UNAUTHORIZED, "User not found").into_response();
+    // Reserve the session before upgrading so limits are reported as a plain HTTP error
+    let mut session = match sessions.open(owner, node_uuid, &vm_id_path, Some(client_ip(&headers, peer)), view_only) {
+        Ok(session) => session,
+        Err(limit) => {
+            tracing::warn!("🚫 VNC session refused for {} on {}: {:?} limit reached", owner_name, vm_id_path, limit);
+            let message = match limit {
+                LimitExceeded::User => "Too many open console sessions for this user",
+                LimitExceeded::Vm => "Too many open console sessions for this VM",
+            };
+            return (StatusCode::TOO_MANY_REQUESTS, message).into_response();
+        }
+    };
+    if let Some(expires_at) = expires_at {
+        session.end_by(expires_at);
     }
-    
+
     ws.on_upgrade(move |socket| async move {
-        let node_uuid = match uuid::Uuid::parse_str(&node_id) {
-            Ok(u) => u,
-            Err(_) => {
-                tracing::error!("Invalid node ID: {}", node_id);
-                return;
+        // Once recording is enabled it is mandatory: a session that cannot be recorded is not started
+        if let Some(dir) = recordings_dir() {
+            match Recording::start(&pool, &dir, &session.info()).await {
+                Ok(recording) => session.set_recording(recording),
+                Err(e) => {
+                    tracing::error!("Failed to start VNC recording for {}: {}", vm_id_path, e);
+                    return;
+                }
             }
-        };
+        }
 
-        // 1. Get Node from DB
-        let node_result = sqlx::query_as::<_, crate::models::node::Node>(
-            "SELECT id, name, node_type, api_url, api_key, api_secret, status, last_check, created_at FROM nodes WHERE id = $1"
-        )
-        .bind(node_uuid)
-        .fetch_one(&pool)
-        .await;
-
-        match node_result {
-            Ok(node) => {
-                // Note: auth_header is not used for VNC - we use the ticket in the cookie instead
-                let _auth_header: Option<String> = None;
-
-                // 2. Initialize NodeClient
-                let client: Box<dyn crate::clients::NodeClient + Send + Sync> = match node.node_type {
-                    crate::models::node::NodeType::Proxmox => Box::new(crate::clients::proxmox::ProxmoxClient::new(
-                        node.api_url.clone(),
-                        node.api_key.clone(),
-                        node.api_secret.clone().unwrap_or_default(),
-                    )),
-                    crate::models::node::NodeType::Incus => Box::new(crate::clients::incus::IncusClient::new(
-                        node.api_url.clone(),
-                        node.api_key.clone(),
-                        node.api_secret.clone(),
-                    )),
-                };
-
-                // 3. Decode vm_id (percent-encoded) into path format
-                let vm_id_path = match urlencoding::decode(&vm_id) {
-                    Ok(decoded) => decoded.into_owned(),
-                    Err(_) => vm_id.clone(),
-                };
-
-                // 4. Get VNC Info (always fetch from Proxmox to ensure fresh ticket)
+        // 1. Get the cached client for this node
+        match clients.get(node_uuid).await {
+            Ok(client) => {
+                // 2. Get VNC Info (always fetch from Proxmox to ensure fresh ticket)
                 let vnc_info = client.get_vnc_info(&vm_id_path).await;
 
                 match vnc_info {
                     Ok(info) => {
                         // Proxmox VNC WebSocket: ticket authentication via query parameter only
-                        tracing::info!("Initiating VNC connection for VM {}", vm_id_path);
+                        tracing::info!("Initiating VNC session {} for VM {} ({})", session.id(), vm_id_path, owner_name);
 
                         // Use the client's Origin header (if present) for the Proxmox websocket handshake
                         let origin_header = headers.get(header::ORIGIN)
                             .and_then(|h| h.to_str().ok())
                             .map(|s| s.to_string());
 
-                        if let Err(e) = proxy_vnc(info.url, socket, None, origin_header).await {
+                        let _gauge = telemetry.vnc_session_started();
+                        if let Err(e) = proxy_vnc(info.url, socket, None, origin_header, client.tls_config(), &session).await {
                             tracing::error!("VNC proxy failed for {}: {}", vm_id_path, e);
                         } else {
                             tracing::info!("VNC session completed for {}", vm_id_path);
@@ -212,3 +261,242 @@ pub async fn vnc_handler(
         }
     })
 }
+
+/// Live console sessions with their owner, client address and traffic
+pub async fn list_vnc_sessions(
+    State(sessions): State<VncSessions>,
+) -> Json<Vec<VncSessionInfo>> {
+    Json(sessions.list())
+}
+
+/// Forcibly close a console session
+pub async fn terminate_vnc_session(
+    State(sessions): State<VncSessions>,
+    Path(id): Path<uuid::Uuid>,
+) -> StatusCode {
+    if sessions.terminate(id) {
+        tracing::info!("🛑 VNC session {} terminated by admin", id);
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+const MAX_TYPED_CHARS: usize = 4096;
+const DEFAULT_TYPING_DELAY_MS: u64 = 20;
+const MAX_TYPING_DELAY_MS: u64 = 1000;
+
+fn inject_error_status(session_id: uuid::Uuid, e: InjectError) -> StatusCode {
+    tracing::warn!("⌨️ Could not send keys to VNC session {}: {:?}", session_id, e);
+    match e {
+        InjectError::NotFound => StatusCode::NOT_FOUND,
+        InjectError::ViewOnly => StatusCode::FORBIDDEN,
+        InjectError::NotReady => StatusCode::CONFLICT,
+    }
+}
+
+fn valid_sysrq_key(payload: &SendKeysRequest) -> bool {
+    match payload.sysrq_key {
+        Some(key) => payload.combo == KeyCombo::SysRq && key.is_ascii_alphanumeric(),
+        None => true,
+    }
+}
+
+/// Type text into a live console session as US-layout key presses, e.g. to paste a password
+pub async fn type_into_vnc_session(
+    State(sessions): State<VncSessions>,
+    Path(id): Path<uuid::Uuid>,
+    Json(payload): Json<TypeTextRequest>,
+) -> Result<StatusCode, StatusCode> {
+    if payload.text.is_empty() || payload.text.chars().count() > MAX_TYPED_CHARS {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if let Some(c) = untypeable(&payload.text) {
+        tracing::warn!("⌨️ Cannot type {:?} on a US keyboard layout", c);
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+    let delay_ms = payload.delay_ms.unwrap_or(DEFAULT_TYPING_DELAY_MS);
+    if delay_ms > MAX_TYPING_DELAY_MS {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    type_text(&sessions, id, &payload.text, Duration::from_millis(delay_ms))
+        .await
+        .map_err(|e| inject_error_status(id, e))?;
+
+    tracing::info!("⌨️ Typed {} characters into VNC session {}", payload.text.chars().count(), id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Press a key combination in a live console session
+pub async fn send_keys_to_vnc_session(
+    State(sessions): State<VncSessions>,
+    Path(id): Path<uuid::Uuid>,
+    Json(payload): Json<SendKeysRequest>,
+) -> Result<StatusCode, StatusCode> {
+    if !valid_sysrq_key(&payload) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    send_combo(&sessions, id, payload.combo, payload.sysrq_key)
+        .await
+        .map_err(|e| inject_error_status(id, e))?;
+
+    tracing::info!("⌨️ Sent {:?} to VNC session {}", payload.combo, id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Press a key combination on a Proxmox QEMU guest without a console session open
+pub async fn send_vm_keys(
+    State(clients): State<ClientRegistry>,
+    Path((node_id, vm_id)): Path<(uuid::Uuid, String)>,
+    Json(payload): Json<SendKeysRequest>,
+) -> Result<StatusCode, StatusCode> {
+    if !valid_sysrq_key(&payload) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let client = clients.get(node_id).await.map_err(|_| StatusCode::NOT_FOUND)?;
+    // Incus has no equivalent; keys can still be sent through a console session
+    let proxmox = client.as_proxmox().ok_or(StatusCode::UNPROCESSABLE_ENTITY)?;
+
+    let vm_id_path = urlencoding::decode(&vm_id).map(|v| v.into_owned()).unwrap_or(vm_id);
+    let keys = qemu_keys(payload.combo, payload.sysrq_key);
+    proxmox.send_key(&vm_id_path, &keys).await.map_err(|e| {
+        tracing::error!("Failed to send {} to {}: {}", keys, vm_id_path, e);
+        StatusCode::BAD_GATEWAY
+    })?;
+
+    tracing::info!("⌨️ Sent {} to {}", keys, vm_id_path);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Recordings filtered by VM and/or user, newest first
+pub async fn list_vnc_recordings(
+    State(pool): State<DbPool>,
+    Query(query): Query<ListRecordingsQuery>,
+) -> Result<Json<Vec<VncRecording>>, StatusCode> {
+    let recordings = sqlx::query_as::<_, VncRecording>(
+        r#"
+        SELECT id, session_id, user_id, username, node_id, vm_id, client_ip, started_at, ended_at, duration_ms, size_bytes
+        FROM vnc_recordings
+        WHERE ($1::uuid IS NULL OR node_id = $1)
+          AND ($2::text IS NULL OR vm_id = $2)
+          AND ($3::uuid IS NULL OR user_id = $3)
+        ORDER BY started_at DESC
+        LIMIT $4
+        "#
+    )
+    .bind(query.node_id)
+    .bind(query.vm_id)
+    .bind(query.user_id)
+    .bind(query.limit.unwrap_or(50).clamp(1, 500))
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Database error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(recordings))
+}
+
+/// Replay a recording over WebSocket at its original speed for noVNC to render
+pub async fn play_vnc_recording(
+    ws: WebSocketUpgrade,
+    Path(id): Path<uuid::Uuid>,
+    State(pool): State<DbPool>,
+    WsUser(user): WsUser,
+) -> Response {
+    if user.role != UserRole::Admin {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    let Some(dir) = recordings_dir() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let file_name = match sqlx::query_scalar::<_, String>("SELECT file_name FROM vnc_recordings WHERE id = $1")
+        .bind(id)
+        .fetch_optional(&pool)
+        .await
+    {
+        Ok(Some(file_name)) => file_name,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            tracing::error!("Database error: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    ws.on_upgrade(move |socket| async move {
+        tracing::info!("▶️ {} is replaying VNC recording {}", user.username, id);
+        if let Err(e) = play(&dir.join(&file_name), socket).await {
+            tracing::error!("VNC recording playback failed for {}: {}", id, e);
+        }
+    })
+}
+
+#[derive(serde::Deserialize)]
+pub struct TerminalQuery {
+    #[serde(default)]
+    pub mode: TerminalMode,
+    pub cols: Option<u16>,
+    pub rows: Option<u16>,
+}
+
+/// Interactive text console (serial console, container console or Incus shell) over WebSocket
+pub async fn terminal_handler(
+    ws: WebSocketUpgrade,
+    Path((node_id, vm_id)): Path<(String, String)>,
+    State(pool): State<DbPool>,
+    State(clients): State<ClientRegistry>,
+    Query(query): Query<TerminalQuery>,
+    headers: axum::http::HeaderMap,
+    WsUser(user): WsUser,
+) -> Response {
+    let node_uuid = match uuid::Uuid::parse_str(&node_id) {
+        Ok(u) => u,
+        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid node ID").into_response(),
+    };
+    let vm_id_path = match urlencoding::decode(&vm_id) {
+        Ok(decoded) => decoded.into_owned(),
+        Err(_) => vm_id.clone(),
+    };
+
+    // A text console has no read-only mode, so it needs control access
+    match console_access(&pool, &user, node_uuid, &vm_id_path).await {
+        Ok(ConsoleAccess::Control) => {}
+        Ok(ConsoleAccess::View) => return (StatusCode::FORBIDDEN, "View-only console access").into_response(),
+        Err(response) => return response,
+    }
+    let size = (query.cols.unwrap_or(80).max(1), query.rows.unwrap_or(24).max(1));
+    let origin_header = headers.get(header::ORIGIN)
+        .and_then(|h| h.to_str().ok())
+        .map(|s| s.to_string());
+
+    ws.on_upgrade(move |socket| async move {
+        let client = match clients.get(node_uuid).await {
+            Ok(client) => client,
+            Err(e) => {
+                tracing::error!("Failed to find node {}: {}", node_id, e);
+                return;
+            }
+        };
+
+        // Always request a fresh session; termproxy tickets and Incus operation secrets are single-use
+        let target = match client.open_terminal(&vm_id_path, query.mode, size.0, size.1).await {
+            Ok(target) => target,
+            Err(e) => {
+                tracing::error!("Failed to open terminal for {}: {}", vm_id_path, e);
+                return;
+            }
+        };
+
+        tracing::info!("⌨️ Initiating {:?} terminal for VM {}", query.mode, vm_id_path);
+        if let Err(e) = proxy_terminal(target, socket, origin_header, client.tls_config(), size).await {
+            tracing::error!("Terminal proxy failed for {}: {}", vm_id_path, e);
+        } else {
+            tracing::info!("Terminal session completed for {}", vm_id_path);
+        }
+    })
+}
@@ -0,0 +1,222 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::db::DbPool;
+use crate::models::webhook::{CreateWebhookRequest, CreatedWebhook, UpdateWebhookRequest, Webhook, WebhookDelivery};
+use crate::services::webhooks::{generate_secret, valid_event_filter, WebhookDispatcher};
+
+fn validate_url(url: &str) -> Result<(), StatusCode> {
+    match reqwest::Url::parse(url) {
+        Ok(u) if matches!(u.scheme(), "http" | "https") => Ok(()),
+        _ => Err(StatusCode::BAD_REQUEST),
+    }
+}
+
+fn validate_events(events: &[String]) -> Result<(), StatusCode> {
+    if events.iter().all(|e| valid_event_filter(e)) {
+        Ok(())
+    } else {
+        Err(StatusCode::BAD_REQUEST)
+    }
+}
+
+pub async fn list_webhooks(
+    State(pool): State<DbPool>,
+) -> Result<Json<Vec<Webhook>>, StatusCode> {
+    let webhooks = sqlx::query_as::<_, Webhook>(
+        "SELECT id, name, url, events, enabled, created_at FROM webhooks ORDER BY created_at DESC"
+    )
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Database error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(webhooks))
+}
+
+pub async fn create_webhook(
+    State(pool): State<DbPool>,
+    Json(payload): Json<CreateWebhookRequest>,
+) -> Result<(StatusCode, Json<CreatedWebhook>), StatusCode> {
+    validate_url(&payload.url)?;
+    validate_events(&payload.events)?;
+    if payload.name.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let secret = payload.secret.filter(|s| !s.is_empty()).unwrap_or_else(generate_secret);
+    let webhook = sqlx::query_as::<_, Webhook>(
+        r#"
+        INSERT INTO webhooks (name, url, secret, events)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id, name, url, events, enabled, created_at
+        "#
+    )
+    .bind(payload.name.trim())
+    .bind(&payload.url)
+    .bind(&secret)
+    .bind(&payload.events)
+    .fetch_one(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Database error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    tracing::info!("🪝 Webhook created: {} -> {}", webhook.name, webhook.url);
+    Ok((StatusCode::CREATED, Json(CreatedWebhook { webhook, secret })))
+}
+
+pub async fn get_webhook(
+    State(pool): State<DbPool>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Webhook>, StatusCode> {
+    sqlx::query_as::<_, Webhook>(
+        "SELECT id, name, url, events, enabled, created_at FROM webhooks WHERE id = $1"
+    )
+    .bind(id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Database error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .map(Json)
+    .ok_or(StatusCode::NOT_FOUND)
+}
+
+pub async fn update_webhook(
+    State(pool): State<DbPool>,
+    Path(id): Path<Uuid>,
+    Json(payload): Json<UpdateWebhookRequest>,
+) -> Result<Json<Webhook>, StatusCode> {
+    if let Some(url) = &payload.url {
+        validate_url(url)?;
+    }
+    if let Some(events) = &payload.events {
+        validate_events(events)?;
+    }
+
+    sqlx::query_as::<_, Webhook>(
+        r#"
+        UPDATE webhooks
+        SET name = COALESCE($1, name), url = COALESCE($2, url), secret = COALESCE($3, secret),
+            events = COALESCE($4, events), enabled = COALESCE($5, enabled)
+        WHERE id = $6
+        RETURNING id, name, url, events, enabled, created_at
+        "#
+    )
+    .bind(payload.name)
+    .bind(payload.url)
+    .bind(payload.secret.filter(|s| !s.is_empty()))
+    .bind(payload.events)
+    .bind(payload.enabled)
+    .bind(id)
+    .fetch_optional(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Database error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .map(Json)
+    .ok_or(StatusCode::NOT_FOUND)
+}
+
+pub async fn delete_webhook(
+    State(pool): State<DbPool>,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    let result = sqlx::query("DELETE FROM webhooks WHERE id = $1")
+        .bind(id)
+        .execute(&pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Database error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    if result.rows_affected() == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+pub struct ListDeliveriesQuery {
+    pub limit: Option<i64>,
+}
+
+/// Delivery log for one subscription, newest first
+pub async fn list_webhook_deliveries(
+    State(pool): State<DbPool>,
+    Path(id): Path<Uuid>,
+    Query(query): Query<ListDeliveriesQuery>,
+) -> Result<Json<Vec<WebhookDelivery>>, StatusCode> {
+    let deliveries = sqlx::query_as::<_, WebhookDelivery>(
+        r#"
+        SELECT id, webhook_id, event_id, event_type, payload, status, attempts, next_attempt_at, last_status_code, last_error, created_at, delivered_at
+        FROM webhook_deliveries
+        WHERE webhook_id = $1
+        ORDER BY created_at DESC
+        LIMIT $2
+        "#
+    )
+    .bind(id)
+    .bind(query.limit.unwrap_or(50).clamp(1, 500))
+    .fetch_all(&pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Database error: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(deliveries))
+}
+
+#[derive(Serialize)]
+pub struct TestEventResponse {
+    pub delivery_id: Uuid,
+}
+
+/// Queue a `webhook.test` event for this subscription, ignoring its event filter
+pub async fn send_test_event(
+    State(pool): State<DbPool>,
+    State(dispatcher): State<WebhookDispatcher>,
+    Path(id): Path<Uuid>,
+) -> Result<(StatusCode, Json<TestEventResponse>), StatusCode> {
+    let exists = sqlx::query_scalar::<_, bool>("SELECT EXISTS(SELECT 1 FROM webhooks WHERE id = $1)")
+        .bind(id)
+        .fetch_one(&pool)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !exists {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let delivery_id = dispatcher.emit_test(id).await.map_err(|e| {
+        tracing::error!("Failed to queue test event: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok((StatusCode::ACCEPTED, Json(TestEventResponse { delivery_id })))
+}
+
+pub async fn redeliver_webhook_delivery(
+    State(dispatcher): State<WebhookDispatcher>,
+    Path((webhook_id, delivery_id)): Path<(Uuid, Uuid)>,
+) -> Result<(StatusCode, Json<WebhookDelivery>), StatusCode> {
+    let delivery = dispatcher.redeliver(webhook_id, delivery_id).await
+        .map_err(|e| {
+            tracing::error!("Failed to re-queue delivery: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok((StatusCode::ACCEPTED, Json(delivery)))
+}
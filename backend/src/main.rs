@@ -5,6 +5,7 @@ mod routes;
 mod services;
 mod controllers;
 mod middleware;
+mod state;
 // mod config;
 
 use std::net::SocketAddr;
@@ -34,11 +35,22 @@ async fn main() {
         .expect("Failed to run database migrations");
 
     // Build our application with a single route
-    let app = routes::create_router(pool);
+    let state = state::AppState::new(pool);
+
+    // Background metrics poller shared by all metrics WebSockets
+    tokio::spawn(state.metrics.clone().run(state.pool.clone(), state.clients.clone()));
+    // Alert rule evaluation and notifications
+    tokio::spawn(state.alerts.clone().run(state.pool.clone(), state.clients.clone(), state.metrics.clone()));
+    // Outbound webhook delivery queue
+    tokio::spawn(state.webhooks.clone().run());
+    // Inventory change feed for the VM events stream
+    tokio::spawn(state.inventory.clone().run(state.clone()));
+
+    let app = routes::create_router(state);
 
     // Run it
     let addr = SocketAddr::from(([0, 0, 0, 0], 3001));
     tracing::debug!("listening on {}", addr);
     let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
 }
@@ -1,95 +1,177 @@
 use axum::{
-    extract::{Request, State},
-    http::{StatusCode, header},
+    async_trait,
+    extract::{FromRef, FromRequestParts, Query, Request, State},
+    http::{request::Parts, HeaderMap, StatusCode, header},
     middleware::Next,
     response::{IntoResponse, Response},
 };
 use jsonwebtoken::{decode, DecodingKey, Validation};
+use serde::Deserialize;
 use crate::controllers::auth::Claims;
 use crate::db::DbPool;
 use crate::models::user::{User, UserRole};
-
-#[derive(Clone)]
-pub struct AuthUser {
-    pub id: uuid::Uuid,
-    pub username: String,
-    pub email: String,
-    pub role: UserRole,
-}
+use crate::services::ws_tickets::WsTickets;
 
 // Extension key for accessing authenticated user in handlers
 #[derive(Clone)]
-pub struct AuthUserExtension(pub AuthUser);
+pub struct AuthUserExtension(pub User);
 
-/// Middleware to verify JWT token and attach user info to request
-pub async fn auth_middleware(
-    State(pool): State<DbPool>,
-    mut req: Request,
-    next: Next,
-) -> Result<Response, StatusCode> {
-    // Try Authorization header first
-    let mut token_opt: Option<String> = req
-        .headers()
+/// JWT from the `Authorization: Bearer` header
+fn bearer_token(headers: &HeaderMap) -> Option<String> {
+    headers
         .get(header::AUTHORIZATION)
         .and_then(|h| h.to_str().ok())
         .and_then(|h| h.strip_prefix("Bearer "))
-        .map(|s| s.to_string());
-
-    // If not present, try access_token cookie
-    if token_opt.is_none() {
-        if let Some(cookie_header) = req.headers().get(header::COOKIE).and_then(|h| h.to_str().ok()) {
-            for part in cookie_header.split(';').map(|s| s.trim()) {
-                if part.starts_with("access_token=") {
-                    token_opt = Some(part["access_token=".len()..].to_string());
-                    break;
-                }
-            }
-        }
-    }
+        .map(|s| s.to_string())
+}
+
+/// Origins the dashboard is served from, per CORS_ALLOWED_ORIGINS
+pub fn allowed_origins() -> Vec<String> {
+    std::env::var("CORS_ALLOWED_ORIGINS")
+        .unwrap_or_else(|_| "http://localhost:3000".into())
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .collect()
+}
 
-    let token = token_opt.ok_or(StatusCode::UNAUTHORIZED)?;
+/// JWT from the HttpOnly `access_token` cookie
+fn cookie_token(headers: &HeaderMap) -> Option<String> {
+    let cookie_header = headers.get(header::COOKIE).and_then(|h| h.to_str().ok())?;
+    cookie_header
+        .split(';')
+        .map(|s| s.trim())
+        .find_map(|part| part.strip_prefix("access_token="))
+        .map(|s| s.to_string())
+}
 
+/// Verify an access token and load the user it was issued to
+async fn user_from_jwt(pool: &DbPool, token: &str) -> Result<User, StatusCode> {
     let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "placeholder_secret".to_string());
-    
+
     let token_data = decode::<Claims>(
-        &token,
+        token,
         &DecodingKey::from_secret(secret.as_ref()),
         &Validation::default(),
-    ).map_err(|_| StatusCode::UNAUTHORIZED)?;
+    ).map_err(|e| {
+        tracing::warn!("❌ Authentication failed: {}", e);
+        StatusCode::UNAUTHORIZED
+    })?;
 
-    // Fetch user from database
-    let user = sqlx::query_as::<_, User>(
+    sqlx::query_as::<_, User>(
         "SELECT id, username, email, password_hash, role, created_at FROM users WHERE username = $1"
     )
     .bind(&token_data.claims.sub)
-    .fetch_optional(&pool)
+    .fetch_optional(pool)
     .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-    .ok_or(StatusCode::UNAUTHORIZED)?;
+    .map_err(|e| {
+        tracing::error!("Database error during auth lookup: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?
+    .ok_or(StatusCode::UNAUTHORIZED)
+}
+
+/// Middleware to verify JWT token and attach user info to request
+pub async fn auth_middleware(
+    State(pool): State<DbPool>,
+    mut req: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    // Try Authorization header first, then the access_token cookie
+    let token = bearer_token(req.headers())
+        .or_else(|| cookie_token(req.headers()))
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    let user = user_from_jwt(&pool, &token).await?;
 
     // Only allow admin users
     if user.role != UserRole::Admin {
         return Err(StatusCode::FORBIDDEN);
     }
 
-    // Create AuthUser and insert into request extensions
-    let auth_user = AuthUser {
-        id: user.id,
-        username: user.username,
-        email: user.email,
-        role: user.role,
-    };
-
-    req.extensions_mut().insert(AuthUserExtension(auth_user));
+    req.extensions_mut().insert(AuthUserExtension(user));
 
     Ok(next.run(req).await)
 }
 
-/// Response for unauthorized/forbidden access
-pub struct AuthError;
+/// Any signed-in user, from the Authorization header or access_token cookie
+pub struct CurrentUser(pub User);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for CurrentUser
+where
+    DbPool: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let token = bearer_token(&parts.headers)
+            .or_else(|| cookie_token(&parts.headers))
+            .ok_or(StatusCode::UNAUTHORIZED)?;
+        let user = user_from_jwt(&DbPool::from_ref(state), &token).await?;
+        Ok(Self(user))
+    }
+}
+
+#[derive(Deserialize)]
+struct WsTicketQuery {
+    ws_ticket: Option<String>,
+}
+
+/// The user behind a WebSocket upgrade. Browsers cannot set headers on WebSockets, so this
+/// takes a one-time `ws_ticket` issued for this route, or the access_token cookie. JWTs in
+/// the query string are not accepted since they end up in proxy and access logs. CORS does
+/// not apply to WebSockets, so cookie logins are only honoured from an allowed Origin.
+pub struct WsUser(pub User);
+
+#[async_trait]
+impl<S> FromRequestParts<S> for WsUser
+where
+    DbPool: FromRef<S>,
+    WsTickets: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let pool = DbPool::from_ref(state);
+        let ticket = Query::<WsTicketQuery>::try_from_uri(&parts.uri).ok().and_then(|q| q.0.ws_ticket);
+
+        let user = match ticket {
+            Some(ticket) => {
+                let Some(user_id) = WsTickets::from_ref(state).redeem(&ticket, parts.uri.path()) else {
+                    tracing::warn!("❌ WebSocket ticket invalid, expired or issued for another route: {}", parts.uri.path());
+                    return Err((StatusCode::UNAUTHORIZED, "Invalid ticket").into_response());
+                };
+                sqlx::query_as::<_, User>(
+                    "SELECT id, username, email, password_hash, role, created_at FROM users WHERE id = $1"
+                )
+                .bind(user_id)
+                .fetch_optional(&pool)
+                .await
+                .map_err(|e| {
+                    tracing::error!("Database error during WebSocket ticket lookup: {}", e);
+                    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+                })?
+                .ok_or_else(|| (StatusCode::UNAUTHORIZED, "User not found").into_response())?
+            }
+            None => {
+                let Some(token) = cookie_token(&parts.headers) else {
+                    tracing::warn!("❌ WebSocket request without authentication: {}", parts.uri.path());
+                    return Err((StatusCode::UNAUTHORIZED, "Authentication required").into_response());
+                };
+                // The browser attaches the cookie to upgrades started by any site
+                if let Some(origin) = parts.headers.get(header::ORIGIN) {
+                    let origin = origin.to_str().unwrap_or_default();
+                    if !allowed_origins().iter().any(|allowed| allowed == origin) {
+                        tracing::warn!("❌ WebSocket cookie login from disallowed origin {:?}: {}", origin, parts.uri.path());
+                        return Err((StatusCode::FORBIDDEN, "Origin not allowed").into_response());
+                    }
+                }
+                user_from_jwt(&pool, &token).await.map_err(|status| status.into_response())?
+            }
+        };
 
-impl IntoResponse for AuthError {
-    fn into_response(self) -> Response {
-        (StatusCode::UNAUTHORIZED, "Unauthorized").into_response()
+        Ok(Self(user))
     }
 }
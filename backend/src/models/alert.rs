@@ -0,0 +1,145 @@
+use serde::{Deserialize, Serialize};
+use sqlx::Type;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+
+/// What an alert rule watches
+#[derive(Debug, Serialize, Deserialize, Type, PartialEq, Clone, Copy)]
+#[sqlx(type_name = "alert_metric", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum AlertMetric {
+    /// Node CPU usage above `threshold` percent
+    NodeCpu,
+    /// Node memory usage above `threshold` percent
+    NodeMemory,
+    /// Node root filesystem usage above `threshold` percent
+    NodeDisk,
+    /// Node failing its health check
+    NodeOffline,
+    /// Guest that was running stopped without a power action from the dashboard
+    VmStopped,
+}
+
+impl AlertMetric {
+    pub fn uses_threshold(self) -> bool {
+        matches!(self, Self::NodeCpu | Self::NodeMemory | Self::NodeDisk)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Type, PartialEq, Clone, Copy)]
+#[sqlx(type_name = "alert_channel_kind", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum AlertChannelKind {
+    /// JSON POST of the full alert payload; config `{ "url": ... }`
+    Webhook,
+    /// Slack-compatible incoming webhook; config `{ "url": ... }`
+    Slack,
+    /// SMTP email using the server from SMTP_* env vars; config `{ "to": [...] }`
+    Email,
+}
+
+#[derive(Debug, Serialize, Deserialize, Type, PartialEq, Clone, Copy)]
+#[sqlx(type_name = "alert_state", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum AlertState {
+    Firing,
+    Resolved,
+}
+
+#[derive(Debug, Serialize, Clone, sqlx::FromRow)]
+pub struct AlertRule {
+    pub id: Uuid,
+    pub name: String,
+    pub metric: AlertMetric,
+    pub threshold: Option<f64>,
+    pub duration_secs: i32,
+    pub node_id: Option<Uuid>,
+    pub vm_id: Option<String>,
+    pub channel_ids: Vec<Uuid>,
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Body for creating a rule and for replacing one with PUT
+#[derive(Debug, Deserialize)]
+pub struct AlertRuleRequest {
+    pub name: String,
+    pub metric: AlertMetric,
+    pub threshold: Option<f64>,
+    #[serde(default)]
+    pub duration_secs: i32,
+    pub node_id: Option<Uuid>,
+    pub vm_id: Option<String>,
+    #[serde(default)]
+    pub channel_ids: Vec<Uuid>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+#[derive(Debug, Serialize, Clone, sqlx::FromRow)]
+pub struct AlertChannel {
+    pub id: Uuid,
+    pub name: String,
+    pub kind: AlertChannelKind,
+    pub config: serde_json::Value,
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AlertChannelRequest {
+    pub name: String,
+    pub kind: AlertChannelKind,
+    pub config: serde_json::Value,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+/// Suppresses notifications for matching alerts; NULL rule/node matches everything
+#[derive(Debug, Serialize, Clone, sqlx::FromRow)]
+pub struct AlertSilence {
+    pub id: Uuid,
+    pub rule_id: Option<Uuid>,
+    pub node_id: Option<Uuid>,
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    pub reason: Option<String>,
+    pub created_by: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl AlertSilence {
+    pub fn matches(&self, rule_id: Uuid, node_id: Uuid, now: DateTime<Utc>) -> bool {
+        self.starts_at <= now
+            && now < self.ends_at
+            && self.rule_id.is_none_or(|id| id == rule_id)
+            && self.node_id.is_none_or(|id| id == node_id)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateSilenceRequest {
+    pub rule_id: Option<Uuid>,
+    pub node_id: Option<Uuid>,
+    pub starts_at: Option<DateTime<Utc>>,
+    pub ends_at: DateTime<Utc>,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone, sqlx::FromRow)]
+pub struct AlertEvent {
+    pub id: Uuid,
+    pub rule_id: Uuid,
+    pub node_id: Option<Uuid>,
+    pub subject: String,
+    pub state: AlertState,
+    pub value: Option<f64>,
+    pub message: String,
+    pub silenced: bool,
+    pub started_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
@@ -0,0 +1,73 @@
+use serde::{Deserialize, Serialize};
+
+/// Addressing for one NIC; the position in `ip_configs` matches `netN` on Proxmox
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IpConfig {
+    /// `dhcp` or an IPv4 CIDR, e.g. `10.0.0.5/24`
+    pub ip: Option<String>,
+    pub gateway: Option<String>,
+    /// `dhcp`, `auto` (SLAAC) or an IPv6 CIDR
+    pub ip6: Option<String>,
+    pub gateway6: Option<String>,
+}
+
+/// Cloud-init settings of a guest. Proxmox fills the structured fields, Incus the raw YAML.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CloudInitConfig {
+    pub user: Option<String>,
+    /// The password itself is never returned
+    pub password_set: bool,
+    pub ssh_keys: Vec<String>,
+    pub ip_configs: Vec<IpConfig>,
+    pub nameservers: Vec<String>,
+    pub search_domain: Option<String>,
+    pub user_data: Option<String>,
+    pub network_config: Option<String>,
+}
+
+/// Changes to apply; omitted fields are left alone and empty strings or lists clear a setting
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CloudInitUpdate {
+    pub user: Option<String>,
+    pub password: Option<String>,
+    pub ssh_keys: Option<Vec<String>>,
+    /// Replaces every NIC's addressing; NICs past the end of the list are cleared
+    pub ip_configs: Option<Vec<IpConfig>>,
+    pub nameservers: Option<Vec<String>>,
+    pub search_domain: Option<String>,
+    pub user_data: Option<String>,
+    pub network_config: Option<String>,
+}
+
+impl CloudInitUpdate {
+    /// Names of the settings this update touches, for logs and webhooks without the values
+    pub fn changed_fields(&self) -> Vec<&'static str> {
+        [
+            ("user", self.user.is_some()),
+            ("password", self.password.is_some()),
+            ("ssh_keys", self.ssh_keys.is_some()),
+            ("ip_configs", self.ip_configs.is_some()),
+            ("nameservers", self.nameservers.is_some()),
+            ("search_domain", self.search_domain.is_some()),
+            ("user_data", self.user_data.is_some()),
+            ("network_config", self.network_config.is_some()),
+        ]
+        .into_iter()
+        .filter_map(|(name, set)| set.then_some(name))
+        .collect()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateCloudInitRequest {
+    pub node_id: String,
+    pub vm_id: String,
+    #[serde(flatten)]
+    pub changes: CloudInitUpdate,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RegenerateCloudInitRequest {
+    pub node_id: String,
+    pub vm_id: String,
+}
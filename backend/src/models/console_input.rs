@@ -0,0 +1,25 @@
+use serde::Deserialize;
+
+/// Key combinations that are awkward or impossible to press through a browser
+#[derive(Debug, Deserialize, PartialEq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeyCombo {
+    CtrlAltDel,
+    AltF2,
+    /// Magic SysRq; `sysrq_key` picks the command, e.g. `b` to reboot
+    #[serde(rename = "sysrq")]
+    SysRq,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TypeTextRequest {
+    pub text: String,
+    /// Pause between characters; defaults to 20ms
+    pub delay_ms: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SendKeysRequest {
+    pub combo: KeyCombo,
+    pub sysrq_key: Option<char>,
+}
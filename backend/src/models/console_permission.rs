@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+use sqlx::Type;
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+
+/// How much of a VM's console a user may use
+#[derive(Debug, Serialize, Deserialize, Type, PartialEq, Clone, Copy)]
+#[sqlx(type_name = "console_access", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ConsoleAccess {
+    /// Watch only; keyboard, mouse and clipboard input is dropped by the proxy
+    View,
+    /// Full interactive console
+    Control,
+}
+
+#[derive(Debug, Serialize, Clone, sqlx::FromRow)]
+pub struct ConsolePermission {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub username: String,
+    pub node_id: Uuid,
+    pub vm_id: String,
+    pub access: ConsoleAccess,
+    pub granted_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Grant or change a user's access to one VM's console
+#[derive(Debug, Deserialize)]
+pub struct SetConsolePermissionRequest {
+    pub user_id: Uuid,
+    pub node_id: Uuid,
+    pub vm_id: String,
+    pub access: ConsoleAccess,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListConsolePermissionsQuery {
+    pub user_id: Option<Uuid>,
+    pub node_id: Option<Uuid>,
+    pub vm_id: Option<String>,
+}
@@ -0,0 +1,41 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Serialize, Clone, sqlx::FromRow)]
+pub struct ConsoleShareLink {
+    pub id: Uuid,
+    pub node_id: Uuid,
+    pub vm_id: String,
+    pub label: Option<String>,
+    pub view_only: bool,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Create response; the token is only handed out here
+#[derive(Debug, Serialize)]
+pub struct CreatedConsoleShareLink {
+    #[serde(flatten)]
+    pub link: ConsoleShareLink,
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateConsoleShareRequest {
+    pub node_id: Uuid,
+    pub vm_id: String,
+    pub label: Option<String>,
+    #[serde(default)]
+    pub view_only: bool,
+    /// Defaults to one hour
+    pub expires_in_secs: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListConsoleSharesQuery {
+    pub node_id: Option<Uuid>,
+    pub vm_id: Option<String>,
+}
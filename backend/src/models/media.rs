@@ -0,0 +1,122 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// A storage (Proxmox) or storage pool (Incus) that can hold ISOs
+#[derive(Debug, Clone, Serialize)]
+pub struct MediaStorage {
+    pub name: String,
+    pub storage_type: String,
+    pub total: Option<u64>,
+    pub available: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaKind {
+    Iso,
+    /// Incus image; listed for reference, not mountable
+    Image,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MediaItem {
+    /// What `/vms/media` takes as `iso_path`: `storage:iso/file.iso` on Proxmox, `pool:volume`
+    /// on Incus, or the fingerprint of an Incus image
+    pub id: String,
+    pub name: String,
+    pub kind: MediaKind,
+    pub storage: String,
+    pub size: Option<u64>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadedMedia {
+    #[serde(flatten)]
+    pub item: MediaItem,
+    /// SHA-256 of what was streamed to the node
+    pub sha256: String,
+}
+
+/// `host` picks the Proxmox cluster member and defaults to the first one online
+#[derive(Debug, Deserialize)]
+pub struct MediaQuery {
+    pub node_id: Uuid,
+    /// All storages when omitted, which on Incus also lists images
+    pub storage: Option<String>,
+    pub host: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteMediaQuery {
+    pub node_id: Uuid,
+    pub id: String,
+    pub host: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UploadMediaQuery {
+    pub node_id: Uuid,
+    pub storage: String,
+    pub filename: String,
+    pub host: Option<String>,
+    /// Expected SHA-256; a mismatching upload is deleted again
+    pub sha256: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChecksumAlgorithm {
+    Md5,
+    Sha1,
+    Sha224,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl ChecksumAlgorithm {
+    /// Length of the hex digest
+    pub fn hex_len(self) -> usize {
+        match self {
+            Self::Md5 => 32,
+            Self::Sha1 => 40,
+            Self::Sha224 => 56,
+            Self::Sha256 => 64,
+            Self::Sha384 => 96,
+            Self::Sha512 => 128,
+        }
+    }
+}
+
+/// Proxmox `download-url`: the node fetches the file itself and verifies the checksum
+#[derive(Debug, Deserialize)]
+pub struct DownloadMediaRequest {
+    pub node_id: Uuid,
+    pub storage: String,
+    pub host: Option<String>,
+    pub url: String,
+    pub filename: String,
+    pub checksum: Option<String>,
+    pub checksum_algorithm: Option<ChecksumAlgorithm>,
+    #[serde(default = "default_verify_certificates")]
+    pub verify_certificates: bool,
+}
+
+fn default_verify_certificates() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MediaTaskQuery {
+    pub node_id: Uuid,
+    /// Proxmox task id (UPID) returned by a download
+    pub task: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EjectMediaRequest {
+    pub node_id: String,
+    pub vm_id: String,
+}
@@ -1,3 +1,12 @@
 pub mod node;
 pub mod user;
 pub mod support;
+pub mod alert;
+pub mod webhook;
+pub mod recording;
+pub mod console_share;
+pub mod console_permission;
+pub mod console_input;
+pub mod cloud_init;
+pub mod resize;
+pub mod media;
@@ -20,6 +20,20 @@ pub enum NodeStatus {
     Error,
 }
 
+/// How the backend verifies a node's TLS certificate
+#[derive(Debug, Serialize, Deserialize, Type, PartialEq, Clone, Copy, Default)]
+#[sqlx(type_name = "node_tls_mode", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum NodeTlsMode {
+    /// Verify against the operating system trust store
+    #[default]
+    System,
+    /// Verify against the PEM bundle stored in `tls_ca_cert`
+    CustomCa,
+    /// Accept only the certificate whose SHA-256 fingerprint matches `tls_fingerprint`
+    Pinned,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, sqlx::FromRow)]
 pub struct Node {
     pub id: Uuid,
@@ -31,13 +45,39 @@ pub struct Node {
     pub status: NodeStatus,
     pub last_check: DateTime<Utc>,
     pub created_at: DateTime<Utc>,
+    pub tls_mode: NodeTlsMode,
+    pub tls_ca_cert: Option<String>,
+    pub tls_fingerprint: Option<String>,
+    pub tls_pending_fingerprint: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
-pub struct CreateNodeRequest {
-    pub name: String,
-    pub node_type: NodeType,
+/// Connection settings for a node that may not be saved yet; `node_type` is auto-detected when omitted
+#[derive(Debug, Deserialize, Clone)]
+pub struct NodeConnectionParams {
+    pub node_type: Option<NodeType>,
     pub api_url: String,
     pub api_key: String,
     pub api_secret: Option<String>,
+    #[serde(default)]
+    pub tls_mode: NodeTlsMode,
+    pub tls_ca_cert: Option<String>,
+    pub tls_fingerprint: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateNodeRequest {
+    pub name: String,
+    #[serde(flatten)]
+    pub connection: NodeConnectionParams,
+}
+
+/// A cluster member recorded under its dashboard node
+#[derive(Debug, Serialize, Clone, sqlx::FromRow)]
+pub struct ClusterMemberRecord {
+    pub id: Uuid,
+    pub node_id: Uuid,
+    pub name: String,
+    pub address: Option<String>,
+    pub online: bool,
+    pub last_seen: DateTime<Utc>,
 }
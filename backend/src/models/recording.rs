@@ -0,0 +1,27 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Serialize, Clone, sqlx::FromRow)]
+pub struct VncRecording {
+    pub id: Uuid,
+    pub session_id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub username: String,
+    pub node_id: Uuid,
+    pub vm_id: String,
+    pub client_ip: Option<String>,
+    pub started_at: DateTime<Utc>,
+    /// Unset while the session is still running
+    pub ended_at: Option<DateTime<Utc>>,
+    pub duration_ms: i64,
+    pub size_bytes: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListRecordingsQuery {
+    pub node_id: Option<Uuid>,
+    pub vm_id: Option<String>,
+    pub user_id: Option<Uuid>,
+    pub limit: Option<i64>,
+}
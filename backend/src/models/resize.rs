@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+
+/// A typed resource change; omitted fields stay as they are
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VmResize {
+    pub cores: Option<u32>,
+    /// Proxmox QEMU only
+    pub sockets: Option<u32>,
+    pub memory_mib: Option<u64>,
+    /// Proxmox QEMU only; 0 disables ballooning
+    pub balloon_mib: Option<u64>,
+    /// Disks only grow, never shrink
+    pub disk_grow_gib: Option<u64>,
+    /// Disk to grow, e.g. `scsi0` or an Incus device name; defaults to the boot/root disk
+    pub disk: Option<String>,
+}
+
+impl VmResize {
+    pub fn is_empty(&self) -> bool {
+        self.cores.is_none()
+            && self.sockets.is_none()
+            && self.memory_mib.is_none()
+            && self.balloon_mib.is_none()
+            && self.disk_grow_gib.is_none()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResizeVmRequest {
+    pub node_id: String,
+    pub vm_id: String,
+    #[serde(flatten)]
+    pub resize: VmResize,
+}
+
+/// What a resize is checked against: the guest's current shape and its host's capacity
+#[derive(Debug, Clone)]
+pub struct ResizeLimits {
+    pub host_cpus: u32,
+    pub host_memory_mib: u64,
+    pub cores: u32,
+    pub sockets: u32,
+    pub memory_mib: u64,
+    pub supports_sockets: bool,
+    pub supports_balloon: bool,
+    /// The disk a grow would apply to, if it exists
+    pub disk: Option<String>,
+    /// Free space on the storage behind `disk`, when the node reports it
+    pub disk_free_gib: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResizeOutcome {
+    /// Some changes are pending until the guest is restarted
+    pub reboot_required: bool,
+}
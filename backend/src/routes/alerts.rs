@@ -0,0 +1,28 @@
+use axum::{
+    routing::{get, post, put, delete},
+    Router,
+};
+use crate::state::AppState;
+use crate::controllers::alerts::{
+    list_alert_events, list_alert_rules, get_alert_rule, create_alert_rule, update_alert_rule, delete_alert_rule,
+    list_alert_channels, create_alert_channel, update_alert_channel, delete_alert_channel, test_alert_channel,
+    list_alert_silences, create_alert_silence, delete_alert_silence,
+};
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/", get(list_alert_events))
+        .route("/rules", get(list_alert_rules))
+        .route("/rules", post(create_alert_rule))
+        .route("/rules/:id", get(get_alert_rule))
+        .route("/rules/:id", put(update_alert_rule))
+        .route("/rules/:id", delete(delete_alert_rule))
+        .route("/channels", get(list_alert_channels))
+        .route("/channels", post(create_alert_channel))
+        .route("/channels/:id", put(update_alert_channel))
+        .route("/channels/:id", delete(delete_alert_channel))
+        .route("/channels/:id/test", post(test_alert_channel))
+        .route("/silences", get(list_alert_silences))
+        .route("/silences", post(create_alert_silence))
+        .route("/silences/:id", delete(delete_alert_silence))
+}
@@ -2,15 +2,16 @@ use axum::{
     routing::{get, post},
     Router,
 };
-use crate::db::DbPool;
+use crate::state::AppState;
 
-use crate::controllers::auth::{handle_login, handle_refresh, handle_register, handle_logout, handle_admin_exists};
+use crate::controllers::auth::{handle_login, handle_refresh, handle_register, handle_logout, handle_admin_exists, handle_ws_ticket};
 
-pub fn routes() -> Router<DbPool> {
+pub fn routes() -> Router<AppState> {
     Router::new()
         .route("/login", post(handle_login))
         .route("/register", post(handle_register))
         .route("/refresh", post(handle_refresh))
         .route("/logout", post(handle_logout))
         .route("/admin_exists", get(handle_admin_exists))
+        .route("/ws-ticket", post(handle_ws_ticket))
 }
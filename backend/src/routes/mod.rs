@@ -1,19 +1,21 @@
 pub mod auth;
 pub mod nodes;
 pub mod vms;
+pub mod alerts;
+pub mod webhooks;
 
 use axum::{Router, middleware};
 use tower_http::cors::{CorsLayer, AllowOrigin};
 use axum::http::{Method, HeaderValue};
-use crate::db::DbPool;
+use crate::state::AppState;
 use crate::middleware::auth_middleware;
+use crate::services::telemetry::track_requests;
 
-pub fn create_router(pool: DbPool) -> Router {
+pub fn create_router(state: AppState) -> Router {
     // Read allowed origins from env, default to localhost:3000 for dev
-    let allowed_origins = std::env::var("CORS_ALLOWED_ORIGINS").unwrap_or_else(|_| "http://localhost:3000".into());
-    let origin_values: Vec<HeaderValue> = allowed_origins
-        .split(',')
-        .map(|s| HeaderValue::from_str(s.trim()).unwrap())
+    let origin_values: Vec<HeaderValue> = crate::middleware::auth::allowed_origins()
+        .iter()
+        .map(|s| HeaderValue::from_str(s).unwrap())
         .collect();
 
     let cors = CorsLayer::new()
@@ -37,6 +39,8 @@ pub fn create_router(pool: DbPool) -> Router {
     // Public routes (no auth required)
     let public_routes = Router::new()
         .route("/health", axum::routing::get(|| async { "OK" }))
+        // Prometheus scrape target (bearer scrape token checked inside handler)
+        .route("/metrics/prometheus", axum::routing::get(crate::controllers::prometheus::prometheus_handler))
         .nest("/api/v1/auth", auth::routes())
         .nest("/auth", auth::routes());
 
@@ -44,7 +48,11 @@ pub fn create_router(pool: DbPool) -> Router {
     let websocket_routes = Router::new()
         .route("/api/v1/vms/console/:node_id/:vm_id", axum::routing::get(crate::controllers::vnc::vnc_handler))
         .route("/vms/console/:node_id/:vm_id", axum::routing::get(crate::controllers::vnc::vnc_handler))
-        // Metrics websocket endpoint (auth handled inside handler via token param or Authorization header)
+        .route("/api/v1/vms/terminal/:node_id/:vm_id", axum::routing::get(crate::controllers::vnc::terminal_handler))
+        .route("/vms/terminal/:node_id/:vm_id", axum::routing::get(crate::controllers::vnc::terminal_handler))
+        .route("/api/v1/vms/console/recordings/:id/play", axum::routing::get(crate::controllers::vnc::play_vnc_recording))
+        .route("/vms/console/recordings/:id/play", axum::routing::get(crate::controllers::vnc::play_vnc_recording))
+        // Metrics websocket endpoint (auth handled inside handler via ws_ticket param or cookie)
         .route("/api/v1/metrics", axum::routing::get(crate::controllers::metrics::metrics_handler))
         .route("/metrics", axum::routing::get(crate::controllers::metrics::metrics_handler));
 
@@ -52,25 +60,30 @@ pub fn create_router(pool: DbPool) -> Router {
     let protected_routes = Router::new()
         .nest("/api/v1/nodes", nodes::routes())
         .nest("/api/v1/vms", vms::routes())
+        .nest("/api/v1/alerts", alerts::routes())
+        .nest("/api/v1/webhooks", webhooks::routes())
         .route("/api/v1/support/message", axum::routing::post(crate::controllers::support::handle_support_message))
         .route("/api/v1/support/history", axum::routing::get(crate::controllers::support::handle_support_history))
         .nest("/nodes", nodes::routes())
         .nest("/vms", vms::routes())
+        .nest("/alerts", alerts::routes())
+        .nest("/webhooks", webhooks::routes())
         .route("/support/message", axum::routing::post(crate::controllers::support::handle_support_message))
         .route("/support/history", axum::routing::get(crate::controllers::support::handle_support_history))
-        .route_layer(middleware::from_fn_with_state(pool.clone(), auth_middleware));
+        .route_layer(middleware::from_fn_with_state(state.pool.clone(), auth_middleware));
 
     // Admin-only routes (currently empty, add routes before applying middleware)
     // let admin_routes = Router::new()
     //     .route("/api/v1/admin/users", axum::routing::get(crate::controllers::admin::list_users))
     //     .route_layer(middleware::from_fn(admin_middleware))
-    //     .route_layer(middleware::from_fn_with_state(pool.clone(), auth_middleware));
+    //     .route_layer(middleware::from_fn_with_state(state.pool.clone(), auth_middleware));
 
     Router::new()
         .merge(public_routes)
         .merge(websocket_routes)
         .merge(protected_routes)
         // .merge(admin_routes)  // Uncomment when admin routes are added
+        .layer(middleware::from_fn_with_state(state.telemetry.clone(), track_requests))
         .layer(cors)
-        .with_state(pool)
+        .with_state(state)
 }
@@ -2,14 +2,19 @@ use axum::{
     routing::{get, post, delete},
     Router,
 };
-use crate::db::DbPool;
-use crate::controllers::nodes::{list_nodes, create_node, delete_node, get_node_details, update_node};
+use crate::state::AppState;
+use crate::controllers::nodes::{list_nodes, create_node, delete_node, get_node_details, update_node, get_node_tls, probe_node_tls, trust_node_fingerprint, test_node, get_node_cluster};
 
-pub fn routes() -> Router<DbPool> {
+pub fn routes() -> Router<AppState> {
     Router::new()
         .route("/", get(list_nodes))
         .route("/", post(create_node))
+        .route("/test", post(test_node))
         .route("/:id", get(get_node_details))
         .route("/:id", axum::routing::patch(update_node))
         .route("/:id", delete(delete_node))
+        .route("/:id/cluster", get(get_node_cluster))
+        .route("/:id/tls", get(get_node_tls))
+        .route("/:id/tls/probe", post(probe_node_tls))
+        .route("/:id/tls/trust", post(trust_node_fingerprint))
 }
@@ -1,14 +1,39 @@
-use axum::{routing::{get, post, patch}, Router};
-use crate::db::DbPool;
-use crate::controllers::vms::{list_vms, handle_vm_power_action, handle_update_vm_config, handle_get_vm_details, handle_mount_media};
-use crate::controllers::vnc::{get_vnc_ticket_handler};
+use axum::{routing::{get, post, patch, delete}, Router};
+use crate::state::AppState;
+use crate::controllers::vms::{list_vms, vm_events, handle_vm_power_action, handle_update_vm_config, handle_resize_vm, handle_get_vm_details, handle_mount_media, handle_eject_media, handle_get_guest_info, handle_guest_fsfreeze, handle_get_cloud_init, handle_update_cloud_init, handle_regenerate_cloud_init};
+use crate::controllers::media::{delete_media, download_media, get_media_task, list_media_library, list_media_storages, upload_media};
+use crate::controllers::console_permissions::{delete_console_permission, list_console_permissions, set_console_permission};
+use crate::controllers::console_shares::{create_console_share, list_console_shares, revoke_console_share};
+use crate::controllers::vnc::{get_vnc_ticket_handler, list_vnc_recordings, list_vnc_sessions, send_keys_to_vnc_session, send_vm_keys, terminate_vnc_session, type_into_vnc_session};
 
-pub fn routes() -> Router<DbPool> {
+pub fn routes() -> Router<AppState> {
     Router::new()
         .route("/", get(list_vms))
+        .route("/events", get(vm_events))
         .route("/details", get(handle_get_vm_details))
+        .route("/guest", get(handle_get_guest_info))
+        .route("/guest/fsfreeze", post(handle_guest_fsfreeze))
+        .route("/cloud-init", get(handle_get_cloud_init).put(handle_update_cloud_init))
+        .route("/cloud-init/regenerate", post(handle_regenerate_cloud_init))
         .route("/power", post(handle_vm_power_action))
         .route("/config", patch(handle_update_vm_config))
+        .route("/resize", post(handle_resize_vm))
         .route("/media", post(handle_mount_media))
+        .route("/media/eject", post(handle_eject_media))
+        .route("/media/storages", get(list_media_storages))
+        .route("/media/library", get(list_media_library).delete(delete_media))
+        .route("/media/upload", post(upload_media))
+        .route("/media/download", post(download_media))
+        .route("/media/tasks", get(get_media_task))
         .route("/console/:node_id/:vm_id/ticket", get(get_vnc_ticket_handler))
+        .route("/console/sessions", get(list_vnc_sessions))
+        .route("/console/:node_id/:vm_id/sendkey", post(send_vm_keys))
+        .route("/console/sessions/:id", delete(terminate_vnc_session))
+        .route("/console/sessions/:id/type", post(type_into_vnc_session))
+        .route("/console/sessions/:id/keys", post(send_keys_to_vnc_session))
+        .route("/console/recordings", get(list_vnc_recordings))
+        .route("/console/shares", get(list_console_shares).post(create_console_share))
+        .route("/console/shares/:id", delete(revoke_console_share))
+        .route("/console/permissions", get(list_console_permissions).put(set_console_permission))
+        .route("/console/permissions/:id", delete(delete_console_permission))
 }
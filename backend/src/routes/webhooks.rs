@@ -0,0 +1,21 @@
+use axum::{
+    routing::{get, post, patch, delete},
+    Router,
+};
+use crate::state::AppState;
+use crate::controllers::webhooks::{
+    list_webhooks, create_webhook, get_webhook, update_webhook, delete_webhook,
+    list_webhook_deliveries, send_test_event, redeliver_webhook_delivery,
+};
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/", get(list_webhooks))
+        .route("/", post(create_webhook))
+        .route("/:id", get(get_webhook))
+        .route("/:id", patch(update_webhook))
+        .route("/:id", delete(delete_webhook))
+        .route("/:id/deliveries", get(list_webhook_deliveries))
+        .route("/:id/deliveries/:delivery_id/redeliver", post(redeliver_webhook_delivery))
+        .route("/:id/test", post(send_test_event))
+}
@@ -0,0 +1,380 @@
+use chrono::{DateTime, Utc};
+use futures::future::join_all;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+use crate::clients::registry::{ClientRegistry, SharedClient};
+use crate::db::DbPool;
+use crate::models::alert::{AlertChannel, AlertMetric, AlertRule, AlertSilence, AlertState};
+use crate::models::node::Node;
+use crate::services::metrics::{MetricsCollector, NodeSample};
+use crate::services::notifications::{deliver, AlertNotification};
+use crate::services::vms::internal_vm_id;
+
+const EVAL_INTERVAL: Duration = Duration::from_secs(30);
+/// How long a stop issued from the dashboard excuses a guest from `vm_stopped` alerts
+const EXPECTED_STOP_WINDOW: Duration = Duration::from_secs(600);
+
+/// One rule evaluated against one subject (a node or a guest)
+struct Check {
+    subject: String,
+    vm_id: Option<String>,
+    active: bool,
+    value: Option<f64>,
+    message: String,
+}
+
+/// Live state of a node gathered once per evaluation round
+struct Observation {
+    node: Node,
+    up: bool,
+    sample: Option<NodeSample>,
+    /// (internal VM id, status); None when the listing was not needed or failed
+    vms: Option<Vec<(String, String)>>,
+}
+
+/// Remembers guest status between rounds to spot running -> stopped transitions
+#[derive(Default)]
+struct VmTracker {
+    last: HashMap<(Uuid, String), String>,
+    unexpected: HashSet<(Uuid, String)>,
+}
+
+/// Background evaluator for alert rules.
+///
+/// Rules, silences and fired alerts live in Postgres so state survives restarts; only the
+/// "condition has held since" timers and guest status history are kept in memory.
+#[derive(Clone, Default)]
+pub struct AlertEngine {
+    expected_stops: Arc<Mutex<HashMap<(Uuid, String), Instant>>>,
+}
+
+impl AlertEngine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a power action issued through the dashboard so the resulting stop is not alerted on
+    pub fn note_power_action(&self, node_id: Uuid, vm_id: &str, action: &str) {
+        if matches!(action, "start" | "resume" | "unfreeze") {
+            return;
+        }
+        self.expected_stops.lock().unwrap().insert((node_id, vm_id.to_string()), Instant::now());
+    }
+
+    fn stop_expected(&self, node_id: Uuid, vm_id: &str) -> bool {
+        let mut expected = self.expected_stops.lock().unwrap();
+        expected.retain(|_, at| at.elapsed() < EXPECTED_STOP_WINDOW);
+        expected.contains_key(&(node_id, vm_id.to_string()))
+    }
+
+    /// Node health and figures come from the metrics collector; only guest listings are fetched here
+    pub async fn run(self, pool: DbPool, clients: ClientRegistry, metrics: MetricsCollector) {
+        let mut pending: HashMap<(Uuid, String), DateTime<Utc>> = HashMap::new();
+        let mut tracker = VmTracker::default();
+
+        // Guests already alerting stay flagged across restarts instead of resolving spuriously
+        match sqlx::query_scalar::<_, String>(
+            "SELECT e.subject FROM alert_events e JOIN alert_rules r ON r.id = e.rule_id WHERE e.state = 'firing' AND r.metric = 'vm_stopped'"
+        )
+        .fetch_all(&pool)
+        .await
+        {
+            Ok(subjects) => {
+                for subject in subjects {
+                    if let Some((node_id, vm_id)) = subject.split_once(':') {
+                        if let Ok(node_id) = Uuid::parse_str(node_id) {
+                            tracker.unexpected.insert((node_id, vm_id.to_string()));
+                        }
+                    }
+                }
+            }
+            Err(e) => tracing::error!("Failed to load firing VM alerts: {}", e),
+        }
+
+        let mut interval = tokio::time::interval(EVAL_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.evaluate(&pool, &clients, &metrics, &mut pending, &mut tracker).await {
+                tracing::error!("Alert evaluation failed: {}", e);
+            }
+        }
+    }
+
+    async fn evaluate(
+        &self,
+        pool: &DbPool,
+        clients: &ClientRegistry,
+        metrics: &MetricsCollector,
+        pending: &mut HashMap<(Uuid, String), DateTime<Utc>>,
+        tracker: &mut VmTracker,
+    ) -> anyhow::Result<()> {
+        let rules = sqlx::query_as::<_, AlertRule>(
+            "SELECT id, name, metric, threshold, duration_secs, node_id, vm_id, channel_ids, enabled, created_at FROM alert_rules WHERE enabled"
+        )
+        .fetch_all(pool)
+        .await?;
+
+        // Alerts of deleted or disabled rules can no longer resolve on their own
+        let rule_ids: Vec<Uuid> = rules.iter().map(|r| r.id).collect();
+        sqlx::query("UPDATE alert_events SET state = 'resolved', resolved_at = NOW() WHERE state = 'firing' AND NOT (rule_id = ANY($1))")
+            .bind(&rule_ids)
+            .execute(pool)
+            .await?;
+        pending.retain(|(rule_id, _), _| rule_ids.contains(rule_id));
+
+        if rules.is_empty() {
+            return Ok(());
+        }
+
+        let nodes = sqlx::query_as::<_, Node>(
+            "SELECT id, name, node_type, api_url, api_key, api_secret, status, last_check, created_at, tls_mode, tls_ca_cert, tls_fingerprint, tls_pending_fingerprint FROM nodes"
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let in_scope = |node: &Node, wanted: fn(AlertMetric) -> bool| {
+            rules.iter().any(|r| wanted(r.metric) && r.node_id.is_none_or(|id| id == node.id))
+        };
+
+        let mut observations = Vec::new();
+        for node in nodes {
+            if !in_scope(&node, |_| true) {
+                continue;
+            }
+            // Not reached by the collector yet; judging it offline now would be a false alarm
+            let Some(snapshot) = metrics.snapshot(node.id) else { continue };
+            let need_vms = in_scope(&node, |m| m == AlertMetric::VmStopped);
+            match clients.client_for(&node) {
+                Ok(client) => observations.push(observe(node, client, snapshot.up, snapshot.sample, need_vms)),
+                Err(e) => tracing::warn!("Alert evaluation skipping {}: {}", node.name, e),
+            }
+        }
+        let observations = join_all(observations).await;
+
+        for obs in &observations {
+            self.track_vms(obs, tracker);
+        }
+
+        let firing: HashMap<(Uuid, String), (Uuid, bool)> = sqlx::query_as::<_, (Uuid, Uuid, String, bool)>(
+            "SELECT id, rule_id, subject, silenced FROM alert_events WHERE state = 'firing'"
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|(id, rule_id, subject, silenced)| ((rule_id, subject), (id, silenced)))
+        .collect();
+
+        let silences = sqlx::query_as::<_, AlertSilence>(
+            "SELECT id, rule_id, node_id, starts_at, ends_at, reason, created_by, created_at FROM alert_silences WHERE ends_at > NOW()"
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let channels: HashMap<Uuid, AlertChannel> = sqlx::query_as::<_, AlertChannel>(
+            "SELECT id, name, kind, config, enabled, created_at FROM alert_channels WHERE enabled"
+        )
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .map(|c| (c.id, c))
+        .collect();
+
+        let now = Utc::now();
+        for rule in &rules {
+            for obs in observations.iter().filter(|o| rule.node_id.is_none_or(|id| id == o.node.id)) {
+                for check in checks(rule, obs, tracker) {
+                    let key = (rule.id, check.subject.clone());
+
+                    if !check.active {
+                        pending.remove(&key);
+                        if let Some(&(event_id, silenced)) = firing.get(&key) {
+                            sqlx::query("UPDATE alert_events SET state = 'resolved', resolved_at = NOW() WHERE id = $1")
+                                .bind(event_id)
+                                .execute(pool)
+                                .await?;
+                            tracing::info!("✅ Alert resolved: {} ({})", rule.name, check.subject);
+                            if !silenced {
+                                notify(rule, &obs.node, &check, event_id, AlertState::Resolved, &channels);
+                            }
+                        }
+                        continue;
+                    }
+
+                    let since = *pending.entry(key.clone()).or_insert(now);
+                    let silenced = silences.iter().any(|s| s.matches(rule.id, obs.node.id, now));
+                    if let Some(&(event_id, was_silenced)) = firing.get(&key) {
+                        // Still firing when its silence ran out: notify now, since nobody was told
+                        if was_silenced && !silenced {
+                            sqlx::query("UPDATE alert_events SET silenced = FALSE WHERE id = $1")
+                                .bind(event_id)
+                                .execute(pool)
+                                .await?;
+                            tracing::warn!("🔥 Alert still firing after its silence ended: {} - {}", rule.name, check.message);
+                            notify(rule, &obs.node, &check, event_id, AlertState::Firing, &channels);
+                        }
+                        continue;
+                    }
+                    if (now - since).num_seconds() < rule.duration_secs as i64 {
+                        continue;
+                    }
+
+                    let event_id = sqlx::query_scalar::<_, Uuid>(
+                        r#"
+                        INSERT INTO alert_events (rule_id, node_id, subject, value, message, silenced, started_at)
+                        VALUES ($1, $2, $3, $4, $5, $6, $7)
+                        ON CONFLICT (rule_id, subject) WHERE state = 'firing' DO NOTHING
+                        RETURNING id
+                        "#
+                    )
+                    .bind(rule.id)
+                    .bind(obs.node.id)
+                    .bind(&check.subject)
+                    .bind(check.value)
+                    .bind(&check.message)
+                    .bind(silenced)
+                    .bind(since)
+                    .fetch_optional(pool)
+                    .await?;
+
+                    if let Some(event_id) = event_id {
+                        tracing::warn!("🔥 Alert firing: {} - {}{}", rule.name, check.message, if silenced { " (silenced)" } else { "" });
+                        if !silenced {
+                            notify(rule, &obs.node, &check, event_id, AlertState::Firing, &channels);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn track_vms(&self, obs: &Observation, tracker: &mut VmTracker) {
+        let Some(vms) = &obs.vms else { return };
+        for (vm_id, status) in vms {
+            let key = (obs.node.id, vm_id.clone());
+            let previous = tracker.last.insert(key.clone(), status.clone());
+            if status == "running" {
+                tracker.unexpected.remove(&key);
+            } else if previous.as_deref() == Some("running") && !self.stop_expected(obs.node.id, vm_id) {
+                tracker.unexpected.insert(key);
+            }
+        }
+    }
+}
+
+async fn observe(node: Node, client: SharedClient, up: bool, sample: Option<NodeSample>, need_vms: bool) -> Observation {
+    if !up {
+        return Observation { node, up, sample: None, vms: None };
+    }
+
+    let vms = if need_vms {
+        match client.list_vms().await {
+            Ok(vms) => Some(vms.iter()
+                .filter_map(|vm| {
+                    let id = internal_vm_id(node.node_type, vm)?;
+                    let status = vm.get("status").and_then(|s| s.as_str()).unwrap_or("unknown").to_lowercase();
+                    Some((id, status))
+                })
+                .collect()),
+            Err(e) => {
+                tracing::warn!("Alert evaluation could not list VMs on {}: {}", node.name, e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    Observation { node, up, sample, vms }
+}
+
+fn checks(rule: &AlertRule, obs: &Observation, tracker: &VmTracker) -> Vec<Check> {
+    let node = &obs.node;
+    let threshold = rule.threshold.unwrap_or(0.0);
+    let usage = |label: &str, value: Option<f32>| -> Vec<Check> {
+        value.map(|v| Check {
+            subject: node.id.to_string(),
+            vm_id: None,
+            active: v as f64 > threshold,
+            value: Some(v as f64),
+            message: format!("{} at {:.1}% on {} (threshold {:.1}%)", label, v, node.name, threshold),
+        })
+        .into_iter()
+        .collect()
+    };
+
+    match rule.metric {
+        AlertMetric::NodeCpu => usage("CPU", obs.sample.as_ref().map(|s| s.cpu)),
+        AlertMetric::NodeMemory => usage("Memory", obs.sample.as_ref().map(|s| s.ram)),
+        AlertMetric::NodeDisk => usage("Disk", obs.sample.as_ref().and_then(|s| s.disk)),
+        AlertMetric::NodeOffline => vec![Check {
+            subject: node.id.to_string(),
+            vm_id: None,
+            active: !obs.up,
+            value: None,
+            message: if obs.up {
+                format!("Node {} is responding again", node.name)
+            } else {
+                format!("Node {} is not responding", node.name)
+            },
+        }],
+        AlertMetric::VmStopped => obs.vms.iter()
+            .flatten()
+            .filter(|(vm_id, _)| rule.vm_id.as_ref().is_none_or(|wanted| wanted == vm_id))
+            .map(|(vm_id, status)| {
+                let active = tracker.unexpected.contains(&(node.id, vm_id.clone()));
+                Check {
+                    subject: format!("{}:{}", node.id, vm_id),
+                    vm_id: Some(vm_id.clone()),
+                    active,
+                    value: None,
+                    message: if active {
+                        format!("VM {} on {} stopped unexpectedly (status: {})", vm_id, node.name, status)
+                    } else {
+                        format!("VM {} on {} is {}", vm_id, node.name, status)
+                    },
+                }
+            })
+            .collect(),
+    }
+}
+
+/// Fan a notification out to the rule's channels without holding up evaluation
+fn notify(
+    rule: &AlertRule,
+    node: &Node,
+    check: &Check,
+    event_id: Uuid,
+    state: AlertState,
+    channels: &HashMap<Uuid, AlertChannel>,
+) {
+    let notification = AlertNotification {
+        event_id,
+        rule_id: rule.id,
+        rule_name: rule.name.clone(),
+        metric: rule.metric,
+        state,
+        node_id: node.id,
+        node_name: node.name.clone(),
+        vm_id: check.vm_id.clone(),
+        value: check.value,
+        threshold: rule.threshold,
+        message: check.message.clone(),
+        timestamp: Utc::now(),
+    };
+
+    for channel in rule.channel_ids.iter().filter_map(|id| channels.get(id)) {
+        let channel = channel.clone();
+        let notification = notification.clone();
+        tokio::spawn(async move {
+            if let Err(e) = deliver(&channel, &notification).await {
+                tracing::error!("Failed to deliver alert to channel {}: {}", channel.name, e);
+            }
+        });
+    }
+}
@@ -0,0 +1,297 @@
+use base64::Engine;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use crate::models::cloud_init::{CloudInitUpdate, IpConfig};
+
+/// Proxmox has ipconfig0 to ipconfig31
+const MAX_IP_CONFIGS: usize = 32;
+const MAX_SSH_KEYS: usize = 64;
+const MAX_YAML_BYTES: usize = 64 * 1024;
+
+const SSH_KEY_TYPES: &[&str] = &[
+    "ssh-ed25519",
+    "ssh-rsa",
+    "ecdsa-sha2-nistp256",
+    "ecdsa-sha2-nistp384",
+    "ecdsa-sha2-nistp521",
+    "sk-ssh-ed25519@openssh.com",
+    "sk-ecdsa-sha2-nistp256@openssh.com",
+];
+
+/// Check an update before it reaches the node. Proxmox only takes the structured fields
+/// (raw YAML would need a snippets storage), Incus only takes raw user-data/network-config.
+pub fn validate(changes: &CloudInitUpdate, proxmox: bool) -> anyhow::Result<()> {
+    if proxmox {
+        if changes.user_data.is_some() || changes.network_config.is_some() {
+            anyhow::bail!("Proxmox guests take user, keys and networking fields, not raw YAML");
+        }
+    } else if changes.user.is_some()
+        || changes.password.is_some()
+        || changes.ssh_keys.is_some()
+        || changes.ip_configs.is_some()
+        || changes.nameservers.is_some()
+        || changes.search_domain.is_some()
+    {
+        anyhow::bail!("Incus guests take user_data and network_config only");
+    }
+
+    if let Some(user) = changes.user.as_deref().filter(|u| !u.is_empty()) {
+        validate_username(user)?;
+    }
+    if let Some(password) = &changes.password {
+        if password.chars().any(char::is_control) {
+            anyhow::bail!("Password contains control characters");
+        }
+    }
+    if let Some(keys) = &changes.ssh_keys {
+        if keys.len() > MAX_SSH_KEYS {
+            anyhow::bail!("At most {} SSH keys are allowed", MAX_SSH_KEYS);
+        }
+        for key in keys {
+            validate_ssh_key(key)?;
+        }
+    }
+    if let Some(configs) = &changes.ip_configs {
+        if configs.len() > MAX_IP_CONFIGS {
+            anyhow::bail!("At most {} IP configs are allowed", MAX_IP_CONFIGS);
+        }
+        for (i, config) in configs.iter().enumerate() {
+            validate_ip_config(config).map_err(|e| anyhow::anyhow!("ipconfig{}: {}", i, e))?;
+        }
+    }
+    if let Some(nameservers) = &changes.nameservers {
+        for ns in nameservers {
+            ns.parse::<IpAddr>().map_err(|_| anyhow::anyhow!("Nameserver {:?} is not an IP address", ns))?;
+        }
+    }
+    if let Some(domain) = changes.search_domain.as_deref().filter(|d| !d.is_empty()) {
+        if !domain.split('.').all(|label| !label.is_empty() && label.len() <= 63 && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')) {
+            anyhow::bail!("Search domain {:?} is not a valid domain", domain);
+        }
+    }
+    if let Some(user_data) = changes.user_data.as_deref().filter(|d| !d.is_empty()) {
+        validate_user_data(user_data)?;
+    }
+    if let Some(network_config) = changes.network_config.as_deref().filter(|d| !d.is_empty()) {
+        validate_network_config(network_config)?;
+    }
+    Ok(())
+}
+
+fn validate_username(user: &str) -> anyhow::Result<()> {
+    let mut chars = user.chars();
+    let valid = user.len() <= 32
+        && chars.next().is_some_and(|c| c.is_ascii_lowercase() || c == '_')
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-');
+    if !valid {
+        anyhow::bail!("User {:?} is not a valid Linux username", user);
+    }
+    Ok(())
+}
+
+/// `<type> <base64 blob> [comment]`, where the blob must decode and name the same key type
+fn validate_ssh_key(key: &str) -> anyhow::Result<()> {
+    if key.contains(['\n', '\r']) {
+        anyhow::bail!("Each SSH key must be a single line");
+    }
+    let mut parts = key.split_whitespace();
+    let (Some(key_type), Some(blob)) = (parts.next(), parts.next()) else {
+        anyhow::bail!("SSH key must look like \"<type> <key> [comment]\"");
+    };
+    if !SSH_KEY_TYPES.contains(&key_type) {
+        anyhow::bail!("Unsupported SSH key type {:?}", key_type);
+    }
+
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(blob)
+        .map_err(|_| anyhow::anyhow!("SSH key data is not valid base64"))?;
+    // The blob starts with the key type as a length-prefixed string
+    let embedded = decoded.get(..4)
+        .map(|len| u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize)
+        .and_then(|len| decoded.get(4..4 + len));
+    if embedded != Some(key_type.as_bytes()) {
+        anyhow::bail!("SSH key data does not match its type {:?}", key_type);
+    }
+    Ok(())
+}
+
+fn validate_ip_config(config: &IpConfig) -> anyhow::Result<()> {
+    let static_v4 = match config.ip.as_deref() {
+        None | Some("dhcp") => false,
+        Some(cidr) => {
+            parse_cidr::<Ipv4Addr>(cidr, 32)?;
+            true
+        }
+    };
+    if let Some(gw) = &config.gateway {
+        if !static_v4 {
+            anyhow::bail!("gateway needs a static ip");
+        }
+        gw.parse::<Ipv4Addr>().map_err(|_| anyhow::anyhow!("gateway {:?} is not an IPv4 address", gw))?;
+    }
+
+    let static_v6 = match config.ip6.as_deref() {
+        None | Some("dhcp") | Some("auto") => false,
+        Some(cidr) => {
+            parse_cidr::<Ipv6Addr>(cidr, 128)?;
+            true
+        }
+    };
+    if let Some(gw) = &config.gateway6 {
+        if !static_v6 {
+            anyhow::bail!("gateway6 needs a static ip6");
+        }
+        gw.parse::<Ipv6Addr>().map_err(|_| anyhow::anyhow!("gateway6 {:?} is not an IPv6 address", gw))?;
+    }
+    Ok(())
+}
+
+fn parse_cidr<A: std::str::FromStr>(cidr: &str, max_prefix: u8) -> anyhow::Result<()> {
+    let valid = cidr.split_once('/').is_some_and(|(addr, prefix)| {
+        addr.parse::<A>().is_ok() && prefix.parse::<u8>().is_ok_and(|p| p <= max_prefix)
+    });
+    if !valid {
+        anyhow::bail!("{:?} is not an address in CIDR notation", cidr);
+    }
+    Ok(())
+}
+
+fn parse_yaml(text: &str, what: &str) -> anyhow::Result<serde_yaml::Value> {
+    if text.len() > MAX_YAML_BYTES {
+        anyhow::bail!("{} is larger than {} bytes", what, MAX_YAML_BYTES);
+    }
+    serde_yaml::from_str(text).map_err(|e| anyhow::anyhow!("{} is not valid YAML: {}", what, e))
+}
+
+/// Cloud-init picks the format from the first line; YAML formats are parsed, scripts and
+/// includes are passed through
+fn validate_user_data(user_data: &str) -> anyhow::Result<()> {
+    let header = user_data.lines().next().unwrap_or_default().trim_end();
+    match header {
+        "#cloud-config" => {
+            let doc = parse_yaml(user_data, "user_data")?;
+            if !doc.is_mapping() && !doc.is_null() {
+                anyhow::bail!("#cloud-config user_data must be a YAML mapping");
+            }
+        }
+        "#cloud-config-archive" => {
+            if !parse_yaml(user_data, "user_data")?.is_sequence() {
+                anyhow::bail!("#cloud-config-archive user_data must be a YAML list");
+            }
+        }
+        h if h.starts_with("#!") || h.starts_with("#include") || h.starts_with("## template: jinja") || h.starts_with("Content-Type:") => {}
+        _ => anyhow::bail!("user_data must start with #cloud-config, #! or another cloud-init header"),
+    }
+    Ok(())
+}
+
+/// Version 1 or 2 network config, optionally nested under a top-level `network` key
+fn validate_network_config(network_config: &str) -> anyhow::Result<()> {
+    let doc = parse_yaml(network_config, "network_config")?;
+    let network = doc.get("network").unwrap_or(&doc);
+    match network.get("version").and_then(|v| v.as_u64()) {
+        Some(1) | Some(2) => Ok(()),
+        _ => anyhow::bail!("network_config must be a mapping with version 1 or 2"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An OpenSSH public key line whose blob embeds `embedded_type`
+    fn ssh_key(key_type: &str, embedded_type: &str) -> String {
+        let mut blob = (embedded_type.len() as u32).to_be_bytes().to_vec();
+        blob.extend(embedded_type.as_bytes());
+        blob.extend(32u32.to_be_bytes());
+        blob.extend([7u8; 32]);
+        format!("{} {} admin@laptop", key_type, base64::engine::general_purpose::STANDARD.encode(blob))
+    }
+
+    fn keys(keys: Vec<String>) -> CloudInitUpdate {
+        CloudInitUpdate { ssh_keys: Some(keys), ..Default::default() }
+    }
+
+    fn user_data(text: &str) -> CloudInitUpdate {
+        CloudInitUpdate { user_data: Some(text.to_string()), ..Default::default() }
+    }
+
+    fn network_config(text: &str) -> CloudInitUpdate {
+        CloudInitUpdate { network_config: Some(text.to_string()), ..Default::default() }
+    }
+
+    #[test]
+    fn accepts_well_formed_ssh_keys() {
+        assert!(validate(&keys(vec![ssh_key("ssh-ed25519", "ssh-ed25519")]), true).is_ok());
+        // The comment is optional
+        let bare = ssh_key("ssh-ed25519", "ssh-ed25519").rsplit_once(' ').unwrap().0.to_string();
+        assert!(validate(&keys(vec![bare]), true).is_ok());
+    }
+
+    #[test]
+    fn rejects_malformed_ssh_keys() {
+        let cases = [
+            ssh_key("ssh-rsa", "ssh-ed25519"),
+            ssh_key("ssh-dss", "ssh-dss"),
+            "ssh-ed25519 not*base64".to_string(),
+            "ssh-ed25519".to_string(),
+            format!("{}\nssh-ed25519 AAAA", ssh_key("ssh-ed25519", "ssh-ed25519")),
+            "ssh-ed25519 AAAA".to_string(),
+        ];
+        for key in cases {
+            assert!(validate(&keys(vec![key.clone()]), true).is_err(), "{:?}", key);
+        }
+
+        let too_many = vec![ssh_key("ssh-ed25519", "ssh-ed25519"); MAX_SSH_KEYS + 1];
+        assert!(validate(&keys(too_many), true).is_err());
+    }
+
+    #[test]
+    fn user_data_is_checked_by_its_header() {
+        assert!(validate(&user_data("#cloud-config\npackages: [nginx]\n"), false).is_ok());
+        assert!(validate(&user_data("#cloud-config\n"), false).is_ok());
+        assert!(validate(&user_data("#!/bin/sh\necho hi: [\n"), false).is_ok());
+        assert!(validate(&user_data("#cloud-config-archive\n- type: text/x-shellscript\n  content: echo\n"), false).is_ok());
+
+        assert!(validate(&user_data("packages: [nginx]\n"), false).is_err());
+        assert!(validate(&user_data("#cloud-config\npackages: [nginx\n"), false).is_err());
+        assert!(validate(&user_data("#cloud-config\n- a list\n"), false).is_err());
+        assert!(validate(&user_data("#cloud-config-archive\nkey: value\n"), false).is_err());
+
+        let huge = format!("#cloud-config\n# {}\n", "x".repeat(MAX_YAML_BYTES));
+        assert!(validate(&user_data(&huge), false).is_err());
+    }
+
+    #[test]
+    fn network_config_needs_a_known_version() {
+        assert!(validate(&network_config("version: 2\nethernets: {}\n"), false).is_ok());
+        assert!(validate(&network_config("network:\n  version: 1\n  config: []\n"), false).is_ok());
+
+        assert!(validate(&network_config("version: 3\n"), false).is_err());
+        assert!(validate(&network_config("ethernets: {}\n"), false).is_err());
+        assert!(validate(&network_config("version: [2\n"), false).is_err());
+    }
+
+    #[test]
+    fn fields_must_match_the_node_type() {
+        assert!(validate(&user_data("#cloud-config\n"), true).is_err());
+        assert!(validate(&keys(vec![ssh_key("ssh-ed25519", "ssh-ed25519")]), false).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_structured_fields() {
+        let update = |u: CloudInitUpdate| validate(&u, true);
+        assert!(update(CloudInitUpdate { user: Some("Admin".into()), ..Default::default() }).is_err());
+        assert!(update(CloudInitUpdate { user: Some("deploy-1".into()), ..Default::default() }).is_ok());
+        assert!(update(CloudInitUpdate { nameservers: Some(vec!["dns.example".into()]), ..Default::default() }).is_err());
+        assert!(update(CloudInitUpdate { search_domain: Some("bad..example".into()), ..Default::default() }).is_err());
+
+        let ip = |ip: &str, gw: Option<&str>| CloudInitUpdate {
+            ip_configs: Some(vec![IpConfig { ip: Some(ip.into()), gateway: gw.map(str::to_string), ..Default::default() }]),
+            ..Default::default()
+        };
+        assert!(update(ip("10.0.0.5/24", Some("10.0.0.1"))).is_ok());
+        assert!(update(ip("10.0.0.5/33", None)).is_err());
+        assert!(update(ip("10.0.0.5", None)).is_err());
+        assert!(update(ip("dhcp", Some("10.0.0.1"))).is_err());
+    }
+}
@@ -0,0 +1,98 @@
+use std::time::Duration;
+use uuid::Uuid;
+use crate::models::console_input::KeyCombo;
+use crate::services::vnc_sessions::{InjectError, VncSessions};
+
+/// X11 keysyms for the non-printing keys used here; printable ASCII maps to its own code
+mod keysym {
+    pub const TAB: u32 = 0xff09;
+    pub const RETURN: u32 = 0xff0d;
+    /// Print Screen, which is SysRq while Alt is held
+    pub const PRINT: u32 = 0xff61;
+    pub const F2: u32 = 0xffbf;
+    pub const SHIFT_L: u32 = 0xffe1;
+    pub const CONTROL_L: u32 = 0xffe3;
+    pub const ALT_L: u32 = 0xffe9;
+    pub const DELETE: u32 = 0xffff;
+}
+
+/// Characters typed with Shift held on a US keyboard, besides capital letters
+const US_SHIFTED: &str = "~!@#$%^&*()_+{}|:\"<>?";
+
+/// Keysym for `c` on a US layout and whether Shift is held to type it
+fn us_keysym(c: char) -> Option<(u32, bool)> {
+    match c {
+        '\n' | '\r' => Some((keysym::RETURN, false)),
+        '\t' => Some((keysym::TAB, false)),
+        ' '..='~' => Some((c as u32, c.is_ascii_uppercase() || US_SHIFTED.contains(c))),
+        _ => None,
+    }
+}
+
+/// RFB KeyEvent client message
+fn key_event(out: &mut Vec<u8>, keysym: u32, down: bool) {
+    out.extend_from_slice(&[4, down as u8, 0, 0]);
+    out.extend_from_slice(&keysym.to_be_bytes());
+}
+
+/// Press `keys` in order, then release them in reverse
+fn chord(keys: &[u32]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(keys.len() * 16);
+    for &key in keys {
+        key_event(&mut out, key, true);
+    }
+    for &key in keys.iter().rev() {
+        key_event(&mut out, key, false);
+    }
+    out
+}
+
+/// The first character of `text` that cannot be typed on a US layout
+pub fn untypeable(text: &str) -> Option<char> {
+    text.chars().find(|&c| us_keysym(c).is_none())
+}
+
+fn combo_keysyms(combo: KeyCombo, sysrq_key: Option<char>) -> Vec<u32> {
+    match combo {
+        KeyCombo::CtrlAltDel => vec![keysym::CONTROL_L, keysym::ALT_L, keysym::DELETE],
+        KeyCombo::AltF2 => vec![keysym::ALT_L, keysym::F2],
+        KeyCombo::SysRq => {
+            let mut keys = vec![keysym::ALT_L, keysym::PRINT];
+            keys.extend(sysrq_key.map(|k| k.to_ascii_lowercase() as u32));
+            keys
+        }
+    }
+}
+
+/// The same combo in QEMU key names, as taken by Proxmox `sendkey`
+pub fn qemu_keys(combo: KeyCombo, sysrq_key: Option<char>) -> String {
+    match combo {
+        KeyCombo::CtrlAltDel => "ctrl-alt-delete".to_string(),
+        KeyCombo::AltF2 => "alt-f2".to_string(),
+        KeyCombo::SysRq => match sysrq_key {
+            Some(key) => format!("alt-sysrq-{}", key.to_ascii_lowercase()),
+            None => "alt-sysrq".to_string(),
+        },
+    }
+}
+
+/// Type `text` into a live console session one character at a time. Callers check it with
+/// `untypeable` first; `\r\n` is a single Enter.
+pub async fn type_text(sessions: &VncSessions, session_id: Uuid, text: &str, delay: Duration) -> Result<(), InjectError> {
+    let text = text.replace("\r\n", "\n");
+    for (i, c) in text.chars().enumerate() {
+        let Some((key, shift)) = us_keysym(c) else { continue };
+        let keys = if shift { vec![keysym::SHIFT_L, key] } else { vec![key] };
+
+        if i > 0 && !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+        sessions.inject(session_id, chord(&keys)).await?;
+    }
+    Ok(())
+}
+
+/// Press a key combination in a live console session
+pub async fn send_combo(sessions: &VncSessions, session_id: Uuid, combo: KeyCombo, sysrq_key: Option<char>) -> Result<(), InjectError> {
+    sessions.inject(session_id, chord(&combo_keysyms(combo, sysrq_key))).await
+}
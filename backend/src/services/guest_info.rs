@@ -0,0 +1,87 @@
+use futures::stream::{self, StreamExt};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+use crate::clients::registry::SharedClient;
+use crate::clients::GuestInfo;
+
+/// How long guest details are reused before the guest is asked again
+const CACHE_TTL: Duration = Duration::from_secs(60);
+/// Guests slower than this are listed without guest details until the next try
+const FETCH_TIMEOUT: Duration = Duration::from_secs(3);
+const MAX_CONCURRENT_FETCHES: usize = 16;
+
+/// Keyed by dashboard node and the guest's action id
+type Entries = HashMap<(Uuid, String), (Instant, GuestInfo)>;
+
+/// Caches what running guests report about themselves, so listing VMs (and the inventory
+/// stream polling it) does not hit every guest agent each time. Failures are cached too.
+#[derive(Clone, Default)]
+pub struct GuestInfoCache {
+    entries: Arc<RwLock<Entries>>,
+}
+
+impl GuestInfoCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn get(&self, client: &SharedClient, node_id: Uuid, vm_id: &str) -> GuestInfo {
+        let key = (node_id, vm_id.to_string());
+        if let Some((fetched, info)) = self.entries.read().unwrap().get(&key) {
+            if fetched.elapsed() < CACHE_TTL {
+                return info.clone();
+            }
+        }
+
+        let info = match tokio::time::timeout(FETCH_TIMEOUT, client.guest_info(vm_id)).await {
+            Ok(Ok(info)) => info,
+            Ok(Err(e)) => {
+                tracing::debug!("No guest info for {}: {}", vm_id, e);
+                GuestInfo::default()
+            }
+            Err(_) => {
+                tracing::debug!("Guest info for {} timed out", vm_id);
+                GuestInfo::default()
+            }
+        };
+
+        let mut entries = self.entries.write().unwrap();
+        // Drop guests that were deleted or stopped since, so the cache does not grow forever
+        entries.retain(|_, (fetched, _)| fetched.elapsed() < CACHE_TTL);
+        entries.insert(key, (Instant::now(), info.clone()));
+        info
+    }
+
+    /// Forget a guest's details, e.g. after a power action changes them
+    pub fn invalidate(&self, node_id: Uuid, vm_id: &str) {
+        self.entries.write().unwrap().remove(&(node_id, vm_id.to_string()));
+    }
+
+    /// Add `ip_addresses`, `os_name`, `os_version` and `guest_agent` to the running guests of
+    /// one node's normalized listing
+    pub async fn merge_into(&self, client: &SharedClient, node_id: Uuid, vms: &mut [Value]) {
+        let running: Vec<(usize, String)> = vms.iter()
+            .enumerate()
+            .filter(|(_, vm)| vm.get("status").and_then(|s| s.as_str()).is_some_and(|s| s.eq_ignore_ascii_case("running")))
+            .filter_map(|(i, vm)| vm.get("internal_id").and_then(|id| id.as_str()).map(|id| (i, id.to_string())))
+            .collect();
+
+        let fetched: Vec<(usize, GuestInfo)> = stream::iter(running)
+            .map(|(i, vm_id)| async move { (i, self.get(client, node_id, &vm_id).await) })
+            .buffer_unordered(MAX_CONCURRENT_FETCHES)
+            .collect()
+            .await;
+
+        for (i, info) in fetched {
+            if let Some(obj) = vms[i].as_object_mut() {
+                obj.insert("guest_agent".to_string(), Value::Bool(info.agent));
+                obj.insert("ip_addresses".to_string(), serde_json::json!(info.ip_addresses));
+                obj.insert("os_name".to_string(), serde_json::json!(info.os_name));
+                obj.insert("os_version".to_string(), serde_json::json!(info.os_version));
+            }
+        }
+    }
+}
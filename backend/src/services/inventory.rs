@@ -0,0 +1,207 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::{broadcast, Notify};
+use uuid::Uuid;
+use crate::services::vms::list_vms_by_node;
+use crate::state::AppState;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const CHANNEL_CAPACITY: usize = 32;
+
+/// Fields whose change is pushed to clients; usage counters (cpu, mem, netin, ...) move on every
+/// poll and are left to the metrics stream
+const TRACKED_FIELDS: &[&str] = &[
+    "status", "name", "node", "location", "cpus", "maxmem", "memory", "maxdisk", "tags", "template", "lock",
+    "ip_addresses", "os_name",
+];
+
+/// A named server-sent event: `snapshot` carries the full inventory, `diff` only what changed
+#[derive(Clone)]
+pub struct InventoryEvent {
+    pub name: &'static str,
+    pub data: Arc<str>,
+}
+
+#[derive(Serialize)]
+struct FieldChange {
+    old: Value,
+    new: Value,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum VmChange {
+    Added { node_id: Uuid, vm_id: String, vm: Value },
+    /// `vm` is the last known row, so clients can match it the same way as additions
+    Removed { node_id: Uuid, vm_id: String, vm: Value },
+    Updated { node_id: Uuid, vm_id: String, fields: BTreeMap<&'static str, FieldChange>, vm: Value },
+}
+
+/// Keyed by dashboard node and the guest's cluster-wide id, so a Proxmox migration between
+/// hosts shows up as a change of `node` rather than a remove/add pair
+type Snapshot = HashMap<(Uuid, String), Value>;
+
+fn stable_key(vm: &Value) -> Option<String> {
+    vm.get("id").or_else(|| vm.get("name")).and_then(|v| v.as_str()).map(str::to_string)
+}
+
+fn action_id(vm: &Value) -> String {
+    vm.get("internal_id").and_then(|v| v.as_str()).unwrap_or_default().to_string()
+}
+
+fn diff(previous: &Snapshot, current: &Snapshot) -> Vec<VmChange> {
+    let mut changes = Vec::new();
+
+    for ((node_id, key), vm) in current {
+        match previous.get(&(*node_id, key.clone())) {
+            None => changes.push(VmChange::Added { node_id: *node_id, vm_id: action_id(vm), vm: vm.clone() }),
+            Some(old) => {
+                let fields: BTreeMap<&'static str, FieldChange> = TRACKED_FIELDS.iter()
+                    .filter_map(|field| {
+                        let (before, after) = (old.get(*field), vm.get(*field));
+                        (before != after).then(|| (*field, FieldChange {
+                            old: before.cloned().unwrap_or(Value::Null),
+                            new: after.cloned().unwrap_or(Value::Null),
+                        }))
+                    })
+                    .collect();
+                if !fields.is_empty() {
+                    changes.push(VmChange::Updated { node_id: *node_id, vm_id: action_id(vm), fields, vm: vm.clone() });
+                }
+            }
+        }
+    }
+
+    for ((node_id, key), vm) in previous {
+        if !current.contains_key(&(*node_id, key.clone())) {
+            changes.push(VmChange::Removed { node_id: *node_id, vm_id: action_id(vm), vm: vm.clone() });
+        }
+    }
+
+    changes
+}
+
+fn snapshot_event(snapshot: &Snapshot) -> Option<InventoryEvent> {
+    let vms: Vec<&Value> = snapshot.values().collect();
+    let data = serde_json::to_string(&serde_json::json!({
+        "timestamp": chrono::Utc::now().timestamp_millis(),
+        "vms": vms,
+    })).ok()?;
+    Some(InventoryEvent { name: "snapshot", data: Arc::from(data) })
+}
+
+/// Periodically snapshots the VM inventory and broadcasts what changed between snapshots.
+///
+/// Like the metrics collector's live streams it only polls while someone is subscribed, and one poll serves
+/// every open stream.
+#[derive(Clone)]
+pub struct InventoryWatcher {
+    tx: broadcast::Sender<InventoryEvent>,
+    latest: Arc<RwLock<Option<InventoryEvent>>>,
+    wake: Arc<Notify>,
+}
+
+impl Default for InventoryWatcher {
+    fn default() -> Self {
+        Self {
+            tx: broadcast::channel(CHANNEL_CAPACITY).0,
+            latest: Arc::new(RwLock::new(None)),
+            wake: Arc::new(Notify::new()),
+        }
+    }
+}
+
+impl InventoryWatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Subscribe to changes; returns the latest full snapshot when one is already known
+    pub fn subscribe(&self) -> (Option<InventoryEvent>, broadcast::Receiver<InventoryEvent>) {
+        let rx = self.tx.subscribe();
+        let latest = self.latest();
+        if latest.is_none() {
+            // First subscriber: take a baseline now instead of waiting for the next tick
+            self.wake.notify_one();
+        }
+        (latest, rx)
+    }
+
+    pub fn latest(&self) -> Option<InventoryEvent> {
+        self.latest.read().unwrap().clone()
+    }
+
+    pub async fn run(self, state: AppState) {
+        let mut previous: Option<Snapshot> = None;
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {}
+                _ = self.wake.notified() => {}
+            }
+
+            if self.tx.receiver_count() == 0 {
+                // Nobody listening: drop the baseline so a stale one is never served
+                previous = None;
+                *self.latest.write().unwrap() = None;
+                continue;
+            }
+
+            let by_node = match list_vms_by_node(&state).await {
+                Ok(n) => n,
+                Err(e) => {
+                    tracing::error!("Inventory snapshot failed: {}", e);
+                    continue;
+                }
+            };
+
+            let mut current = Snapshot::new();
+            for (node_id, vms) in by_node {
+                match vms {
+                    Some(vms) => {
+                        for vm in vms {
+                            if let Some(key) = stable_key(&vm) {
+                                current.insert((node_id, key), vm);
+                            }
+                        }
+                    }
+                    // An unreachable node keeps its last known guests instead of reporting them removed
+                    None => {
+                        for (key, vm) in previous.iter().flatten().filter(|((id, _), _)| *id == node_id) {
+                            current.insert(key.clone(), vm.clone());
+                        }
+                    }
+                }
+            }
+
+            let snapshot = snapshot_event(&current);
+            let event = match &previous {
+                None => snapshot.clone(),
+                Some(previous) => {
+                    let changes = diff(previous, &current);
+                    if changes.is_empty() {
+                        None
+                    } else {
+                        serde_json::to_string(&serde_json::json!({
+                            "timestamp": chrono::Utc::now().timestamp_millis(),
+                            "changes": changes,
+                        }))
+                        .ok()
+                        .map(|data| InventoryEvent { name: "diff", data: Arc::from(data) })
+                    }
+                }
+            };
+
+            *self.latest.write().unwrap() = snapshot;
+            previous = Some(current);
+            if let Some(event) = event {
+                let _ = self.tx.send(event);
+            }
+        }
+    }
+}
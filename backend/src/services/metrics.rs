@@ -0,0 +1,670 @@
+use futures::future::join_all;
+use serde::{Serialize, Deserialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+use crate::db::DbPool;
+use crate::models::node::{Node, NodeStatus, NodeType};
+use crate::clients::registry::{ClientRegistry, SharedClient};
+use crate::clients::{NodeClient, VmCounters};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(3);
+/// Every node is sampled at least this often, subscribed or not, for alerts and the exporter
+const BACKGROUND_INTERVAL: Duration = Duration::from_secs(30);
+/// A node that takes longer is skipped for the round instead of holding up the others
+const SAMPLE_TIMEOUT: Duration = Duration::from_secs(10);
+const CHANNEL_CAPACITY: usize = 16;
+
+/// A guest followed by the collector: (dashboard node, internal VM id)
+type VmKey = (Uuid, String);
+
+#[derive(Serialize)]
+struct MetricUpdate {
+    cpu: f32,
+    ram: f32,
+    disk: Option<f32>,
+    /// Network throughput in bytes per second
+    net_in: Option<f32>,
+    net_out: Option<f32>,
+    /// Guest disk I/O in bytes per second, derived from counter deltas between polls
+    disk_read: Option<f32>,
+    disk_write: Option<f32>,
+    load: Option<[f32; 3]>,
+    uptime: Option<u64>,
+    timestamp: u64,
+    node_id: String,
+    node_name: String,
+    quorate: Option<bool>,
+    members: Vec<MemberMetric>,
+    /// Set on points replayed from RRD history when a socket opens
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    backfill: bool,
+}
+
+/// Figures for one Proxmox cluster member; the enclosing update aggregates all of them
+#[derive(Serialize)]
+struct MemberMetric {
+    name: String,
+    online: bool,
+    cpu: Option<f32>,
+    ram: Option<f32>,
+    disk: Option<f32>,
+    net_in: Option<f32>,
+    net_out: Option<f32>,
+    load: Option<[f32; 3]>,
+    uptime: Option<u64>,
+}
+
+impl MemberMetric {
+    fn unavailable(name: String, online: bool) -> Self {
+        Self { name, online, cpu: None, ram: None, disk: None, net_in: None, net_out: None, load: None, uptime: None }
+    }
+}
+
+/// Live figures for one guest; rates are per second since the previous sample
+#[derive(Serialize)]
+struct VmMetricUpdate {
+    node_id: String,
+    vm_id: String,
+    status: String,
+    cpu: Option<f32>,
+    ram: Option<f32>,
+    mem_used: u64,
+    mem_total: u64,
+    disk_read: Option<f32>,
+    disk_write: Option<f32>,
+    net_in: Option<f32>,
+    net_out: Option<f32>,
+    uptime: Option<u64>,
+    timestamp: u64,
+}
+
+/// Per-second rate of a cumulative counter, or None on the first sample or after a reset
+fn counter_rate(current: Option<u64>, previous: Option<u64>, elapsed_secs: f64) -> Option<f32> {
+    match (current, previous) {
+        (Some(cur), Some(prev)) if cur >= prev && elapsed_secs > 0.0 => Some(((cur - prev) as f64 / elapsed_secs) as f32),
+        _ => None,
+    }
+}
+
+fn vm_metric_update(
+    node_id: &str,
+    vm_id: &str,
+    current: &VmCounters,
+    previous: Option<&(u64, VmCounters)>,
+    timestamp: u64,
+) -> VmMetricUpdate {
+    let elapsed = previous.map(|(ts, _)| timestamp.saturating_sub(*ts) as f64 / 1000.0).unwrap_or(0.0);
+    let prev = previous.map(|(_, c)| c);
+
+    // Proxmox reports CPU as a ready-made fraction; Incus only gives cumulative CPU time
+    let cpu = match current.cpu_fraction {
+        Some(fraction) => Some((fraction * 100.0) as f32),
+        None => counter_rate(current.cpu_time_ns, prev.and_then(|p| p.cpu_time_ns), elapsed)
+            .map(|ns_per_sec| ns_per_sec / 1e9 / current.cpus.unwrap_or(1.0).max(1.0) as f32 * 100.0),
+    };
+
+    VmMetricUpdate {
+        node_id: node_id.to_string(),
+        vm_id: vm_id.to_string(),
+        status: current.status.clone(),
+        cpu,
+        ram: percent(current.mem_used, current.mem_total),
+        mem_used: current.mem_used,
+        mem_total: current.mem_total,
+        disk_read: counter_rate(current.disk_read, prev.and_then(|p| p.disk_read), elapsed),
+        disk_write: counter_rate(current.disk_write, prev.and_then(|p| p.disk_write), elapsed),
+        net_in: counter_rate(Some(current.net_in), prev.map(|p| p.net_in), elapsed),
+        net_out: counter_rate(Some(current.net_out), prev.map(|p| p.net_out), elapsed),
+        uptime: current.uptime,
+        timestamp,
+    }
+}
+
+#[derive(Deserialize)]
+struct ProxmoxNodeStatus {
+    cpu: Option<f64>,
+    cpuinfo: Option<ProxmoxCpuInfo>,
+    memory: Option<ProxmoxMemory>,
+    rootfs: Option<ProxmoxRootfs>,
+    loadavg: Option<Vec<String>>,
+    uptime: Option<u64>,
+}
+
+/// One averaged sample from `/nodes/{node}/rrddata`; network values are already bytes/s
+#[derive(Deserialize)]
+struct ProxmoxRrdPoint {
+    time: u64,
+    cpu: Option<f64>,
+    maxcpu: Option<f64>,
+    memused: Option<f64>,
+    memtotal: Option<f64>,
+    rootused: Option<f64>,
+    roottotal: Option<f64>,
+    netin: Option<f64>,
+    netout: Option<f64>,
+}
+
+/// Cumulative guest disk counters from the previous poll, per dashboard node and cluster member
+#[derive(Default)]
+struct IoCounters {
+    last: HashMap<(uuid::Uuid, String), (u64, u64, u64)>,
+}
+
+impl IoCounters {
+    /// Turn cumulative read/write byte counters into rates since the previous sample
+    fn rates(&mut self, node_id: uuid::Uuid, member: &str, timestamp_ms: u64, read: u64, write: u64) -> (Option<f32>, Option<f32>) {
+        let previous = self.last.insert((node_id, member.to_string()), (timestamp_ms, read, write));
+        match previous {
+            // Counters reset when guests stop or migrate; skip that sample rather than report garbage
+            Some((prev_ts, prev_read, prev_write)) if timestamp_ms > prev_ts && read >= prev_read && write >= prev_write => {
+                let secs = (timestamp_ms - prev_ts) as f64 / 1000.0;
+                (
+                    Some(((read - prev_read) as f64 / secs) as f32),
+                    Some(((write - prev_write) as f64 / secs) as f32),
+                )
+            }
+            _ => (None, None),
+        }
+    }
+}
+
+fn parse_loadavg(values: &[String]) -> Option<[f32; 3]> {
+    let parsed: Vec<f32> = values.iter().filter_map(|v| v.parse().ok()).collect();
+    match parsed[..] {
+        [one, five, fifteen, ..] => Some([one, five, fifteen]),
+        _ => None,
+    }
+}
+
+#[derive(Deserialize)]
+struct ProxmoxCpuInfo {
+    cpus: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct ProxmoxMemory {
+    used: Option<u64>,
+    total: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct ProxmoxRootfs {
+    used: Option<u64>,
+    total: Option<u64>,
+}
+
+pub fn percent(used: u64, total: u64) -> Option<f32> {
+    if total > 0 {
+        Some((used as f64 / total as f64 * 100.0) as f32)
+    } else {
+        None
+    }
+}
+
+/// Latest RRD sample for a member, used for network throughput
+async fn latest_rrd_point(client: &crate::clients::proxmox::ProxmoxClient, api_url: &str, member: &str) -> Option<ProxmoxRrdPoint> {
+    let url = format!("{}/api2/json/nodes/{}/rrddata?timeframe=hour&cf=AVERAGE", api_url, member);
+    let points = client.get_json::<Vec<ProxmoxRrdPoint>>(&url).await
+        .map_err(|e| tracing::debug!("No RRD data for {}: {}", member, e))
+        .ok()?;
+    // The newest bucket is often still empty, so take the last one with network data
+    points.into_iter().rev().find(|p| p.netin.is_some())
+}
+
+/// Cumulative disk counters of the guests running on each cluster member, for the I/O rate calculation
+async fn guest_disk_counters(client: &crate::clients::proxmox::ProxmoxClient, api_url: &str) -> Option<HashMap<String, (u64, u64)>> {
+    let url = format!("{}/api2/json/cluster/resources?type=vm", api_url);
+    let guests = client.get_json::<Vec<serde_json::Value>>(&url).await.ok()?;
+    let mut per_member: HashMap<String, (u64, u64)> = HashMap::new();
+    for g in &guests {
+        let Some(member) = g["node"].as_str() else { continue };
+        let (read, write) = per_member.entry(member.to_string()).or_default();
+        *read += g["diskread"].as_u64().unwrap_or(0);
+        *write += g["diskwrite"].as_u64().unwrap_or(0);
+    }
+    Some(per_member)
+}
+
+/// Chart history for a node from the last hour of RRD data, merged across cluster members
+async fn fetch_node_history(node: &Node, client: SharedClient) -> Vec<MetricUpdate> {
+    const HISTORY_POINTS: usize = 30;
+
+    let Some(proxmox) = client.as_proxmox() else { return Vec::new() };
+    let cluster = match client.cluster_status().await {
+        Ok(c) => c,
+        Err(_) => return Vec::new(),
+    };
+
+    // time -> (cpu weighted, cpus, mem used, mem total, root used, root total, net in, net out),
+    // each `None` until some member reported it
+    let mut buckets: BTreeMap<u64, [Option<f64>; 8]> = BTreeMap::new();
+    for member in cluster.members.iter().filter(|m| m.online) {
+        let url = format!("{}/api2/json/nodes/{}/rrddata?timeframe=hour&cf=AVERAGE", node.api_url, member.name);
+        let Ok(points) = proxmox.get_json::<Vec<ProxmoxRrdPoint>>(&url).await else { continue };
+        for p in points.into_iter().filter(|p| p.cpu.is_some()) {
+            let cpus = p.maxcpu.unwrap_or(1.0);
+            let fields = [p.cpu.map(|cpu| cpu * cpus), Some(cpus), p.memused, p.memtotal, p.rootused, p.roottotal, p.netin, p.netout];
+            let b = buckets.entry(p.time).or_default();
+            for (sum, value) in b.iter_mut().zip(fields) {
+                if let Some(value) = value {
+                    *sum = Some(sum.unwrap_or(0.0) + value);
+                }
+            }
+        }
+    }
+
+    let ratio = |used: Option<f64>, total: Option<f64>| used.zip(total).and_then(|(u, t)| percent(u as u64, t as u64));
+    let skip = buckets.len().saturating_sub(HISTORY_POINTS);
+    buckets.into_iter()
+        .skip(skip)
+        .map(|(time, b)| MetricUpdate {
+            cpu: b[0].zip(b[1]).filter(|(_, cpus)| *cpus > 0.0).map(|(weighted, cpus)| (weighted / cpus * 100.0) as f32).unwrap_or(0.0),
+            ram: ratio(b[2], b[3]).unwrap_or(0.0),
+            disk: ratio(b[4], b[5]),
+            net_in: b[6].map(|v| v as f32),
+            net_out: b[7].map(|v| v as f32),
+            disk_read: None,
+            disk_write: None,
+            load: None,
+            uptime: None,
+            timestamp: time * 1000,
+            node_id: node.id.to_string(),
+            node_name: node.name.clone(),
+            quorate: None,
+            members: Vec::new(),
+            backfill: true,
+        })
+        .collect()
+}
+
+async fn fetch_node_metrics(node: &Node, client: SharedClient, io_counters: &Mutex<IoCounters>) -> Option<MetricUpdate> {
+    match node.node_type {
+        NodeType::Proxmox => {
+            let client = client.as_proxmox()?;
+
+            let cluster = match client.cluster_status().await {
+                Ok(c) => c,
+                Err(e) => {
+                    tracing::warn!("Failed to get Proxmox cluster status for {}: {}", node.name, e);
+                    return None;
+                }
+            };
+
+            // Totals across members so the node-level figures stay comparable to a single host
+            let (mut cpu_weighted, mut cpu_count) = (0.0_f64, 0_u64);
+            let (mut mem_used, mut mem_total) = (0_u64, 0_u64);
+            let (mut disk_used, mut disk_total) = (0_u64, 0_u64);
+            let (mut net_in, mut net_out): (Option<f64>, Option<f64>) = (None, None);
+            let mut load_sum = [0.0_f32; 3];
+            let mut load_count = 0;
+            let mut uptime: Option<u64> = None;
+            let mut members = Vec::with_capacity(cluster.members.len());
+
+            for member in cluster.members {
+                if !member.online {
+                    members.push(MemberMetric::unavailable(member.name, false));
+                    continue;
+                }
+
+                // GET /api2/json/nodes/{node}/status
+                let status_url = format!("{}/api2/json/nodes/{}/status", node.api_url, member.name);
+                tracing::debug!("Fetching metrics from: {}", status_url);
+
+                let status = match client.get_json::<ProxmoxNodeStatus>(&status_url).await {
+                    Ok(s) => s,
+                    Err(e) => {
+                        tracing::warn!("Failed to fetch metrics from {}/{}: {}", node.name, member.name, e);
+                        members.push(MemberMetric::unavailable(member.name, true));
+                        continue;
+                    }
+                };
+
+                let cpus = status.cpuinfo.and_then(|c| c.cpus).unwrap_or(1);
+                let cpu = status.cpu.unwrap_or(0.0);
+                cpu_weighted += cpu * cpus as f64;
+                cpu_count += cpus;
+
+                let (m_used, m_total) = status.memory
+                    .map(|m| (m.used.unwrap_or(0), m.total.unwrap_or(0)))
+                    .unwrap_or((0, 0));
+                let (d_used, d_total) = status.rootfs
+                    .map(|r| (r.used.unwrap_or(0), r.total.unwrap_or(0)))
+                    .unwrap_or((0, 0));
+                mem_used += m_used;
+                mem_total += m_total;
+                disk_used += d_used;
+                disk_total += d_total;
+                uptime = uptime.max(status.uptime);
+
+                let load = status.loadavg.as_deref().and_then(parse_loadavg);
+                if let Some(l) = load {
+                    load_sum.iter_mut().zip(l).for_each(|(sum, v)| *sum += v);
+                    load_count += 1;
+                }
+
+                let rrd = latest_rrd_point(client, &node.api_url, &member.name).await;
+                let member_in = rrd.as_ref().and_then(|p| p.netin);
+                let member_out = rrd.as_ref().and_then(|p| p.netout);
+                if let Some(v) = member_in {
+                    *net_in.get_or_insert(0.0) += v;
+                }
+                if let Some(v) = member_out {
+                    *net_out.get_or_insert(0.0) += v;
+                }
+
+                members.push(MemberMetric {
+                    name: member.name,
+                    online: true,
+                    cpu: Some((cpu * 100.0) as f32),
+                    ram: percent(m_used, m_total),
+                    disk: percent(d_used, d_total),
+                    net_in: member_in.map(|v| v as f32),
+                    net_out: member_out.map(|v| v as f32),
+                    load,
+                    uptime: status.uptime,
+                });
+            }
+
+            if cpu_count == 0 {
+                tracing::warn!("No reachable cluster members for {}", node.name);
+                return None;
+            }
+
+            let timestamp = chrono::Utc::now().timestamp_millis() as u64;
+            // Only members polled above count, each against its own previous sample, so a member
+            // dropping out or coming back does not show up as a burst of I/O
+            let (mut disk_read, mut disk_write): (Option<f32>, Option<f32>) = (None, None);
+            if let Some(counters) = guest_disk_counters(client, &node.api_url).await {
+                let mut io_counters = io_counters.lock().unwrap();
+                for member in members.iter().filter(|m| m.cpu.is_some()) {
+                    let (read, write) = counters.get(&member.name).copied().unwrap_or((0, 0));
+                    let (r, w) = io_counters.rates(node.id, &member.name, timestamp, read, write);
+                    if let Some(r) = r {
+                        *disk_read.get_or_insert(0.0) += r;
+                    }
+                    if let Some(w) = w {
+                        *disk_write.get_or_insert(0.0) += w;
+                    }
+                }
+            }
+
+            Some(MetricUpdate {
+                cpu: (cpu_weighted / cpu_count as f64 * 100.0) as f32,
+                ram: percent(mem_used, mem_total).unwrap_or(0.0),
+                disk: percent(disk_used, disk_total),
+                net_in: net_in.map(|v| v as f32),
+                net_out: net_out.map(|v| v as f32),
+                disk_read,
+                disk_write,
+                load: (load_count > 0).then(|| load_sum.map(|v| v / load_count as f32)),
+                uptime,
+                timestamp,
+                node_id: node.id.to_string(),
+                node_name: node.name.clone(),
+                quorate: Some(cluster.quorate),
+                members,
+                backfill: false,
+            })
+        }
+        NodeType::Incus => {
+            // Incus metrics would use different API endpoints
+            // For now, return placeholder
+            Some(MetricUpdate {
+                cpu: 0.0,
+                ram: 0.0,
+                disk: None,
+                net_in: None,
+                net_out: None,
+                disk_read: None,
+                disk_write: None,
+                load: None,
+                uptime: None,
+                timestamp: chrono::Utc::now().timestamp_millis() as u64,
+                node_id: node.id.to_string(),
+                node_name: node.name.clone(),
+                quorate: None,
+                members: Vec::new(),
+                backfill: false,
+            })
+        }
+    }
+}
+
+/// Latest health check and figures of a node, as taken by the collector
+#[derive(Clone)]
+pub struct NodeSnapshot {
+    pub up: bool,
+    pub health_seconds: f64,
+    pub sample: Option<NodeSample>,
+    taken_at: Instant,
+}
+
+/// Single background poller for node and guest metrics.
+///
+/// Every node and every streamed guest gets a broadcast channel carrying serialized updates.
+/// Subscribed nodes and guests are polled every few seconds and any number of open dashboards
+/// share one request per interval. All other nodes are only sampled in the background, so the
+/// alert engine and the Prometheus exporter read snapshots instead of polling nodes themselves.
+#[derive(Clone)]
+pub struct MetricsCollector {
+    channels: Arc<Mutex<HashMap<Uuid, broadcast::Sender<Arc<str>>>>>,
+    vm_channels: Arc<Mutex<HashMap<VmKey, broadcast::Sender<Arc<str>>>>>,
+    added: broadcast::Sender<Uuid>,
+    latest: Arc<Mutex<HashMap<Uuid, NodeSnapshot>>>,
+}
+
+impl Default for MetricsCollector {
+    fn default() -> Self {
+        Self {
+            channels: Arc::new(Mutex::new(HashMap::new())),
+            vm_channels: Arc::new(Mutex::new(HashMap::new())),
+            added: broadcast::channel(CHANNEL_CAPACITY).0,
+            latest: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+impl MetricsCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&self, node_id: Uuid) -> broadcast::Receiver<Arc<str>> {
+        self.channels.lock().unwrap()
+            .entry(node_id)
+            .or_insert_with(|| broadcast::channel(CHANNEL_CAPACITY).0)
+            .subscribe()
+    }
+
+    pub fn subscribe_vm(&self, node_id: Uuid, vm_id: &str) -> broadcast::Receiver<Arc<str>> {
+        self.vm_channels.lock().unwrap()
+            .entry((node_id, vm_id.to_string()))
+            .or_insert_with(|| broadcast::channel(CHANNEL_CAPACITY).0)
+            .subscribe()
+    }
+
+    /// Ids of nodes added after subscribing, so open dashboards can start following them
+    pub fn subscribe_added(&self) -> broadcast::Receiver<Uuid> {
+        self.added.subscribe()
+    }
+
+    pub fn node_added(&self, node_id: Uuid) {
+        let _ = self.added.send(node_id);
+    }
+
+    /// The node's most recent snapshot; `None` until the collector first reached it
+    pub fn snapshot(&self, node_id: Uuid) -> Option<NodeSnapshot> {
+        self.latest.lock().unwrap().get(&node_id).cloned()
+    }
+
+    fn due(&self, node_id: Uuid, active: &HashSet<Uuid>) -> bool {
+        active.contains(&node_id)
+            || self.latest.lock().unwrap().get(&node_id).is_none_or(|s| s.taken_at.elapsed() >= BACKGROUND_INTERVAL)
+    }
+
+    /// Nodes with live subscribers; channels nobody listens to any more are dropped
+    fn active_nodes(&self) -> HashSet<Uuid> {
+        let mut channels = self.channels.lock().unwrap();
+        channels.retain(|_, tx| tx.receiver_count() > 0);
+        channels.keys().copied().collect()
+    }
+
+    fn active_vms(&self) -> HashSet<VmKey> {
+        let mut channels = self.vm_channels.lock().unwrap();
+        channels.retain(|_, tx| tx.receiver_count() > 0);
+        channels.keys().cloned().collect()
+    }
+
+    fn publish(&self, node_id: Uuid, update: &MetricUpdate) {
+        let Some(tx) = self.channels.lock().unwrap().get(&node_id).cloned() else { return };
+        match serde_json::to_string(update) {
+            Ok(msg) => {
+                let _ = tx.send(Arc::from(msg));
+            }
+            Err(e) => tracing::error!("Failed to serialize metrics for {}: {}", node_id, e),
+        }
+    }
+
+    /// Sample one streamed guest and publish it with rates against its previous sample
+    async fn poll_vm(&self, clients: &ClientRegistry, key: VmKey, previous: &Mutex<HashMap<VmKey, (u64, VmCounters)>>) {
+        let (node_id, vm_id) = &key;
+        let poll = async {
+            let client = clients.get(*node_id).await?;
+            client.vm_counters(vm_id).await
+        };
+        let counters = match tokio::time::timeout(SAMPLE_TIMEOUT, poll).await {
+            Ok(Ok(c)) => c,
+            Ok(Err(e)) => {
+                tracing::warn!("Failed to fetch metrics for VM {}: {}", vm_id, e);
+                return;
+            }
+            Err(_) => {
+                tracing::warn!("Metrics for VM {} timed out after {:?}", vm_id, SAMPLE_TIMEOUT);
+                return;
+            }
+        };
+
+        let timestamp = chrono::Utc::now().timestamp_millis() as u64;
+        let update = vm_metric_update(&node_id.to_string(), vm_id, &counters, previous.lock().unwrap().get(&key), timestamp);
+        let Some(tx) = self.vm_channels.lock().unwrap().get(&key).cloned() else { return };
+        match serde_json::to_string(&update) {
+            Ok(msg) => {
+                let _ = tx.send(Arc::from(msg));
+            }
+            Err(e) => tracing::error!("Failed to serialize metrics for VM {}: {}", vm_id, e),
+        }
+        previous.lock().unwrap().insert(key, (timestamp, counters));
+    }
+
+    /// Health check plus figures for one node; the update is only published to subscribers
+    async fn poll_node(&self, node: &Node, clients: &ClientRegistry, io_counters: &Mutex<IoCounters>) {
+        let started = Instant::now();
+        let poll = async {
+            let client = clients.client_for(node)?;
+            let up = matches!(client.check_health().await, Ok(NodeStatus::Online));
+            let health_seconds = started.elapsed().as_secs_f64();
+            let update = if up { fetch_node_metrics(node, client, io_counters).await } else { None };
+            anyhow::Ok((up, health_seconds, update))
+        };
+
+        let (up, health_seconds, update) = match tokio::time::timeout(SAMPLE_TIMEOUT, poll).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(e)) => {
+                tracing::warn!("Skipping metrics for {}: {}", node.name, e);
+                (false, started.elapsed().as_secs_f64(), None)
+            }
+            Err(_) => {
+                tracing::warn!("Metrics for {} timed out after {:?}", node.name, SAMPLE_TIMEOUT);
+                (false, SAMPLE_TIMEOUT.as_secs_f64(), None)
+            }
+        };
+
+        if let Some(update) = &update {
+            self.publish(node.id, update);
+        }
+        // Incus node figures are still placeholders; handing zeros to alerts or the exporter would be misleading
+        let sample = update.filter(|_| node.node_type != NodeType::Incus).map(NodeSample::from);
+        self.latest.lock().unwrap().insert(node.id, NodeSnapshot { up, health_seconds, sample, taken_at: Instant::now() });
+    }
+
+    pub async fn run(self, pool: DbPool, clients: ClientRegistry) {
+        let io_counters = Mutex::new(IoCounters::default());
+        let vm_previous: Mutex<HashMap<VmKey, (u64, VmCounters)>> = Mutex::new(HashMap::new());
+        let mut interval = tokio::time::interval(POLL_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+
+            // Guests are polled side by side so one slow node cannot hold up every other stream
+            let active_vms = self.active_vms();
+            vm_previous.lock().unwrap().retain(|key, _| active_vms.contains(key));
+            join_all(active_vms.into_iter().map(|key| self.poll_vm(&clients, key, &vm_previous))).await;
+
+            let nodes = match sqlx::query_as::<_, Node>(
+                "SELECT id, name, node_type, api_url, api_key, api_secret, status, last_check, created_at, tls_mode, tls_ca_cert, tls_fingerprint, tls_pending_fingerprint FROM nodes"
+            )
+            .fetch_all(&pool)
+            .await
+            {
+                Ok(n) => n,
+                Err(e) => {
+                    tracing::error!("Failed to fetch nodes for metrics: {}", e);
+                    continue;
+                }
+            };
+
+            // Forget deleted nodes
+            let ids: HashSet<Uuid> = nodes.iter().map(|n| n.id).collect();
+            self.latest.lock().unwrap().retain(|id, _| ids.contains(id));
+            io_counters.lock().unwrap().last.retain(|(id, _), _| ids.contains(id));
+
+            let active = self.active_nodes();
+            let due = nodes.iter().filter(|n| self.due(n.id, &active));
+            join_all(due.map(|node| self.poll_node(node, &clients, &io_counters))).await;
+        }
+    }
+}
+
+/// Serialized RRD history for a node, replayed to a socket before live updates start
+pub async fn node_history(node: &Node, client: SharedClient) -> Vec<String> {
+    fetch_node_history(node, client).await
+        .iter()
+        .filter_map(|point| serde_json::to_string(point).ok())
+        .collect()
+}
+
+/// Point-in-time node figures for alerts and the Prometheus exporter
+#[derive(Clone)]
+pub struct NodeSample {
+    pub cpu: f32,
+    pub ram: f32,
+    pub disk: Option<f32>,
+    pub net_in: Option<f32>,
+    pub net_out: Option<f32>,
+    pub disk_read: Option<f32>,
+    pub disk_write: Option<f32>,
+    pub load: Option<[f32; 3]>,
+    pub uptime: Option<u64>,
+    pub quorate: Option<bool>,
+}
+
+impl From<MetricUpdate> for NodeSample {
+    fn from(update: MetricUpdate) -> Self {
+        Self {
+            cpu: update.cpu,
+            ram: update.ram,
+            disk: update.disk,
+            net_in: update.net_in,
+            net_out: update.net_out,
+            disk_read: update.disk_read,
+            disk_write: update.disk_write,
+            load: update.load,
+            uptime: update.uptime,
+            quorate: update.quorate,
+        }
+    }
+}
@@ -1,2 +1,19 @@
 pub mod vms;
+pub mod guest_info;
 pub mod vnc;
+pub mod vnc_sessions;
+pub mod vnc_recording;
+pub mod rfb;
+pub mod console_input;
+pub mod cloud_init;
+pub mod resize;
+pub mod console_shares;
+pub mod ws_tickets;
+pub mod terminal;
+pub mod nodes;
+pub mod metrics;
+pub mod telemetry;
+pub mod alerts;
+pub mod notifications;
+pub mod webhooks;
+pub mod inventory;
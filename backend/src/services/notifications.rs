@@ -0,0 +1,126 @@
+use lettre::{
+    message::Mailbox,
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use serde::Serialize;
+use std::time::Duration;
+use uuid::Uuid;
+use crate::models::alert::{AlertChannel, AlertChannelKind, AlertMetric, AlertState};
+
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What gets sent to a channel when an alert fires or resolves
+#[derive(Debug, Serialize, Clone)]
+pub struct AlertNotification {
+    pub event_id: Uuid,
+    pub rule_id: Uuid,
+    pub rule_name: String,
+    pub metric: AlertMetric,
+    pub state: AlertState,
+    pub node_id: Uuid,
+    pub node_name: String,
+    pub vm_id: Option<String>,
+    pub value: Option<f64>,
+    pub threshold: Option<f64>,
+    pub message: String,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+}
+
+impl AlertNotification {
+    fn summary(&self) -> String {
+        let prefix = match self.state {
+            AlertState::Firing => "🔥 FIRING",
+            AlertState::Resolved => "✅ RESOLVED",
+        };
+        format!("[{}] {}: {}", prefix, self.rule_name, self.message)
+    }
+}
+
+/// Check a channel's kind-specific config before it is saved
+pub fn validate_channel_config(kind: AlertChannelKind, config: &serde_json::Value) -> anyhow::Result<()> {
+    match kind {
+        AlertChannelKind::Webhook | AlertChannelKind::Slack => {
+            let url = config["url"].as_str().ok_or_else(|| anyhow::anyhow!("config.url is required"))?;
+            let parsed = reqwest::Url::parse(url)?;
+            if !matches!(parsed.scheme(), "http" | "https") {
+                anyhow::bail!("config.url must be http(s)");
+            }
+        }
+        AlertChannelKind::Email => {
+            let recipients = config["to"].as_array().ok_or_else(|| anyhow::anyhow!("config.to must be a list of addresses"))?;
+            if recipients.is_empty() {
+                anyhow::bail!("config.to must not be empty");
+            }
+            for recipient in recipients {
+                recipient.as_str()
+                    .ok_or_else(|| anyhow::anyhow!("config.to entries must be strings"))?
+                    .parse::<Mailbox>()?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn deliver(channel: &AlertChannel, notification: &AlertNotification) -> anyhow::Result<()> {
+    match channel.kind {
+        AlertChannelKind::Webhook => post_json(&channel.config, notification).await,
+        AlertChannelKind::Slack => {
+            post_json(&channel.config, &serde_json::json!({ "text": notification.summary() })).await
+        }
+        AlertChannelKind::Email => send_email(&channel.config, notification).await,
+    }
+}
+
+async fn post_json<T: Serialize>(config: &serde_json::Value, body: &T) -> anyhow::Result<()> {
+    let url = config["url"].as_str().ok_or_else(|| anyhow::anyhow!("Channel has no url"))?;
+    let resp = reqwest::Client::new()
+        .post(url)
+        .timeout(DELIVERY_TIMEOUT)
+        .json(body)
+        .send()
+        .await?;
+
+    if !resp.status().is_success() {
+        anyhow::bail!("Webhook returned {}", resp.status());
+    }
+    Ok(())
+}
+
+async fn send_email(config: &serde_json::Value, notification: &AlertNotification) -> anyhow::Result<()> {
+    let host = std::env::var("SMTP_HOST").map_err(|_| anyhow::anyhow!("SMTP_HOST is not configured"))?;
+    let from: Mailbox = std::env::var("SMTP_FROM")
+        .unwrap_or_else(|_| "fossvps@localhost".to_string())
+        .parse()?;
+
+    let mut builder = Message::builder()
+        .from(from)
+        .subject(notification.summary());
+    for recipient in config["to"].as_array().into_iter().flatten().filter_map(|r| r.as_str()) {
+        builder = builder.to(recipient.parse()?);
+    }
+
+    let body = format!(
+        "{}\n\nNode: {} ({})\nVM: {}\nValue: {}\nThreshold: {}\nTime: {}\n",
+        notification.message,
+        notification.node_name,
+        notification.node_id,
+        notification.vm_id.as_deref().unwrap_or("-"),
+        notification.value.map(|v| format!("{:.1}", v)).unwrap_or_else(|| "-".to_string()),
+        notification.threshold.map(|v| format!("{:.1}", v)).unwrap_or_else(|| "-".to_string()),
+        notification.timestamp.to_rfc3339(),
+    );
+    let email = builder.body(body)?;
+
+    let mut transport = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&host)?
+        .timeout(Some(DELIVERY_TIMEOUT));
+    if let Some(port) = std::env::var("SMTP_PORT").ok().and_then(|p| p.parse().ok()) {
+        transport = transport.port(port);
+    }
+    if let (Ok(user), Ok(pass)) = (std::env::var("SMTP_USERNAME"), std::env::var("SMTP_PASSWORD")) {
+        transport = transport.credentials(Credentials::new(user, pass));
+    }
+
+    transport.build().send(email).await?;
+    Ok(())
+}
@@ -0,0 +1,134 @@
+use crate::models::resize::{ResizeLimits, VmResize};
+
+/// Below this most guests do not boot at all
+const MIN_MEMORY_MIB: u64 = 64;
+
+/// Check a resize against the guest's current shape and what its host can give it
+pub fn validate(resize: &VmResize, limits: &ResizeLimits) -> anyhow::Result<()> {
+    if resize.sockets.is_some() && !limits.supports_sockets {
+        anyhow::bail!("This guest has no CPU sockets setting");
+    }
+    if resize.balloon_mib.is_some() && !limits.supports_balloon {
+        anyhow::bail!("This guest has no memory balloon");
+    }
+
+    let cores = resize.cores.unwrap_or(limits.cores);
+    let sockets = resize.sockets.unwrap_or(limits.sockets);
+    if cores == 0 || sockets == 0 {
+        anyhow::bail!("Cores and sockets must be at least 1");
+    }
+    let vcpus = cores.saturating_mul(sockets);
+    if vcpus > limits.host_cpus {
+        anyhow::bail!("{} vCPUs exceed the host's {} CPUs", vcpus, limits.host_cpus);
+    }
+
+    let memory_mib = resize.memory_mib.unwrap_or(limits.memory_mib);
+    if let Some(requested) = resize.memory_mib {
+        if requested < MIN_MEMORY_MIB {
+            anyhow::bail!("Memory must be at least {} MiB", MIN_MEMORY_MIB);
+        }
+        if requested > limits.host_memory_mib {
+            anyhow::bail!("{} MiB of memory exceeds the host's {} MiB", requested, limits.host_memory_mib);
+        }
+    }
+    if let Some(balloon) = resize.balloon_mib {
+        if balloon > memory_mib {
+            anyhow::bail!("Balloon minimum of {} MiB is above the {} MiB of memory", balloon, memory_mib);
+        }
+    }
+
+    if let Some(grow) = resize.disk_grow_gib {
+        if grow == 0 {
+            anyhow::bail!("Disk growth must be at least 1 GiB");
+        }
+        let Some(disk) = &limits.disk else {
+            anyhow::bail!("Disk {} not found", resize.disk.as_deref().unwrap_or("(boot disk)"));
+        };
+        if let Some(free) = limits.disk_free_gib {
+            if grow > free {
+                anyhow::bail!("Growing {} by {} GiB exceeds the {} GiB free on its storage", disk, grow, free);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> ResizeLimits {
+        ResizeLimits {
+            host_cpus: 16,
+            host_memory_mib: 32768,
+            cores: 2,
+            sockets: 1,
+            memory_mib: 2048,
+            supports_sockets: true,
+            supports_balloon: true,
+            disk: Some("scsi0".into()),
+            disk_free_gib: Some(100),
+        }
+    }
+
+    fn error(resize: VmResize, limits: &ResizeLimits) -> String {
+        validate(&resize, limits).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn accepts_changes_within_host_capacity() {
+        let resize = VmResize {
+            cores: Some(4),
+            sockets: Some(4),
+            memory_mib: Some(32768),
+            balloon_mib: Some(1024),
+            disk_grow_gib: Some(100),
+            ..Default::default()
+        };
+        assert!(validate(&resize, &limits()).is_ok());
+    }
+
+    #[test]
+    fn vcpus_count_cores_times_sockets() {
+        // 9 cores on the current single socket fit, but not on two sockets
+        assert!(validate(&VmResize { cores: Some(9), ..Default::default() }, &limits()).is_ok());
+        assert!(error(VmResize { cores: Some(9), sockets: Some(2), ..Default::default() }, &limits()).contains("18 vCPUs"));
+        // The unchanged 2 cores still count against new sockets
+        assert!(error(VmResize { sockets: Some(9), ..Default::default() }, &limits()).contains("18 vCPUs"));
+        assert!(error(VmResize { cores: Some(0), ..Default::default() }, &limits()).contains("at least 1"));
+    }
+
+    #[test]
+    fn memory_must_fit_between_minimum_and_host() {
+        assert!(error(VmResize { memory_mib: Some(32), ..Default::default() }, &limits()).contains("at least 64"));
+        assert!(error(VmResize { memory_mib: Some(32769), ..Default::default() }, &limits()).contains("exceeds"));
+    }
+
+    #[test]
+    fn balloon_stays_below_memory() {
+        assert!(error(VmResize { balloon_mib: Some(4096), ..Default::default() }, &limits()).contains("above"));
+        // Checked against the new memory when both change
+        assert!(validate(&VmResize { memory_mib: Some(8192), balloon_mib: Some(4096), ..Default::default() }, &limits()).is_ok());
+    }
+
+    #[test]
+    fn unsupported_settings_are_rejected() {
+        let lxc = ResizeLimits { supports_sockets: false, supports_balloon: false, ..limits() };
+        assert!(error(VmResize { sockets: Some(1), ..Default::default() }, &lxc).contains("sockets"));
+        assert!(error(VmResize { balloon_mib: Some(0), ..Default::default() }, &lxc).contains("balloon"));
+    }
+
+    #[test]
+    fn disk_growth_needs_a_disk_and_free_space() {
+        assert!(error(VmResize { disk_grow_gib: Some(0), ..Default::default() }, &limits()).contains("at least 1 GiB"));
+        assert!(error(VmResize { disk_grow_gib: Some(101), ..Default::default() }, &limits()).contains("100 GiB free"));
+
+        let missing = ResizeLimits { disk: None, ..limits() };
+        let resize = VmResize { disk_grow_gib: Some(1), disk: Some("virtio3".into()), ..Default::default() };
+        assert!(error(resize, &missing).contains("virtio3 not found"));
+
+        // Storages that do not report free space are left to the node to refuse
+        let unknown = ResizeLimits { disk_free_gib: None, ..limits() };
+        assert!(validate(&VmResize { disk_grow_gib: Some(1000), ..Default::default() }, &unknown).is_ok());
+    }
+}
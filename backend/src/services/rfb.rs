@@ -0,0 +1,255 @@
+/// Where a client-to-server RFB stream is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ClientState {
+    Version,
+    SecurityChoice,
+    AuthResponse,
+    ClientInit,
+    Messages,
+}
+
+/// Parses the browser's side of an RFB connection to know where its messages start, so the
+/// backend can slip in its own key events between them. With `drop_input` it also removes
+/// everything that would act on the guest (keys, pointer, clipboard, resolution and power
+/// requests), so a viewer can watch without interfering; handshake and display negotiation
+/// pass through unchanged.
+pub struct RfbClientStream {
+    state: ClientState,
+    buf: Vec<u8>,
+    drop_input: bool,
+    /// Set once the stream could not be parsed; interactive sessions keep working without it
+    lost: bool,
+}
+
+impl RfbClientStream {
+    pub fn new(drop_input: bool) -> Self {
+        Self { state: ClientState::Version, buf: Vec::new(), drop_input, lost: false }
+    }
+
+    /// Returns the bytes to forward upstream. When dropping input, messages split across
+    /// frames are held back until complete, and anything unparseable is an error since input
+    /// could not be told apart; otherwise every byte is forwarded as it arrives.
+    pub fn process(&mut self, bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
+        if self.drop_input {
+            return self.parse(bytes);
+        }
+        if !self.lost {
+            if let Err(e) = self.parse(bytes) {
+                tracing::debug!("Stopped following RFB client stream: {}", e);
+                self.lost = true;
+                self.buf.clear();
+            }
+        }
+        Ok(bytes.to_vec())
+    }
+
+    /// Whether a client message can be inserted upstream without splitting one of the browser's
+    pub fn at_message_boundary(&self) -> bool {
+        !self.lost && self.state == ClientState::Messages && self.buf.is_empty()
+    }
+
+    fn parse(&mut self, bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
+        self.buf.extend_from_slice(bytes);
+        let mut out = Vec::new();
+
+        loop {
+            let (len, forward) = match self.state {
+                ClientState::Version => {
+                    if self.buf.len() < 12 {
+                        break;
+                    }
+                    let minor = std::str::from_utf8(&self.buf[8..11]).ok().and_then(|m| m.parse::<u16>().ok()).unwrap_or(0);
+                    if minor < 7 {
+                        // RFB 3.3 lets the server pick the security type, which we do not see here
+                        anyhow::bail!("RFB 3.{} clients are not supported", minor);
+                    }
+                    self.state = ClientState::SecurityChoice;
+                    (12, true)
+                }
+                ClientState::SecurityChoice => {
+                    let Some(&choice) = self.buf.first() else { break };
+                    self.state = match choice {
+                        1 => ClientState::ClientInit,
+                        2 => ClientState::AuthResponse,
+                        other => anyhow::bail!("Unsupported RFB security type {}", other),
+                    };
+                    (1, true)
+                }
+                ClientState::AuthResponse => {
+                    if self.buf.len() < 16 {
+                        break;
+                    }
+                    self.state = ClientState::ClientInit;
+                    (16, true)
+                }
+                ClientState::ClientInit => {
+                    if self.buf.is_empty() {
+                        break;
+                    }
+                    self.state = ClientState::Messages;
+                    (1, true)
+                }
+                ClientState::Messages => match client_message(&self.buf)? {
+                    Some((len, is_input)) => (len, !(is_input && self.drop_input)),
+                    None => break,
+                },
+            };
+
+            let message = self.buf.drain(..len);
+            if forward {
+                out.extend(message);
+            }
+        }
+
+        Ok(out)
+    }
+}
+
+/// Length of the client message at the start of `buf` and whether it acts on the guest;
+/// `None` while more bytes are needed
+fn client_message(buf: &[u8]) -> anyhow::Result<Option<(usize, bool)>> {
+    let Some(&kind) = buf.first() else { return Ok(None) };
+    let have = |n: usize| buf.len() >= n;
+
+    let (len, input) = match kind {
+        // SetPixelFormat
+        0 => (20, false),
+        // SetEncodings
+        2 if have(4) => (4 + 4 * u16::from_be_bytes([buf[2], buf[3]]) as usize, false),
+        // FramebufferUpdateRequest
+        3 => (10, false),
+        // KeyEvent
+        4 => (8, true),
+        // PointerEvent
+        5 => (6, true),
+        // ClientCutText; a negative length is the extended clipboard format
+        6 if have(8) => (8 + i32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]).unsigned_abs() as usize, true),
+        // EnableContinuousUpdates
+        150 => (10, false),
+        // ClientFence
+        248 if have(9) => (9 + buf[8] as usize, false),
+        // xvp: shutdown, reboot or reset the guest
+        250 => (4, true),
+        // SetDesktopSize changes the guest's resolution
+        251 if have(8) => (8 + 16 * buf[6] as usize, true),
+        // QEMU extended key event
+        255 if have(2) && buf[1] == 0 => (12, true),
+        // QEMU audio enable/disable/set format
+        255 if have(4) && buf[1] == 1 => match u16::from_be_bytes([buf[2], buf[3]]) {
+            0 | 1 => (4, false),
+            2 => (10, false),
+            other => anyhow::bail!("Unknown QEMU audio operation {}", other),
+        },
+        2 | 6 | 248 | 251 | 255 if buf.len() < 9 => return Ok(None),
+        other => anyhow::bail!("Unknown RFB client message type {}", other),
+    };
+
+    Ok(have(len).then_some((len, input)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Version, security type None and ClientInit, as sent by noVNC
+    fn handshake() -> Vec<u8> {
+        let mut bytes = b"RFB 003.008\n".to_vec();
+        bytes.extend([1, 1]);
+        bytes
+    }
+
+    fn key_event(down: bool) -> Vec<u8> {
+        vec![4, down as u8, 0, 0, 0, 0, 0, 0x61]
+    }
+
+    fn update_request() -> Vec<u8> {
+        vec![3, 1, 0, 0, 0, 0, 4, 0, 3, 0]
+    }
+
+    #[test]
+    fn set_encodings_length_counts_four_bytes_per_encoding() {
+        let mut msg = vec![2, 0, 0, 3];
+        msg.extend([0u8; 12]);
+        assert_eq!(client_message(&msg).unwrap(), Some((16, false)));
+        assert_eq!(client_message(&msg[..15]).unwrap(), None);
+        assert_eq!(client_message(&msg[..2]).unwrap(), None);
+    }
+
+    #[test]
+    fn client_fence_length_includes_payload() {
+        let mut msg = vec![248, 0, 0, 0, 0, 0, 0, 1, 5];
+        msg.extend([0u8; 5]);
+        assert_eq!(client_message(&msg).unwrap(), Some((14, false)));
+        assert_eq!(client_message(&msg[..13]).unwrap(), None);
+        assert_eq!(client_message(&msg[..8]).unwrap(), None);
+    }
+
+    #[test]
+    fn qemu_sub_types() {
+        let extended_key = [255, 0, 0, 1, 0, 0, 0, 0x61, 0, 0, 0, 0x1e];
+        assert_eq!(client_message(&extended_key).unwrap(), Some((12, true)));
+        assert_eq!(client_message(&[255, 1, 0, 0]).unwrap(), Some((4, false)));
+        assert_eq!(client_message(&[255, 1, 0, 1]).unwrap(), Some((4, false)));
+        assert_eq!(client_message(&[255, 1, 0, 2, 3, 2, 0, 0, 0xac, 0x44]).unwrap(), Some((10, false)));
+        assert!(client_message(&[255, 1, 0, 9]).is_err());
+        assert!(client_message(&[255, 7, 0, 0, 0, 0, 0, 0, 0]).is_err());
+        assert_eq!(client_message(&[255]).unwrap(), None);
+    }
+
+    #[test]
+    fn unknown_message_type_is_an_error() {
+        assert!(client_message(&[42, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn view_only_drops_input_split_across_frames() {
+        let mut stream = RfbClientStream::new(true);
+        assert_eq!(stream.process(&handshake()).unwrap(), handshake());
+
+        let mut frame = update_request();
+        frame.extend(&key_event(true)[..3]);
+        assert_eq!(stream.process(&frame).unwrap(), update_request());
+        assert!(!stream.at_message_boundary());
+
+        let mut frame = key_event(true)[3..].to_vec();
+        frame.extend(&update_request()[..4]);
+        assert_eq!(stream.process(&frame).unwrap(), Vec::<u8>::new());
+
+        assert_eq!(stream.process(&update_request()[4..]).unwrap(), update_request());
+        assert!(stream.at_message_boundary());
+    }
+
+    #[test]
+    fn view_only_rejects_unparseable_streams() {
+        let mut stream = RfbClientStream::new(true);
+        stream.process(&handshake()).unwrap();
+        assert!(stream.process(&[42]).is_err());
+
+        let mut old = RfbClientStream::new(true);
+        assert!(old.process(b"RFB 003.003\n").is_err());
+    }
+
+    #[test]
+    fn interactive_streams_forward_everything() {
+        let mut stream = RfbClientStream::new(false);
+        let mut frame = handshake();
+        frame.extend(key_event(true));
+        assert_eq!(stream.process(&frame).unwrap(), frame);
+        assert!(stream.at_message_boundary());
+
+        // Unparseable input is still forwarded, but no longer offers insertion points
+        assert_eq!(stream.process(&[42, 1]).unwrap(), vec![42, 1]);
+        assert!(!stream.at_message_boundary());
+    }
+
+    #[test]
+    fn vnc_auth_response_is_forwarded() {
+        let mut stream = RfbClientStream::new(true);
+        let mut frame = b"RFB 003.008\n".to_vec();
+        frame.push(2);
+        frame.extend([7u8; 16]);
+        frame.push(1);
+        assert_eq!(stream.process(&frame).unwrap(), frame);
+        assert!(stream.at_message_boundary());
+    }
+}
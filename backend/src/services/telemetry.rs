@@ -0,0 +1,182 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+
+/// Upper bounds (seconds) of the request latency histogram buckets
+const LATENCY_BUCKETS: [f64; 10] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 10.0];
+
+#[derive(Default)]
+struct RouteStats {
+    /// Request count per response status code
+    statuses: BTreeMap<u16, u64>,
+    bucket_counts: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum_seconds: f64,
+}
+
+/// In-process counters describing the backend itself, rendered by the Prometheus exporter
+#[derive(Clone, Default)]
+pub struct Telemetry {
+    http: Arc<Mutex<BTreeMap<(String, String), RouteStats>>>,
+    vnc_sessions: Arc<AtomicI64>,
+}
+
+/// Decrements the active VNC session gauge when the proxied session ends
+pub struct VncSessionGuard {
+    counter: Arc<AtomicI64>,
+}
+
+impl Drop for VncSessionGuard {
+    fn drop(&mut self) {
+        self.counter.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Telemetry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_request(&self, method: &str, route: &str, status: u16, seconds: f64) {
+        let mut http = self.http.lock().unwrap();
+        let stats = http.entry((method.to_string(), route.to_string())).or_default();
+        *stats.statuses.entry(status).or_default() += 1;
+        stats.count += 1;
+        stats.sum_seconds += seconds;
+        for (bucket, bound) in stats.bucket_counts.iter_mut().zip(LATENCY_BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+    }
+
+    pub fn vnc_session_started(&self) -> VncSessionGuard {
+        self.vnc_sessions.fetch_add(1, Ordering::Relaxed);
+        VncSessionGuard { counter: self.vnc_sessions.clone() }
+    }
+
+    /// Append the backend self-metrics to an exposition
+    pub fn render(&self, out: &mut Exposition) {
+        let http = self.http.lock().unwrap();
+
+        for ((method, route), stats) in http.iter() {
+            for (status, count) in &stats.statuses {
+                let status = status.to_string();
+                out.sample(
+                    "fossvps_http_requests_total", "HTTP requests handled, by route and status.", "counter",
+                    &[("method", method), ("route", route), ("status", &status)], *count as f64,
+                );
+            }
+        }
+
+        const DURATION: &str = "fossvps_http_request_duration_seconds";
+        const DURATION_HELP: &str = "HTTP request latency, by route.";
+        for ((method, route), stats) in http.iter() {
+            for (count, bound) in stats.bucket_counts.iter().zip(LATENCY_BUCKETS) {
+                let le = bound.to_string();
+                out.histogram_part(DURATION, DURATION_HELP, "_bucket", &[("method", method), ("route", route), ("le", &le)], *count as f64);
+            }
+            out.histogram_part(DURATION, DURATION_HELP, "_bucket", &[("method", method), ("route", route), ("le", "+Inf")], stats.count as f64);
+            out.histogram_part(DURATION, DURATION_HELP, "_sum", &[("method", method), ("route", route)], stats.sum_seconds);
+            out.histogram_part(DURATION, DURATION_HELP, "_count", &[("method", method), ("route", route)], stats.count as f64);
+        }
+
+        out.sample(
+            "fossvps_vnc_sessions_active", "Console sessions currently being proxied.", "gauge",
+            &[], self.vnc_sessions.load(Ordering::Relaxed) as f64,
+        );
+    }
+}
+
+struct Family {
+    name: &'static str,
+    help: &'static str,
+    kind: &'static str,
+    lines: Vec<String>,
+}
+
+/// Prometheus text exposition builder that keeps each metric family's samples together
+#[derive(Default)]
+pub struct Exposition {
+    families: Vec<Family>,
+}
+
+impl Exposition {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn sample(&mut self, name: &'static str, help: &'static str, kind: &'static str, labels: &[(&str, &str)], value: f64) {
+        self.push(name, help, kind, "", labels, value);
+    }
+
+    /// Add a `_bucket`, `_sum` or `_count` line to a histogram family
+    pub fn histogram_part(&mut self, name: &'static str, help: &'static str, suffix: &str, labels: &[(&str, &str)], value: f64) {
+        self.push(name, help, "histogram", suffix, labels, value);
+    }
+
+    fn push(&mut self, name: &'static str, help: &'static str, kind: &'static str, suffix: &str, labels: &[(&str, &str)], value: f64) {
+        let index = match self.families.iter().position(|f| f.name == name) {
+            Some(i) => i,
+            None => {
+                self.families.push(Family { name, help, kind, lines: Vec::new() });
+                self.families.len() - 1
+            }
+        };
+
+        let mut line = format!("{}{}", name, suffix);
+        if !labels.is_empty() {
+            let rendered: Vec<String> = labels.iter()
+                .map(|(k, v)| format!("{}=\"{}\"", k, escape_label(v)))
+                .collect();
+            let _ = write!(line, "{{{}}}", rendered.join(","));
+        }
+        let _ = write!(line, " {}", value);
+        self.families[index].lines.push(line);
+    }
+
+    pub fn finish(self) -> String {
+        let mut out = String::new();
+        for family in self.families {
+            let _ = writeln!(out, "# HELP {} {}", family.name, family.help);
+            let _ = writeln!(out, "# TYPE {} {}", family.name, family.kind);
+            for line in family.lines {
+                out.push_str(&line);
+                out.push('\n');
+            }
+        }
+        out
+    }
+}
+
+/// Escape a Prometheus label value
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Middleware recording request counts and latency per matched route template
+pub async fn track_requests(
+    State(telemetry): State<Telemetry>,
+    req: Request,
+    next: Next,
+) -> Response {
+    // Use the route template (e.g. /api/v1/nodes/:id) so label cardinality stays bounded
+    let route = req.extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+    let method = req.method().to_string();
+    let started = Instant::now();
+
+    let response = next.run(req).await;
+
+    telemetry.record_request(&method, &route, response.status().as_u16(), started.elapsed().as_secs_f64());
+    response
+}
@@ -0,0 +1,242 @@
+use axum::extract::ws::{Message as ClientMessage, WebSocket};
+use futures_util::{SinkExt, StreamExt};
+use serde::Deserialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_tungstenite::{tungstenite::protocol::Message, WebSocketStream};
+use tracing::{debug, error};
+use crate::clients::TerminalTarget;
+use crate::services::vnc::{connect_upstream, upstream_request, UpstreamStream};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+/// termproxy drops sessions that stay silent; xterm.js pings at the same interval
+const XTERM_PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Control messages the browser sends as text frames; binary frames are raw keyboard input
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ControlMessage {
+    Input { data: String },
+    Resize { cols: u16, rows: u16 },
+}
+
+enum ClientFrame {
+    Input(Vec<u8>),
+    Resize { cols: u16, rows: u16 },
+    Close,
+}
+
+fn client_frame(msg: ClientMessage) -> Option<ClientFrame> {
+    match msg {
+        ClientMessage::Binary(bin) => Some(ClientFrame::Input(bin)),
+        ClientMessage::Text(txt) => match serde_json::from_str::<ControlMessage>(&txt) {
+            Ok(ControlMessage::Input { data }) => Some(ClientFrame::Input(data.into_bytes())),
+            Ok(ControlMessage::Resize { cols, rows }) => Some(ClientFrame::Resize { cols, rows }),
+            Err(e) => {
+                debug!("Ignoring malformed terminal message: {}", e);
+                None
+            }
+        },
+        ClientMessage::Close(_) => Some(ClientFrame::Close),
+        _ => None,
+    }
+}
+
+async fn connect(
+    url: &str,
+    origin_header: Option<String>,
+    tls: Arc<rustls::ClientConfig>,
+) -> anyhow::Result<WebSocketStream<Box<dyn UpstreamStream>>> {
+    let request = upstream_request(url, origin_header)?.body(())?;
+    match tokio::time::timeout(CONNECT_TIMEOUT, connect_upstream(request, tls)).await {
+        Ok(Ok((ws, _))) => Ok(ws),
+        Ok(Err(e)) => Err(anyhow::anyhow!("Failed to establish terminal connection: {}", e)),
+        Err(_) => Err(anyhow::anyhow!("Terminal connection timeout - check network and node availability")),
+    }
+}
+
+/// Bridge a browser terminal (xterm.js or similar) to a guest's text console.
+///
+/// The browser side speaks one protocol for every hypervisor: binary frames carry keystrokes,
+/// text frames carry `{"type":"resize","cols":..,"rows":..}` (or `{"type":"input","data":..}`),
+/// and terminal output comes back as binary frames.
+pub async fn proxy_terminal(
+    target: TerminalTarget,
+    client_ws: WebSocket,
+    origin_header: Option<String>,
+    tls: Arc<rustls::ClientConfig>,
+    (cols, rows): (u16, u16),
+) -> anyhow::Result<()> {
+    match target {
+        TerminalTarget::Xterm { url, user, ticket } => {
+            proxy_xterm(&url, &user, &ticket, client_ws, origin_header, tls, (cols, rows)).await
+        }
+        TerminalTarget::Incus { data_url, control_url } => {
+            proxy_incus(&data_url, &control_url, client_ws, origin_header, tls).await
+        }
+    }
+}
+
+/// Proxmox termproxy: authenticate with `user:ticket\n`, then `0:<len>:<data>` for input,
+/// `1:<cols>:<rows>:` for resize and `2` as keepalive
+async fn proxy_xterm(
+    url: &str,
+    user: &str,
+    ticket: &str,
+    client_ws: WebSocket,
+    origin_header: Option<String>,
+    tls: Arc<rustls::ClientConfig>,
+    (cols, rows): (u16, u16),
+) -> anyhow::Result<()> {
+    let mut upstream = connect(url, origin_header, tls).await?;
+
+    upstream.send(Message::Text(format!("{}:{}\n", user, ticket))).await?;
+    match tokio::time::timeout(CONNECT_TIMEOUT, upstream.next()).await {
+        Ok(Some(Ok(msg))) if msg.clone().into_data().starts_with(b"OK") => {}
+        _ => {
+            error!("❌ Terminal proxy rejected the ticket");
+            anyhow::bail!("Terminal authentication failed");
+        }
+    }
+    upstream.send(Message::Text(format!("1:{}:{}:", cols, rows))).await?;
+
+    let (mut backend_sender, mut backend_receiver) = upstream.split();
+    let (mut client_sender, mut client_receiver) = client_ws.split();
+
+    let client_to_backend = async {
+        let mut ping = tokio::time::interval(XTERM_PING_INTERVAL);
+        loop {
+            let frame = tokio::select! {
+                msg = client_receiver.next() => match msg {
+                    Some(Ok(msg)) => client_frame(msg),
+                    Some(Err(e)) => {
+                        error!("❌ Client websocket error: {}", e);
+                        break;
+                    }
+                    None => break,
+                },
+                _ = ping.tick() => {
+                    if backend_sender.send(Message::Text("2".into())).await.is_err() {
+                        break;
+                    }
+                    continue;
+                }
+            };
+
+            let upstream_msg = match frame {
+                Some(ClientFrame::Input(data)) => {
+                    let mut payload = format!("0:{}:", data.len()).into_bytes();
+                    payload.extend_from_slice(&data);
+                    Message::Binary(payload)
+                }
+                Some(ClientFrame::Resize { cols, rows }) => Message::Text(format!("1:{}:{}:", cols, rows)),
+                Some(ClientFrame::Close) => {
+                    let _ = backend_sender.send(Message::Close(None)).await;
+                    break;
+                }
+                None => continue,
+            };
+            if let Err(e) = backend_sender.send(upstream_msg).await {
+                error!("❌ Error sending to backend: {}", e);
+                break;
+            }
+        }
+        debug!("🔌 Terminal client to backend loop ended");
+    };
+
+    let backend_to_client = async {
+        while let Some(Ok(msg)) = backend_receiver.next().await {
+            let output = match msg {
+                Message::Binary(bin) => bin,
+                Message::Text(txt) => txt.into_bytes(),
+                Message::Close(_) => break,
+                _ => continue,
+            };
+            if client_sender.send(ClientMessage::Binary(output)).await.is_err() {
+                break;
+            }
+        }
+        let _ = client_sender.send(ClientMessage::Close(None)).await;
+        debug!("🔌 Terminal backend to client loop ended");
+    };
+
+    tokio::select! {
+        _ = client_to_backend => {},
+        _ = backend_to_client => {},
+    }
+
+    Ok(())
+}
+
+/// Incus console/exec: raw PTY bytes on the data socket, `window-resize` commands on the control socket
+async fn proxy_incus(
+    data_url: &str,
+    control_url: &str,
+    client_ws: WebSocket,
+    origin_header: Option<String>,
+    tls: Arc<rustls::ClientConfig>,
+) -> anyhow::Result<()> {
+    // The operation only starts once every websocket it announced is attached
+    let mut control = connect(control_url, origin_header.clone(), tls.clone()).await?;
+    let data = connect(data_url, origin_header, tls).await?;
+
+    let (mut backend_sender, mut backend_receiver) = data.split();
+    let (mut client_sender, mut client_receiver) = client_ws.split();
+
+    let client_to_backend = async {
+        while let Some(msg) = client_receiver.next().await {
+            let frame = match msg {
+                Ok(msg) => client_frame(msg),
+                Err(e) => {
+                    error!("❌ Client websocket error: {}", e);
+                    break;
+                }
+            };
+
+            let result = match frame {
+                Some(ClientFrame::Input(data)) => backend_sender.send(Message::Binary(data)).await,
+                Some(ClientFrame::Resize { cols, rows }) => {
+                    let command = serde_json::json!({
+                        "command": "window-resize",
+                        "args": { "width": cols.to_string(), "height": rows.to_string() },
+                    });
+                    control.send(Message::Text(command.to_string())).await
+                }
+                Some(ClientFrame::Close) => break,
+                None => continue,
+            };
+            if let Err(e) = result {
+                error!("❌ Error sending to backend: {}", e);
+                break;
+            }
+        }
+
+        // Closing the data socket hangs up the PTY on the Incus side
+        let _ = backend_sender.send(Message::Close(None)).await;
+        let _ = control.close(None).await;
+        debug!("🔌 Terminal client to backend loop ended");
+    };
+
+    let backend_to_client = async {
+        while let Some(Ok(msg)) = backend_receiver.next().await {
+            let output = match msg {
+                Message::Binary(bin) => bin,
+                Message::Text(txt) => txt.into_bytes(),
+                Message::Close(_) => break,
+                _ => continue,
+            };
+            if client_sender.send(ClientMessage::Binary(output)).await.is_err() {
+                break;
+            }
+        }
+        let _ = client_sender.send(ClientMessage::Close(None)).await;
+        debug!("🔌 Terminal backend to client loop ended");
+    };
+
+    tokio::select! {
+        _ = client_to_backend => {},
+        _ = backend_to_client => {},
+    }
+
+    Ok(())
+}
@@ -1,55 +1,119 @@
-use crate::db::DbPool;
-use crate::models::node::{Node, NodeType};
-use crate::clients::{proxmox::ProxmoxClient, incus::IncusClient, NodeClient};
+use crate::clients::{FsFreezeAction, GuestInfo, VmDetails};
+use crate::clients::registry::SharedClient;
+use crate::models::cloud_init::{CloudInitConfig, CloudInitUpdate};
+use crate::models::resize::{ResizeOutcome, VmResize};
+use crate::models::node::{Node, NodeStatus, NodeType};
+use crate::services::nodes::record_node_status;
+use crate::state::AppState;
+use serde::Serialize;
 use serde_json::Value;
+use std::cmp::Ordering;
 
-pub async fn list_all_vms(pool: &DbPool) -> anyhow::Result<Vec<Value>> {
+/// Largest page `GET /vms` returns, whatever `limit` asks for
+pub const MAX_PAGE_SIZE: usize = 500;
+
+/// Kind of guest regardless of backend: Proxmox `qemu`/`lxc`, Incus `virtual-machine`/`container`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GuestType {
+    Vm,
+    Container,
+}
+
+impl GuestType {
+    fn of(vm: &Value) -> Option<Self> {
+        vm.get("type").and_then(|t| t.as_str()).and_then(|t| t.parse().ok())
+    }
+}
+
+impl std::str::FromStr for GuestType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "vm" | "qemu" | "virtual-machine" => Ok(Self::Vm),
+            "container" | "lxc" => Ok(Self::Container),
+            other => anyhow::bail!("Unsupported guest type: {}", other),
+        }
+    }
+}
+
+/// Filters applied to the aggregated VM inventory before pagination
+#[derive(Debug, Default, Clone)]
+pub struct VmFilter {
+    pub status: Option<String>,
+    pub node_id: Option<String>,
+    pub guest_type: Option<GuestType>,
+    pub name: Option<String>,
+    pub tag: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VmSortKey {
+    Name,
+    Cpu,
+    Memory,
+    Uptime,
+}
+
+impl std::str::FromStr for VmSortKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "name" => Ok(Self::Name),
+            "cpu" => Ok(Self::Cpu),
+            "memory" | "mem" => Ok(Self::Memory),
+            "uptime" => Ok(Self::Uptime),
+            other => anyhow::bail!("Unsupported sort key: {}", other),
+        }
+    }
+}
+
+/// The id VM actions expect: "node/type/vmid" on Proxmox, the instance name on Incus
+pub fn internal_vm_id(node_type: NodeType, vm: &Value) -> Option<String> {
+    match node_type {
+        NodeType::Proxmox => match (vm.get("node").and_then(|n| n.as_str()), vm.get("id").and_then(|i| i.as_str())) {
+            (Some(node), Some(id)) => Some(format!("{}/{}", node, id)),
+            _ => None,
+        },
+        NodeType::Incus => vm.get("name").and_then(|n| n.as_str()).map(str::to_string),
+    }
+}
+
+pub async fn list_all_vms(state: &AppState) -> anyhow::Result<Vec<Value>> {
+    Ok(list_vms_by_node(state).await?
+        .into_iter()
+        .flat_map(|(_, vms)| vms.unwrap_or_default())
+        .collect())
+}
+
+/// Normalized VM listing per node; `None` marks a node that could not be queried this time
+pub async fn list_vms_by_node(state: &AppState) -> anyhow::Result<Vec<(uuid::Uuid, Option<Vec<Value>>)>> {
     let nodes = sqlx::query_as::<_, Node>(
         r#"
-        SELECT id, name, node_type, api_url, api_key, api_secret, status, last_check, created_at
+        SELECT id, name, node_type, api_url, api_key, api_secret, status, last_check, created_at, tls_mode, tls_ca_cert, tls_fingerprint, tls_pending_fingerprint
         FROM nodes
         "#
     )
-    .fetch_all(pool)
+    .fetch_all(&state.pool)
     .await?;
 
-    let mut all_vms = Vec::new();
+    let mut by_node = Vec::with_capacity(nodes.len());
 
     for node in nodes {
-        let vms_result = match node.node_type {
-            NodeType::Proxmox => {
-                let client = ProxmoxClient::new(
-                    node.api_url.clone(),
-                    node.api_key.clone(),
-                    node.api_secret.clone().unwrap_or_default(),
-                );
-                client.list_vms().await
-            }
-            NodeType::Incus => {
-                let client = IncusClient::new(
-                    node.api_url.clone(),
-                    node.api_key.clone(),
-                    node.api_secret.clone(),
-                );
-                client.list_vms().await
-            }
+        let vms_result = match state.clients.client_for(&node) {
+            Ok(client) => client.list_vms().await.map(|vms| (client, vms)),
+            Err(e) => Err(e),
         };
 
         match vms_result {
-            Ok(mut vms) => {
+            Ok((client, mut vms)) => {
                 // Update node status to online
-                let _ = sqlx::query(
-                    r#"
-                    UPDATE nodes 
-                    SET status = 'online', last_check = NOW() 
-                    WHERE id = $1
-                    "#
-                )
-                .bind(node.id)
-                .execute(pool)
-                .await;
+                record_node_status(&state.pool, &state.webhooks, &node, NodeStatus::Online).await;
                 
                 for vm in vms.iter_mut() {
+                    let guest_type = GuestType::of(vm);
                     if let Some(obj) = vm.as_object_mut() {
                         obj.insert("node_id".to_string(), Value::String(node.id.to_string()));
                         obj.insert("node_name".to_string(), Value::String(node.name.clone()));
@@ -61,6 +125,11 @@ pub async fn list_all_vms(pool: &DbPool) -> anyhow::Result<Vec<Value>> {
                             }
                         }
                         
+                        // `type` stays as the backend reports it; `guest_type` is the same on both
+                        if let Some(guest_type) = guest_type {
+                            obj.insert("guest_type".to_string(), serde_json::json!(guest_type));
+                        }
+
                         // Normalize memory field (ensure it's in bytes)
                         if let Some(mem) = obj.get("maxmem") {
                             // maxmem is already in bytes for QEMU VMs
@@ -68,196 +137,419 @@ pub async fn list_all_vms(pool: &DbPool) -> anyhow::Result<Vec<Value>> {
                                 obj.insert("memory".to_string(), mem.clone());
                             }
                         }
-                        
-                        // Construct a unified internal ID for actions
-                        if node.node_type == NodeType::Proxmox {
-                            if let (Some(node_resource), Some(vm_id)) = (obj.get("node"), obj.get("id")) {
-                                let node_str = node_resource.as_str().unwrap_or_default();
-                                let id_str = vm_id.as_str().unwrap_or_default();
-                                obj.insert("internal_id".to_string(), Value::String(format!("{}/{}", node_str, id_str)));
-                            }
-                        } else {
-                            // For Incus, the identifier is the name
-                            if let Some(name) = obj.get("name") {
-                                obj.insert("internal_id".to_string(), name.clone());
-                            }
+                    }
+
+                    // Construct a unified internal ID for actions
+                    if let Some(internal_id) = internal_vm_id(node.node_type, vm) {
+                        if let Some(obj) = vm.as_object_mut() {
+                            obj.insert("internal_id".to_string(), Value::String(internal_id));
                         }
                     }
                 }
-                all_vms.extend(vms);
+                // IPs and OS come from the guests themselves
+                state.guest_info.merge_into(&client, node.id, &mut vms).await;
+                by_node.push((node.id, Some(vms)));
             }
             Err(e) => {
                 tracing::error!("❌ Failed to list VMs for node {}: {}", node.name, e);
                 
                 // Update node status to error
-                let _ = sqlx::query(
-                    r#"
-                    UPDATE nodes 
-                    SET status = 'error', last_check = NOW() 
-                    WHERE id = $1
-                    "#
-                )
-                .bind(node.id)
-                .execute(pool)
-                .await;
+                record_node_status(&state.pool, &state.webhooks, &node, NodeStatus::Error).await;
+                by_node.push((node.id, None));
             }
         }
     }
 
-    Ok(all_vms)
+    Ok(by_node)
 }
 
 pub async fn perform_vm_power_action(
-    pool: &DbPool,
+    state: &AppState,
     node_id: &str,
     vm_id: &str,
     action: &str,
 ) -> anyhow::Result<()> {
     let node_uuid = uuid::Uuid::parse_str(node_id)?;
-    
-    let node = sqlx::query_as::<_, Node>(
-        r#"
-        SELECT id, name, node_type, api_url, api_key, api_secret, status, last_check, created_at
-        FROM nodes
-        WHERE id = $1
-        "#
-    )
-    .bind(node_uuid)
-    .fetch_one(pool)
-    .await?;
-
-    match node.node_type {
-        NodeType::Proxmox => {
-            let client = ProxmoxClient::new(
-                node.api_url,
-                node.api_key,
-                node.api_secret.unwrap_or_default(),
-            );
-            client.vm_power_action(vm_id, action).await
-        }
-        NodeType::Incus => {
-            let client = IncusClient::new(
-                node.api_url,
-                node.api_key,
-                node.api_secret,
-            );
-            client.vm_power_action(vm_id, action).await
-        }
-    }
+    let client = state.clients.get(node_uuid).await?;
+    client.vm_power_action(vm_id, action).await?;
+    state.guest_info.invalidate(node_uuid, vm_id);
+    state.alerts.note_power_action(node_uuid, vm_id, action);
+    state.webhooks.emit("vm.power", serde_json::json!({
+        "node_id": node_uuid,
+        "vm_id": vm_id,
+        "action": action,
+    })).await;
+    Ok(())
 }
 
+/// Apply a raw config change based on the `digest` the caller read it at, failing with
+/// `ConfigConflict` if someone else changed the config in between
 pub async fn update_vm_resources(
-    pool: &DbPool,
+    state: &AppState,
     node_id: &str,
     vm_id: &str,
     config: serde_json::Value,
+    digest: &str,
 ) -> anyhow::Result<()> {
     let node_uuid = uuid::Uuid::parse_str(node_id)?;
-    
-    let node = sqlx::query_as::<_, Node>(
-        r#"
-        SELECT id, name, node_type, api_url, api_key, api_secret, status, last_check, created_at
-        FROM nodes
-        WHERE id = $1
-        "#
-    )
-    .bind(node_uuid)
-    .fetch_one(pool)
-    .await?;
+    let client = state.clients.get(node_uuid).await?;
+    // Only the keys go out: values can hold secrets like cipassword or sshkeys
+    let changed_keys: Vec<String> = config.as_object().map(|o| o.keys().cloned().collect()).unwrap_or_default();
+    client.update_vm_config(vm_id, config, Some(digest)).await?;
+    state.webhooks.emit("vm.config_updated", serde_json::json!({
+        "node_id": node_uuid,
+        "vm_id": vm_id,
+        "changed_keys": changed_keys,
+    })).await;
+    Ok(())
+}
 
-    match node.node_type {
-        NodeType::Proxmox => {
-            let client = ProxmoxClient::new(
-                node.api_url,
-                node.api_key,
-                node.api_secret.unwrap_or_default(),
-            );
-            client.update_vm_config(vm_id, config).await
-        }
-        NodeType::Incus => {
-            let client = IncusClient::new(
-                node.api_url,
-                node.api_key,
-                node.api_secret,
-            );
-            client.update_vm_config(vm_id, config).await
-        }
-    }
+/// Apply a resize already checked by `resize::validate` against this client's limits
+pub async fn resize_vm(
+    state: &AppState,
+    client: &SharedClient,
+    node_id: uuid::Uuid,
+    vm_id: &str,
+    resize: &VmResize,
+) -> anyhow::Result<ResizeOutcome> {
+    let outcome = client.resize_vm(vm_id, resize).await?;
+    tracing::info!("📐 Resized {}: {:?} (reboot required: {})", vm_id, resize, outcome.reboot_required);
+    state.webhooks.emit("vm.resized", serde_json::json!({
+        "node_id": node_id,
+        "vm_id": vm_id,
+        "resize": resize,
+        "reboot_required": outcome.reboot_required,
+    })).await;
+    Ok(outcome)
 }
 
 pub async fn get_vm_info(
-    pool: &DbPool,
+    state: &AppState,
     node_id: &str,
     vm_id: &str,
-) -> anyhow::Result<serde_json::Value> {
+) -> anyhow::Result<VmDetails> {
     let node_uuid = uuid::Uuid::parse_str(node_id)?;
-    
-    let node = sqlx::query_as::<_, Node>(
-        r#"
-        SELECT id, name, node_type, api_url, api_key, api_secret, status, last_check, created_at
-        FROM nodes
-        WHERE id = $1
-        "#
-    )
-    .bind(node_uuid)
-    .fetch_one(pool)
-    .await?;
+    let client = state.clients.get(node_uuid).await?;
+    client.get_vm_details(vm_id).await
+}
 
-    match node.node_type {
-        NodeType::Proxmox => {
-            let client = ProxmoxClient::new(
-                node.api_url,
-                node.api_key,
-                node.api_secret.unwrap_or_default(),
-            );
-            client.get_vm_details(vm_id).await
-        }
-        NodeType::Incus => {
-            let client = IncusClient::new(
-                node.api_url,
-                node.api_key,
-                node.api_secret,
-            );
-            client.get_vm_details(vm_id).await
-        }
-    }
+/// Ask the guest directly, bypassing the listing cache
+pub async fn get_guest_info(
+    state: &AppState,
+    node_id: &str,
+    vm_id: &str,
+) -> anyhow::Result<GuestInfo> {
+    let node_uuid = uuid::Uuid::parse_str(node_id)?;
+    let client = state.clients.get(node_uuid).await?;
+    client.guest_info(vm_id).await
+}
+
+pub async fn guest_fsfreeze(
+    state: &AppState,
+    node_id: &str,
+    vm_id: &str,
+    action: FsFreezeAction,
+) -> anyhow::Result<Value> {
+    let node_uuid = uuid::Uuid::parse_str(node_id)?;
+    let client = state.clients.get(node_uuid).await?;
+    let result = client.guest_fsfreeze(vm_id, action).await?;
+    tracing::info!("🧊 Guest filesystem {:?} on {}: {}", action, vm_id, result);
+    Ok(result)
+}
+
+pub async fn get_cloud_init(
+    state: &AppState,
+    node_id: &str,
+    vm_id: &str,
+) -> anyhow::Result<CloudInitConfig> {
+    let node_uuid = uuid::Uuid::parse_str(node_id)?;
+    let client = state.clients.get(node_uuid).await?;
+    client.get_cloud_init(vm_id).await
+}
+
+/// Apply cloud-init changes already checked by `cloud_init::validate` for this client
+pub async fn update_cloud_init(
+    state: &AppState,
+    client: &SharedClient,
+    node_id: uuid::Uuid,
+    vm_id: &str,
+    changes: &CloudInitUpdate,
+) -> anyhow::Result<()> {
+    client.update_cloud_init(vm_id, changes).await?;
+    // Only the field names: the values include passwords and keys
+    let fields = changes.changed_fields();
+    tracing::info!("☁️ Cloud-init of {} updated: {}", vm_id, fields.join(", "));
+    state.webhooks.emit("vm.cloud_init_updated", serde_json::json!({
+        "node_id": node_id,
+        "vm_id": vm_id,
+        "fields": fields,
+    })).await;
+    Ok(())
 }
 
 pub async fn perform_media_action(
-    pool: &DbPool,
+    state: &AppState,
     node_id: &str,
     vm_id: &str,
     iso_path: &str,
 ) -> anyhow::Result<()> {
     let node_uuid = uuid::Uuid::parse_str(node_id)?;
-    
-    let node = sqlx::query_as::<_, Node>(
-        r#"
-        SELECT id, name, node_type, api_url, api_key, api_secret, status, last_check, created_at
-        FROM nodes
-        WHERE id = $1
-        "#
-    )
-    .bind(node_uuid)
-    .fetch_one(pool)
-    .await?;
+    let client = state.clients.get(node_uuid).await?;
+    client.mount_media(vm_id, iso_path).await
+}
+
+pub async fn perform_media_eject(
+    state: &AppState,
+    node_id: &str,
+    vm_id: &str,
+) -> anyhow::Result<()> {
+    let node_uuid = uuid::Uuid::parse_str(node_id)?;
+    let client = state.clients.get(node_uuid).await?;
+    client.eject_media(vm_id).await
+}
+
+/// Tags are stored as a `;`-separated string by Proxmox and as `user.tags` (comma-separated) on Incus
+fn vm_tags(vm: &Value) -> Vec<String> {
+    let raw = vm.get("tags")
+        .and_then(|t| t.as_str())
+        .or_else(|| vm.get("config").and_then(|c| c.get("user.tags")).and_then(|t| t.as_str()))
+        .unwrap_or_default();
 
-    match node.node_type {
-        NodeType::Proxmox => {
-            let client = ProxmoxClient::new(
-                node.api_url,
-                node.api_key,
-                node.api_secret.unwrap_or_default(),
-            );
-            client.mount_media(vm_id, iso_path).await
+    raw.split([';', ','])
+        .map(|t| t.trim().to_lowercase())
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+fn vm_str<'a>(vm: &'a Value, key: &str) -> &'a str {
+    vm.get(key).and_then(|v| v.as_str()).unwrap_or_default()
+}
+
+fn vm_number(vm: &Value, keys: &[&str]) -> Option<f64> {
+    keys.iter()
+        .filter_map(|k| vm.get(*k))
+        .find_map(|v| v.as_f64().or_else(|| v.as_str().and_then(|s| s.parse().ok())))
+}
+
+fn matches_filter(vm: &Value, filter: &VmFilter) -> bool {
+    if let Some(status) = &filter.status {
+        if !vm_str(vm, "status").eq_ignore_ascii_case(status) {
+            return false;
         }
-        NodeType::Incus => {
-            let client = IncusClient::new(
-                node.api_url,
-                node.api_key,
-                node.api_secret,
-            );
-            client.mount_media(vm_id, iso_path).await
+    }
+
+    if let Some(node_id) = &filter.node_id {
+        if vm_str(vm, "node_id") != node_id {
+            return false;
         }
     }
+
+    if let Some(guest_type) = filter.guest_type {
+        if GuestType::of(vm) != Some(guest_type) {
+            return false;
+        }
+    }
+
+    if let Some(name) = &filter.name {
+        if !vm_str(vm, "name").to_lowercase().contains(&name.to_lowercase()) {
+            return false;
+        }
+    }
+
+    if let Some(tag) = &filter.tag {
+        if !vm_tags(vm).contains(&tag.to_lowercase()) {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Figure a numeric sort key reads; `None` when the backend does not report it
+fn sort_value(vm: &Value, key: VmSortKey) -> Option<f64> {
+    match key {
+        VmSortKey::Name => None,
+        VmSortKey::Cpu => vm_number(vm, &["cpu"]),
+        VmSortKey::Memory => vm_number(vm, &["mem"]),
+        VmSortKey::Uptime => vm_number(vm, &["uptime"]),
+    }
+}
+
+/// Apply filters and sorting to an aggregated VM list, returning the matching VMs. Guests
+/// without a figure for the sort key (such as CPU usage on Incus) go last in either order.
+pub fn filter_and_sort_vms(
+    vms: Vec<Value>,
+    filter: &VmFilter,
+    sort: Option<VmSortKey>,
+    descending: bool,
+) -> Vec<Value> {
+    let mut matched: Vec<Value> = vms.into_iter()
+        .filter(|vm| matches_filter(vm, filter))
+        .collect();
+
+    if let Some(key) = sort {
+        matched.sort_by(|a, b| {
+            if key == VmSortKey::Name {
+                let ord = vm_str(a, "name").to_lowercase().cmp(&vm_str(b, "name").to_lowercase());
+                return if descending { ord.reverse() } else { ord };
+            }
+            match (sort_value(a, key), sort_value(b, key)) {
+                (Some(x), Some(y)) => if descending { y.total_cmp(&x) } else { x.total_cmp(&y) },
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            }
+        });
+    }
+
+    matched
+}
+
+/// One page of a filtered listing
+#[derive(Debug, Serialize)]
+pub struct VmPage {
+    pub items: Vec<Value>,
+    pub total: usize,
+    pub offset: usize,
+    pub limit: Option<usize>,
+    pub next_offset: Option<usize>,
+}
+
+/// Cut a page out of the matching VMs; `limit` is clamped to `1..=MAX_PAGE_SIZE`
+pub fn paginate(matched: Vec<Value>, offset: usize, limit: Option<usize>) -> VmPage {
+    let total = matched.len();
+    let limit = limit.map(|l| l.clamp(1, MAX_PAGE_SIZE));
+
+    let items: Vec<Value> = matched.into_iter()
+        .skip(offset)
+        .take(limit.unwrap_or(usize::MAX))
+        .collect();
+
+    // Only report a next page when the caller asked for a bounded page and more results remain
+    let next_offset = limit
+        .map(|_| offset + items.len())
+        .filter(|next| *next < total);
+
+    VmPage { items, total, offset, limit, next_offset }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn inventory() -> Vec<Value> {
+        vec![
+            json!({ "name": "web-1", "type": "qemu", "status": "running", "node_id": "a", "cpu": 0.5, "mem": 2048, "uptime": 300, "tags": "prod;web" }),
+            json!({ "name": "db", "type": "lxc", "status": "stopped", "node_id": "a", "cpu": 0.0, "mem": 0, "tags": "prod" }),
+            json!({ "name": "Web-2", "type": "virtual-machine", "status": "Running", "node_id": "b", "mem": 4096, "uptime": 60, "config": { "user.tags": "web, staging" } }),
+            json!({ "name": "cache", "type": "container", "status": "Stopped", "node_id": "b" }),
+        ]
+    }
+
+    fn names(vms: &[Value]) -> Vec<&str> {
+        vms.iter().map(|vm| vm_str(vm, "name")).collect()
+    }
+
+    fn filtered(filter: VmFilter) -> Vec<Value> {
+        filter_and_sort_vms(inventory(), &filter, None, false)
+    }
+
+    #[test]
+    fn guest_type_accepts_both_backends_spellings() {
+        for raw in ["vm", "qemu", "virtual-machine", "VM"] {
+            assert_eq!(raw.parse::<GuestType>().unwrap(), GuestType::Vm);
+        }
+        for raw in ["container", "lxc"] {
+            assert_eq!(raw.parse::<GuestType>().unwrap(), GuestType::Container);
+        }
+        assert!("openvz".parse::<GuestType>().is_err());
+    }
+
+    #[test]
+    fn filters_by_normalized_type() {
+        let vms = filtered(VmFilter { guest_type: Some(GuestType::Vm), ..Default::default() });
+        assert_eq!(names(&vms), ["web-1", "Web-2"]);
+        let containers = filtered(VmFilter { guest_type: Some(GuestType::Container), ..Default::default() });
+        assert_eq!(names(&containers), ["db", "cache"]);
+    }
+
+    #[test]
+    fn filters_status_and_name_case_insensitively() {
+        let running = filtered(VmFilter { status: Some("running".into()), ..Default::default() });
+        assert_eq!(names(&running), ["web-1", "Web-2"]);
+        let web = filtered(VmFilter { name: Some("WEB".into()), node_id: Some("b".into()), ..Default::default() });
+        assert_eq!(names(&web), ["Web-2"]);
+    }
+
+    #[test]
+    fn filters_tags_from_proxmox_and_incus() {
+        let tagged = filtered(VmFilter { tag: Some("web".into()), ..Default::default() });
+        assert_eq!(names(&tagged), ["web-1", "Web-2"]);
+        let prod = filtered(VmFilter { tag: Some("Prod".into()), ..Default::default() });
+        assert_eq!(names(&prod), ["web-1", "db"]);
+    }
+
+    #[test]
+    fn sorts_by_name_ignoring_case() {
+        let vms = filter_and_sort_vms(inventory(), &VmFilter::default(), Some(VmSortKey::Name), false);
+        assert_eq!(names(&vms), ["cache", "db", "web-1", "Web-2"]);
+        let vms = filter_and_sort_vms(inventory(), &VmFilter::default(), Some(VmSortKey::Name), true);
+        assert_eq!(names(&vms), ["Web-2", "web-1", "db", "cache"]);
+    }
+
+    #[test]
+    fn missing_figures_sort_last_in_both_orders() {
+        let asc = filter_and_sort_vms(inventory(), &VmFilter::default(), Some(VmSortKey::Cpu), false);
+        assert_eq!(names(&asc), ["db", "web-1", "Web-2", "cache"]);
+        let desc = filter_and_sort_vms(inventory(), &VmFilter::default(), Some(VmSortKey::Cpu), true);
+        assert_eq!(names(&desc), ["web-1", "db", "Web-2", "cache"]);
+
+        let uptime = filter_and_sort_vms(inventory(), &VmFilter::default(), Some(VmSortKey::Uptime), true);
+        assert_eq!(names(&uptime), ["web-1", "Web-2", "db", "cache"]);
+        let memory = filter_and_sort_vms(inventory(), &VmFilter::default(), Some(VmSortKey::Memory), true);
+        assert_eq!(names(&memory), ["Web-2", "web-1", "db", "cache"]);
+    }
+
+    #[test]
+    fn sort_keys_parse() {
+        assert_eq!("MEM".parse::<VmSortKey>().unwrap(), VmSortKey::Memory);
+        assert!("disk".parse::<VmSortKey>().is_err());
+    }
+
+    #[test]
+    fn pages_report_the_next_offset_until_the_end() {
+        let vms: Vec<Value> = (0..5).map(|i| json!({ "name": i.to_string() })).collect();
+
+        let first = paginate(vms.clone(), 0, Some(2));
+        assert_eq!(names(&first.items), ["0", "1"]);
+        assert_eq!((first.total, first.offset, first.limit, first.next_offset), (5, 0, Some(2), Some(2)));
+
+        let last = paginate(vms.clone(), 4, Some(2));
+        assert_eq!(names(&last.items), ["4"]);
+        assert_eq!(last.next_offset, None);
+
+        let past_end = paginate(vms.clone(), 9, Some(2));
+        assert!(past_end.items.is_empty());
+        assert_eq!((past_end.total, past_end.next_offset), (5, None));
+    }
+
+    #[test]
+    fn unbounded_pages_return_everything() {
+        let vms: Vec<Value> = (0..3).map(|i| json!({ "name": i.to_string() })).collect();
+        let page = paginate(vms, 1, None);
+        assert_eq!(names(&page.items), ["1", "2"]);
+        assert_eq!((page.limit, page.next_offset), (None, None));
+    }
+
+    #[test]
+    fn limit_is_clamped() {
+        let vms: Vec<Value> = (0..MAX_PAGE_SIZE + 10).map(|i| json!({ "name": i.to_string() })).collect();
+        let page = paginate(vms.clone(), 0, Some(10_000));
+        assert_eq!(page.items.len(), MAX_PAGE_SIZE);
+        assert_eq!((page.limit, page.next_offset), (Some(MAX_PAGE_SIZE), Some(MAX_PAGE_SIZE)));
+
+        let page = paginate(vms, 0, Some(0));
+        assert_eq!((page.items.len(), page.limit), (1, Some(1)));
+    }
 }
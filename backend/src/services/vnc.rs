@@ -1,48 +1,82 @@
-use tokio_tungstenite::{Connector, tungstenite::protocol::Message};
+use tokio_tungstenite::{WebSocketStream, tungstenite::{self, protocol::Message, handshake::client::Response}};
+use tokio::io::{AsyncRead, AsyncWrite};
 use futures_util::{StreamExt, SinkExt};
-use tracing::{error, debug};
+use tracing::{error, debug, info};
+use crate::services::vnc_sessions::VncSession;
+use std::sync::Arc;
 use std::time::Duration;
 
-pub async fn proxy_vnc(
-    target_url: String,
-    client_ws: axum::extract::ws::WebSocket,
-    auth_header: Option<String>,
-    origin_header: Option<String>,
-) -> anyhow::Result<()> {
+/// Object-safe bound so plain and TLS upstream connections share one proxy loop
+pub trait UpstreamStream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> UpstreamStream for T {}
+
+/// Open the upstream WebSocket, verifying TLS with the node's own settings
+pub async fn connect_upstream(
+    request: axum::http::Request<()>,
+    tls: Arc<rustls::ClientConfig>,
+) -> Result<(WebSocketStream<Box<dyn UpstreamStream>>, Response), tungstenite::Error> {
+    let is_tls = request.uri().scheme_str() == Some("wss");
+    let (host, port) = crate::clients::tls::host_and_port(&request.uri().to_string())
+        .map_err(|e| tungstenite::Error::Io(std::io::Error::new(std::io::ErrorKind::InvalidInput, e.to_string())))?;
+
+    let tcp = tokio::net::TcpStream::connect((host.as_str(), port)).await?;
+    let stream: Box<dyn UpstreamStream> = if is_tls {
+        let server_name = rustls::pki_types::ServerName::try_from(host)
+            .map_err(|e| tungstenite::Error::Io(std::io::Error::new(std::io::ErrorKind::InvalidInput, e)))?;
+        Box::new(tokio_rustls::TlsConnector::from(tls).connect(server_name, tcp).await?)
+    } else {
+        Box::new(tcp)
+    };
+
+    tokio_tungstenite::client_async(request, stream).await
+}
+
+/// Handshake request for a node console WebSocket, with the Host and Origin the node expects.
+/// Without a caller-provided Origin (the dashboard/browser origin) the node's own is used.
+pub fn upstream_request(target_url: &str, origin_header: Option<String>) -> anyhow::Result<axum::http::request::Builder> {
     use tokio_tungstenite::tungstenite::handshake::client::generate_key;
-    use axum::http::Request;
 
-    // Connect to the Proxmox/Incus WebSocket with auth if provided
     let uri = target_url.parse::<axum::http::Uri>()?;
     let host = uri.host().ok_or_else(|| anyhow::anyhow!("No host in target URL"))?;
     let port_u16 = uri.port_u16();
-    let is_standard_port = match (uri.scheme_str(), port_u16) {
-        (Some("wss"), Some(443)) | (Some("ws"), Some(80)) | (_, None) => true,
-        _ => false,
-    };
+    let is_standard_port = matches!(
+        (uri.scheme_str(), port_u16),
+        (Some("wss"), Some(443)) | (Some("ws"), Some(80)) | (_, None)
+    );
     
     let port_suffix = if is_standard_port { "".to_string() } else { format!(":{}", port_u16.unwrap()) };
     let scheme = if uri.scheme_str() == Some("wss") { "https" } else { "http" };
 
-    // Validate VNC ticket is present in URL
-    if !target_url.contains("vncticket=") {
-        error!("VNC connection attempted without ticket in URL");
-        return Err(anyhow::anyhow!("Missing VNC ticket in URL"));
-    }
+    let origin_value = origin_header.unwrap_or_else(|| format!("{}://{}{}", scheme, host, port_suffix));
+    debug!("Establishing console WebSocket connection to {}:{} (origin: {})", host, port_u16.unwrap_or(8006), origin_value);
 
-    let mut request = Request::builder()
-        .uri(&target_url)
+    Ok(axum::http::Request::builder()
+        .uri(target_url)
         .header("Connection", "Upgrade")
         .header("Upgrade", "websocket")
         .header("Sec-WebSocket-Version", "13")
         .header("Sec-WebSocket-Key", generate_key())
         .header("User-Agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36")
-        .header("Host", format!("{}{}", host, port_suffix));
+        .header("Host", format!("{}{}", host, port_suffix))
+        .header("Origin", origin_value))
+}
 
-    // Determine Origin to send: prefer caller-provided Origin (the dashboard/browser origin)
-    let origin_value = origin_header.clone().unwrap_or_else(|| format!("{}://{}{}", scheme, host, port_suffix));
-    debug!("Establishing VNC WebSocket connection to {}:{} (origin: {})", host, port_u16.unwrap_or(8006), origin_value);
-    request = request.header("Origin", origin_value);
+pub async fn proxy_vnc(
+    target_url: String,
+    client_ws: axum::extract::ws::WebSocket,
+    auth_header: Option<String>,
+    origin_header: Option<String>,
+    tls: Arc<rustls::ClientConfig>,
+    session: &VncSession,
+) -> anyhow::Result<()> {
+    // Validate VNC ticket is present in URL
+    if !target_url.contains("vncticket=") {
+        error!("VNC connection attempted without ticket in URL");
+        return Err(anyhow::anyhow!("Missing VNC ticket in URL"));
+    }
+
+    // Connect to the Proxmox/Incus WebSocket with auth if provided
+    let mut request = upstream_request(&target_url, origin_header)?;
 
     // Pass auth header if provided (API token for Proxmox/Incus, or PVEAuthCookie for Proxmox VNC)
     if let Some(auth) = auth_header {
@@ -54,24 +88,8 @@ pub async fn proxy_vnc(
         }
     }
 
-    // Create a TLS connector that accepts invalid certificates (for self-signed certs on internal nodes)
-    let connector = if uri.scheme_str() == Some("wss") {
-        let mut connector_builder = native_tls::TlsConnector::builder();
-        connector_builder.danger_accept_invalid_certs(true);
-        connector_builder.danger_accept_invalid_hostnames(true);
-        let tls_connector = connector_builder.build()?;
-        Some(Connector::NativeTls(tls_connector))
-    } else {
-        None
-    };
-
-    // Add timeout to connection establishment
-    let connect_future = tokio_tungstenite::connect_async_tls_with_config(
-        request.body(()).unwrap(),
-        None,
-        false,
-        connector,
-    );
+    // Add timeout to connection establishment; TLS is verified per the node's settings
+    let connect_future = connect_upstream(request.body(()).unwrap(), tls);
     let (backend_ws, response) = match tokio::time::timeout(Duration::from_secs(15), connect_future).await {
         Ok(Ok(ws)) => ws,
         Ok(Err(e)) => {
@@ -135,17 +153,51 @@ pub async fn proxy_vnc(
     let (mut backend_sender, mut backend_receiver) = backend_ws.split();
     let (mut client_sender, mut client_receiver) = client_ws.split();
 
-    // Proxy loop: Client to Backend
+    // Proxy loop: Client to Backend, with typed text and key combos slotted in between the browser's messages
     let client_to_backend = async {
-        while let Some(msg_result) = client_receiver.next().await {
+        loop {
+            let msg_result = tokio::select! {
+                msg = client_receiver.next() => match msg {
+                    Some(msg) => msg,
+                    None => break,
+                },
+                Some(mut injection) = session.next_injection() => {
+                    if !session.accepts_injection() {
+                        injection.finish(false);
+                        continue;
+                    }
+                    session.record_injected();
+                    let bytes = std::mem::take(&mut injection.bytes);
+                    if let Err(e) = backend_sender.send(Message::Binary(bytes)).await {
+                        error!("❌ Error sending to backend: {}", e);
+                        injection.finish(false);
+                        break;
+                    }
+                    injection.finish(true);
+                    continue;
+                }
+            };
             match msg_result {
                 Ok(axum::extract::ws::Message::Binary(bin)) => {
+                    session.record_from_client(&bin);
+                    let bin = match session.filter_input(bin) {
+                        Ok(bin) if bin.is_empty() => continue,
+                        Ok(bin) => bin,
+                        Err(e) => {
+                            error!("❌ Closing view-only session {}: {}", session.id(), e);
+                            break;
+                        }
+                    };
                     if let Err(e) = backend_sender.send(Message::Binary(bin)).await {
                         error!("❌ Error sending to backend: {}", e);
                         break;
                     }
                 }
                 Ok(axum::extract::ws::Message::Text(txt)) => {
+                    session.record_from_client(txt.as_bytes());
+                    if session.is_view_only() {
+                        continue;
+                    }
                     if let Err(e) = backend_sender.send(Message::Text(txt)).await {
                         error!("❌ Error sending to backend: {}", e);
                         break;
@@ -176,12 +228,14 @@ pub async fn proxy_vnc(
         while let Some(msg_result) = backend_receiver.next().await {
             match msg_result {
                 Ok(Message::Binary(bin)) => {
+                    session.record_to_client(&bin);
                     if let Err(e) = client_sender.send(axum::extract::ws::Message::Binary(bin)).await {
                         error!("❌ Error sending to client: {}", e);
                         break;
                     }
                 }
                 Ok(Message::Text(txt)) => {
+                    session.record_to_client(txt.as_bytes());
                     if let Err(e) = client_sender.send(axum::extract::ws::Message::Text(txt)).await {
                         error!("❌ Error sending to client: {}", e);
                         break;
@@ -209,13 +263,22 @@ pub async fn proxy_vnc(
     };
 
     // Run both proxy directions concurrently
-    tokio::select! {
+    let closed_by_backend = tokio::select! {
         _ = client_to_backend => {
             debug!("📤 Client to backend completed");
+            None
         },
         _ = backend_to_client => {
             debug!("📥 Backend to client completed");
+            None
         },
+        reason = session.closed() => Some(reason),
+    };
+
+    if let Some(reason) = closed_by_backend {
+        info!("⏱️ Closing VNC session {}: {:?}", session.id(), reason);
+        let _ = client_sender.send(axum::extract::ws::Message::Close(None)).await;
+        let _ = backend_sender.send(Message::Close(None)).await;
     }
 
     Ok(())
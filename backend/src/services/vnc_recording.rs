@@ -0,0 +1,311 @@
+use axum::extract::ws::{Message, WebSocket};
+use futures_util::{SinkExt, StreamExt};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+use crate::db::DbPool;
+use crate::services::vnc_sessions::VncSessionInfo;
+
+/// File header; every frame after it is `u32 LE offset_ms`, `u32 LE length`, then the bytes
+const MAGIC: &[u8; 8] = b"FVREC01\n";
+const FRAME_HEADER_LEN: usize = 8;
+
+/// Recording is enabled by pointing `VNC_RECORDINGS_DIR` at a writable directory
+pub fn recordings_dir() -> Option<PathBuf> {
+    std::env::var("VNC_RECORDINGS_DIR").ok().filter(|d| !d.is_empty()).map(PathBuf::from)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HandshakeState {
+    ServerVersion,
+    ClientVersion,
+    SecurityTypes,
+    ClientChoice,
+    Challenge,
+    SecurityResult,
+    Recording,
+    Abandoned,
+}
+
+/// Follows the RFB handshake on both directions so only what comes after authentication
+/// (ServerInit onwards) is recorded. Playback substitutes its own unauthenticated handshake,
+/// which keeps tickets out of the recording and lets noVNC connect without credentials.
+struct RfbHandshake {
+    state: HandshakeState,
+    server: Vec<u8>,
+    client: Vec<u8>,
+    minor: u8,
+}
+
+impl RfbHandshake {
+    fn new() -> Self {
+        Self { state: HandshakeState::ServerVersion, server: Vec::new(), client: Vec::new(), minor: 8 }
+    }
+
+    /// Feed server-to-client bytes; returns whatever now belongs to the recorded stream
+    fn server(&mut self, bytes: &[u8]) -> Option<Vec<u8>> {
+        match self.state {
+            HandshakeState::Recording => Some(bytes.to_vec()),
+            HandshakeState::Abandoned => None,
+            _ => {
+                self.server.extend_from_slice(bytes);
+                self.advance()
+            }
+        }
+    }
+
+    /// Feed client-to-server bytes, which only matter until the security type is chosen
+    fn client(&mut self, bytes: &[u8]) -> Option<Vec<u8>> {
+        match self.state {
+            HandshakeState::ServerVersion | HandshakeState::ClientVersion | HandshakeState::SecurityTypes | HandshakeState::ClientChoice => {
+                self.client.extend_from_slice(bytes);
+                self.advance()
+            }
+            _ => None,
+        }
+    }
+
+    fn take_server(&mut self, n: usize) -> Option<Vec<u8>> {
+        (self.server.len() >= n).then(|| self.server.drain(..n).collect())
+    }
+
+    fn take_client(&mut self, n: usize) -> Option<Vec<u8>> {
+        (self.client.len() >= n).then(|| self.client.drain(..n).collect())
+    }
+
+    fn after_choice(&self, security_type: u32) -> HandshakeState {
+        match security_type {
+            // None: RFB 3.8 still sends a SecurityResult
+            1 if self.minor >= 8 => HandshakeState::SecurityResult,
+            1 => HandshakeState::Recording,
+            // VNC authentication (Proxmox uses the ticket as password)
+            2 => HandshakeState::Challenge,
+            other => {
+                tracing::warn!("Not recording VNC session: unsupported security type {}", other);
+                HandshakeState::Abandoned
+            }
+        }
+    }
+
+    fn advance(&mut self) -> Option<Vec<u8>> {
+        loop {
+            let next = match self.state {
+                HandshakeState::ServerVersion => match self.take_server(12) {
+                    Some(_) => HandshakeState::ClientVersion,
+                    None => return None,
+                },
+                // The version the client answers with decides the rest of the handshake
+                HandshakeState::ClientVersion => match self.take_client(12) {
+                    Some(version) => {
+                        let minor = std::str::from_utf8(&version[8..11]).ok().and_then(|m| m.parse::<u16>().ok()).unwrap_or(3);
+                        self.minor = match minor { 8.. => 8, 7 => 7, _ => 3 };
+                        HandshakeState::SecurityTypes
+                    }
+                    None => return None,
+                },
+                HandshakeState::SecurityTypes if self.minor == 3 => match self.take_server(4) {
+                    Some(t) => match u32::from_be_bytes([t[0], t[1], t[2], t[3]]) {
+                        0 => HandshakeState::Abandoned,
+                        t => self.after_choice(t),
+                    },
+                    None => return None,
+                },
+                HandshakeState::SecurityTypes => {
+                    let count = *self.server.first()? as usize;
+                    match self.take_server(1 + count) {
+                        Some(_) if count == 0 => HandshakeState::Abandoned,
+                        Some(_) => HandshakeState::ClientChoice,
+                        None => return None,
+                    }
+                }
+                HandshakeState::ClientChoice => match self.take_client(1) {
+                    Some(choice) => self.after_choice(choice[0] as u32),
+                    None => return None,
+                },
+                HandshakeState::Challenge => match self.take_server(16) {
+                    Some(_) => HandshakeState::SecurityResult,
+                    None => return None,
+                },
+                HandshakeState::SecurityResult => match self.take_server(4) {
+                    Some(r) if r == [0, 0, 0, 0] => HandshakeState::Recording,
+                    Some(_) => HandshakeState::Abandoned,
+                    None => return None,
+                },
+                HandshakeState::Recording => {
+                    self.client.clear();
+                    return Some(std::mem::take(&mut self.server));
+                }
+                HandshakeState::Abandoned => {
+                    self.server.clear();
+                    self.client.clear();
+                    return None;
+                }
+            };
+            self.state = next;
+        }
+    }
+}
+
+struct Frame {
+    offset_ms: u32,
+    data: Vec<u8>,
+}
+
+/// Tees one session's RFB stream to disk; the file is finalized when this is dropped
+pub struct Recording {
+    handshake: RfbHandshake,
+    started: Instant,
+    tx: mpsc::UnboundedSender<Frame>,
+}
+
+impl Recording {
+    pub async fn start(pool: &DbPool, dir: &Path, session: &VncSessionInfo) -> anyhow::Result<Self> {
+        tokio::fs::create_dir_all(dir).await?;
+
+        let id = Uuid::new_v4();
+        let file_name = format!("{}.fvrec", id);
+        let mut file = BufWriter::new(File::create(dir.join(&file_name)).await?);
+        file.write_all(MAGIC).await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO vnc_recordings (id, session_id, user_id, username, node_id, vm_id, client_ip, file_name)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            "#
+        )
+        .bind(id)
+        .bind(session.id)
+        .bind(session.user_id)
+        .bind(&session.username)
+        .bind(session.node_id)
+        .bind(&session.vm_id)
+        .bind(&session.client_ip)
+        .bind(&file_name)
+        .execute(pool)
+        .await?;
+
+        let started = Instant::now();
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(write_frames(pool.clone(), id, file, rx, started));
+
+        tracing::info!("🎥 Recording VNC session {} as {}", session.id, id);
+        Ok(Self { handshake: RfbHandshake::new(), started, tx })
+    }
+
+    pub fn server_bytes(&mut self, bytes: &[u8]) {
+        if let Some(data) = self.handshake.server(bytes) {
+            self.push(data);
+        }
+    }
+
+    pub fn client_bytes(&mut self, bytes: &[u8]) {
+        if let Some(data) = self.handshake.client(bytes) {
+            self.push(data);
+        }
+    }
+
+    fn push(&self, data: Vec<u8>) {
+        if data.is_empty() {
+            return;
+        }
+        let offset_ms = self.started.elapsed().as_millis().min(u32::MAX as u128) as u32;
+        let _ = self.tx.send(Frame { offset_ms, data });
+    }
+}
+
+async fn write_frames(pool: DbPool, id: Uuid, mut file: BufWriter<File>, mut rx: mpsc::UnboundedReceiver<Frame>, started: Instant) {
+    let mut size = MAGIC.len() as i64;
+
+    while let Some(frame) = rx.recv().await {
+        let mut header = [0u8; FRAME_HEADER_LEN];
+        header[..4].copy_from_slice(&frame.offset_ms.to_le_bytes());
+        header[4..].copy_from_slice(&(frame.data.len() as u32).to_le_bytes());
+
+        if let Err(e) = async {
+            file.write_all(&header).await?;
+            file.write_all(&frame.data).await
+        }.await {
+            tracing::error!("Failed to write VNC recording {}: {}", id, e);
+            break;
+        }
+        size += (FRAME_HEADER_LEN + frame.data.len()) as i64;
+    }
+
+    if let Err(e) = file.flush().await {
+        tracing::error!("Failed to flush VNC recording {}: {}", id, e);
+    }
+
+    let result = sqlx::query("UPDATE vnc_recordings SET ended_at = NOW(), duration_ms = $1, size_bytes = $2 WHERE id = $3")
+        .bind(started.elapsed().as_millis() as i64)
+        .bind(size)
+        .bind(id)
+        .execute(&pool)
+        .await;
+    if let Err(e) = result {
+        tracing::error!("Failed to finalize VNC recording {}: {}", id, e);
+    }
+}
+
+/// Read the next frame, or None at the end of the file (including a frame cut off mid-write)
+async fn read_frame(file: &mut BufReader<File>) -> anyhow::Result<Option<Frame>> {
+    let mut header = [0u8; FRAME_HEADER_LEN];
+    match file.read_exact(&mut header).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+
+    let offset_ms = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+    let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+    let mut data = vec![0u8; len];
+    match file.read_exact(&mut data).await {
+        Ok(_) => Ok(Some(Frame { offset_ms, data })),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Stream a recording to a noVNC client at its original pace. Input from the viewer is read
+/// and discarded; the connection closes after the last frame.
+pub async fn play(path: &Path, ws: WebSocket) -> anyhow::Result<()> {
+    let mut file = BufReader::new(File::open(path).await?);
+    let mut magic = [0u8; MAGIC.len()];
+    file.read_exact(&mut magic).await?;
+    if &magic != MAGIC {
+        anyhow::bail!("Not a VNC recording");
+    }
+
+    let (mut sender, mut receiver) = ws.split();
+
+    let replay = async {
+        // RFB 3.8 with security type None, then the recorded stream from ServerInit on
+        sender.send(Message::Binary(b"RFB 003.008\n".to_vec())).await?;
+        sender.send(Message::Binary(vec![1, 1])).await?;
+        sender.send(Message::Binary(0u32.to_be_bytes().to_vec())).await?;
+
+        let start = tokio::time::Instant::now();
+        while let Some(frame) = read_frame(&mut file).await? {
+            tokio::time::sleep_until(start + Duration::from_millis(frame.offset_ms as u64)).await;
+            sender.send(Message::Binary(frame.data)).await?;
+        }
+
+        let _ = sender.send(Message::Close(None)).await;
+        anyhow::Ok(())
+    };
+
+    let viewer_gone = async {
+        while let Some(Ok(msg)) = receiver.next().await {
+            if matches!(msg, Message::Close(_)) {
+                break;
+            }
+        }
+    };
+
+    tokio::select! {
+        result = replay => result,
+        _ = viewer_gone => Ok(()),
+    }
+}
@@ -0,0 +1,389 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot, Notify};
+use uuid::Uuid;
+use crate::models::user::User;
+use crate::services::rfb::RfbClientStream;
+use crate::services::vnc_recording::Recording;
+
+/// How often the idle timeout is checked
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+/// Concurrency limits and timeouts applied to every console session
+#[derive(Debug, Clone, Copy)]
+pub struct SessionLimits {
+    pub per_user: usize,
+    pub per_vm: usize,
+    pub idle_timeout: Duration,
+    pub max_duration: Duration,
+}
+
+impl SessionLimits {
+    pub fn from_env() -> Self {
+        Self {
+            per_user: env_or("VNC_MAX_SESSIONS_PER_USER", 3),
+            per_vm: env_or("VNC_MAX_SESSIONS_PER_VM", 2),
+            idle_timeout: Duration::from_secs(env_or("VNC_IDLE_TIMEOUT_SECS", 15 * 60)),
+            max_duration: Duration::from_secs(env_or("VNC_MAX_SESSION_SECS", 8 * 60 * 60)),
+        }
+    }
+}
+
+/// Which concurrency limit refused a new session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitExceeded {
+    User,
+    Vm,
+}
+
+/// Why a session was ended by the backend rather than by either peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloseReason {
+    Terminated,
+    IdleTimeout,
+    MaxDuration,
+}
+
+/// Why client messages could not be injected into a session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InjectError {
+    NotFound,
+    ViewOnly,
+    /// The browser has not finished the RFB handshake, or its stream could not be followed
+    NotReady,
+}
+
+/// Client messages queued for a session's proxy loop to write upstream
+pub struct Injection {
+    pub bytes: Vec<u8>,
+    done: oneshot::Sender<bool>,
+}
+
+impl Injection {
+    /// Report whether the bytes were written
+    pub fn finish(self, written: bool) {
+        let _ = self.done.send(written);
+    }
+}
+
+/// Who opened a session: a dashboard user or a console share link
+#[derive(Debug, Clone)]
+pub struct SessionOwner {
+    pub user_id: Option<Uuid>,
+    pub share_link_id: Option<Uuid>,
+    pub name: String,
+}
+
+impl SessionOwner {
+    pub fn user(user: &User) -> Self {
+        Self { user_id: Some(user.id), share_link_id: None, name: user.username.clone() }
+    }
+
+    pub fn share_link(id: Uuid, label: Option<&str>) -> Self {
+        let name = format!("share:{}", label.unwrap_or(&id.to_string()));
+        Self { user_id: None, share_link_id: Some(id), name }
+    }
+
+    /// Concurrency limits count per user, or per link for shared sessions
+    fn key(&self) -> Option<Uuid> {
+        self.user_id.or(self.share_link_id)
+    }
+}
+
+/// A live session as shown to admins
+#[derive(Debug, Serialize)]
+pub struct VncSessionInfo {
+    pub id: Uuid,
+    pub user_id: Option<Uuid>,
+    pub share_link_id: Option<Uuid>,
+    pub username: String,
+    pub node_id: Uuid,
+    pub vm_id: String,
+    pub client_ip: Option<String>,
+    pub started_at: DateTime<Utc>,
+    pub last_activity_at: DateTime<Utc>,
+    pub bytes_from_client: u64,
+    pub bytes_to_client: u64,
+    pub view_only: bool,
+}
+
+struct SessionEntry {
+    id: Uuid,
+    owner: SessionOwner,
+    node_id: Uuid,
+    vm_id: String,
+    client_ip: Option<String>,
+    started_at: DateTime<Utc>,
+    started: Instant,
+    /// Milliseconds after `started` of the last user input
+    last_activity_ms: AtomicU64,
+    bytes_from_client: AtomicU64,
+    bytes_to_client: AtomicU64,
+    view_only: bool,
+    terminate: Notify,
+    inject: mpsc::UnboundedSender<Injection>,
+}
+
+impl SessionEntry {
+    fn info(&self) -> VncSessionInfo {
+        let idle_ms = self.last_activity_ms.load(Ordering::Relaxed);
+        VncSessionInfo {
+            id: self.id,
+            user_id: self.owner.user_id,
+            share_link_id: self.owner.share_link_id,
+            username: self.owner.name.clone(),
+            node_id: self.node_id,
+            vm_id: self.vm_id.clone(),
+            client_ip: self.client_ip.clone(),
+            started_at: self.started_at,
+            last_activity_at: self.started_at + chrono::Duration::milliseconds(idle_ms as i64),
+            bytes_from_client: self.bytes_from_client.load(Ordering::Relaxed),
+            bytes_to_client: self.bytes_to_client.load(Ordering::Relaxed),
+            view_only: self.view_only,
+        }
+    }
+}
+
+/// In-memory registry of proxied console sessions, enforcing concurrency limits on open
+#[derive(Clone)]
+pub struct VncSessions {
+    sessions: Arc<Mutex<HashMap<Uuid, Arc<SessionEntry>>>>,
+    limits: SessionLimits,
+}
+
+impl Default for VncSessions {
+    fn default() -> Self {
+        Self::new(SessionLimits::from_env())
+    }
+}
+
+impl VncSessions {
+    pub fn new(limits: SessionLimits) -> Self {
+        Self { sessions: Arc::new(Mutex::new(HashMap::new())), limits }
+    }
+
+    /// Register a session, refusing it when the owner or the VM is already at its limit.
+    /// View-only sessions have every input message stripped by the proxy.
+    pub fn open(&self, owner: SessionOwner, node_id: Uuid, vm_id: &str, client_ip: Option<String>, view_only: bool) -> Result<VncSession, LimitExceeded> {
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions.values().filter(|s| s.owner.key() == owner.key()).count() >= self.limits.per_user {
+            return Err(LimitExceeded::User);
+        }
+        if sessions.values().filter(|s| s.node_id == node_id && s.vm_id == vm_id).count() >= self.limits.per_vm {
+            return Err(LimitExceeded::Vm);
+        }
+
+        let (inject, injections) = mpsc::unbounded_channel();
+        let entry = Arc::new(SessionEntry {
+            id: Uuid::new_v4(),
+            owner,
+            node_id,
+            vm_id: vm_id.to_string(),
+            client_ip,
+            started_at: Utc::now(),
+            started: Instant::now(),
+            last_activity_ms: AtomicU64::new(0),
+            bytes_from_client: AtomicU64::new(0),
+            bytes_to_client: AtomicU64::new(0),
+            view_only,
+            terminate: Notify::new(),
+            inject,
+        });
+        sessions.insert(entry.id, entry.clone());
+
+        Ok(VncSession {
+            entry,
+            registry: self.clone(),
+            recording: None,
+            client_stream: Mutex::new(RfbClientStream::new(view_only)),
+            injections: tokio::sync::Mutex::new(injections),
+            deadline: None,
+        })
+    }
+
+    pub fn list(&self) -> Vec<VncSessionInfo> {
+        let mut sessions: Vec<VncSessionInfo> = self.sessions.lock().unwrap().values().map(|s| s.info()).collect();
+        sessions.sort_by_key(|s| s.started_at);
+        sessions
+    }
+
+    /// Ask a session's proxy loop to close it; false if no such session is live
+    pub fn terminate(&self, id: Uuid) -> bool {
+        match self.sessions.lock().unwrap().get(&id) {
+            Some(entry) => {
+                // notify_one stores a permit, so a proxy loop that is not waiting yet still sees it
+                entry.terminate.notify_one();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Close every session opened through a share link, e.g. once it is revoked
+    pub fn terminate_share_link(&self, link_id: Uuid) -> usize {
+        let sessions = self.sessions.lock().unwrap();
+        let matching: Vec<_> = sessions.values().filter(|s| s.owner.share_link_id == Some(link_id)).collect();
+        for entry in &matching {
+            entry.terminate.notify_one();
+        }
+        matching.len()
+    }
+
+    /// Write client messages (e.g. key events) upstream in a live session, between the
+    /// browser's own messages. View-only sessions never accept input.
+    pub async fn inject(&self, id: Uuid, bytes: Vec<u8>) -> Result<(), InjectError> {
+        let inject = {
+            let sessions = self.sessions.lock().unwrap();
+            let entry = sessions.get(&id).ok_or(InjectError::NotFound)?;
+            if entry.view_only {
+                return Err(InjectError::ViewOnly);
+            }
+            entry.inject.clone()
+        };
+
+        let (done, written) = oneshot::channel();
+        inject.send(Injection { bytes, done }).map_err(|_| InjectError::NotFound)?;
+        match written.await {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(InjectError::NotReady),
+            // The proxy loop ended before getting to it
+            Err(_) => Err(InjectError::NotFound),
+        }
+    }
+
+    /// Close a user's sessions on one VM, e.g. after their console access changes
+    pub fn terminate_user_vm(&self, user_id: Uuid, node_id: Uuid, vm_id: &str) -> usize {
+        let sessions = self.sessions.lock().unwrap();
+        let matching: Vec<_> = sessions
+            .values()
+            .filter(|s| s.owner.user_id == Some(user_id) && s.node_id == node_id && s.vm_id == vm_id)
+            .collect();
+        for entry in &matching {
+            entry.terminate.notify_one();
+        }
+        matching.len()
+    }
+}
+
+/// Handle held by the proxy loop; dropping it removes the session from the registry
+pub struct VncSession {
+    entry: Arc<SessionEntry>,
+    registry: VncSessions,
+    recording: Option<Mutex<Recording>>,
+    client_stream: Mutex<RfbClientStream>,
+    injections: tokio::sync::Mutex<mpsc::UnboundedReceiver<Injection>>,
+    /// Hard end before the configured maximum, e.g. when a share link expires
+    deadline: Option<DateTime<Utc>>,
+}
+
+impl VncSession {
+    pub fn id(&self) -> Uuid {
+        self.entry.id
+    }
+
+    pub fn info(&self) -> VncSessionInfo {
+        self.entry.info()
+    }
+
+    /// End the session no later than `at`
+    pub fn end_by(&mut self, at: DateTime<Utc>) {
+        self.deadline = Some(at);
+    }
+
+    /// What to forward upstream for a browser frame; view-only sessions drop input messages
+    pub fn filter_input(&self, frame: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+        self.client_stream.lock().unwrap().process(&frame)
+    }
+
+    pub fn is_view_only(&self) -> bool {
+        self.entry.view_only
+    }
+
+    /// Next batch of messages queued through `VncSessions::inject`
+    pub async fn next_injection(&self) -> Option<Injection> {
+        self.injections.lock().await.recv().await
+    }
+
+    /// Whether injected messages can be written upstream right now
+    pub fn accepts_injection(&self) -> bool {
+        self.client_stream.lock().unwrap().at_message_boundary()
+    }
+
+    /// Injected input counts as activity like the browser's own
+    pub fn record_injected(&self) {
+        let elapsed = self.entry.started.elapsed().as_millis() as u64;
+        self.entry.last_activity_ms.store(elapsed, Ordering::Relaxed);
+    }
+
+    /// Tee the session's traffic into a recording from now on
+    pub fn set_recording(&mut self, recording: Recording) {
+        self.recording = Some(Mutex::new(recording));
+    }
+
+    /// Count a frame from the browser. RFB framebuffer update requests are sent continuously
+    /// by noVNC, so only other messages (keys, pointer, clipboard) count as activity.
+    pub fn record_from_client(&self, frame: &[u8]) {
+        self.entry.bytes_from_client.fetch_add(frame.len() as u64, Ordering::Relaxed);
+        if let Some(recording) = &self.recording {
+            recording.lock().unwrap().client_bytes(frame);
+        }
+        let is_update_request = frame.len() == 10 && frame[0] == 3;
+        if !is_update_request {
+            let elapsed = self.entry.started.elapsed().as_millis() as u64;
+            self.entry.last_activity_ms.store(elapsed, Ordering::Relaxed);
+        }
+    }
+
+    pub fn record_to_client(&self, frame: &[u8]) {
+        self.entry.bytes_to_client.fetch_add(frame.len() as u64, Ordering::Relaxed);
+        if let Some(recording) = &self.recording {
+            recording.lock().unwrap().server_bytes(frame);
+        }
+    }
+
+    /// Resolves when the session must end: killed by an admin, idle or past its maximum duration.
+    /// View-only sessions never send input, so only the maximum duration applies to them.
+    pub async fn closed(&self) -> CloseReason {
+        let limits = self.registry.limits;
+        let mut max_duration = limits.max_duration;
+        if let Some(deadline) = self.deadline {
+            let remaining = (deadline - Utc::now()).to_std().unwrap_or_default();
+            max_duration = max_duration.min(self.entry.started.elapsed() + remaining);
+        }
+        let deadline = tokio::time::Instant::from_std(self.entry.started + max_duration);
+
+        let idle = async {
+            if self.entry.view_only {
+                return std::future::pending().await;
+            }
+            let mut interval = tokio::time::interval(IDLE_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+                let last = Duration::from_millis(self.entry.last_activity_ms.load(Ordering::Relaxed));
+                if self.entry.started.elapsed().saturating_sub(last) >= limits.idle_timeout {
+                    break;
+                }
+            }
+        };
+
+        tokio::select! {
+            _ = self.entry.terminate.notified() => CloseReason::Terminated,
+            _ = tokio::time::sleep_until(deadline) => CloseReason::MaxDuration,
+            _ = idle => CloseReason::IdleTimeout,
+        }
+    }
+}
+
+impl Drop for VncSession {
+    fn drop(&mut self) {
+        self.registry.sessions.lock().unwrap().remove(&self.entry.id);
+    }
+}
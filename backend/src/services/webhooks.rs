@@ -0,0 +1,295 @@
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use sha2::Sha256;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+use uuid::Uuid;
+use crate::db::DbPool;
+use crate::models::webhook::WebhookDelivery;
+
+/// Event types a subscription can filter on
+pub const EVENT_TYPES: &[&str] = &[
+    "vm.power",
+    "vm.config_updated",
+    "node.status_changed",
+    "node.updated",
+    "support.ticket_created",
+];
+pub const TEST_EVENT: &str = "webhook.test";
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+const BATCH_SIZE: i64 = 20;
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_ATTEMPTS: i32 = 8;
+/// First retry after 30s, doubling up to an hour
+const BASE_BACKOFF_SECS: i64 = 30;
+const MAX_BACKOFF_SECS: i64 = 3600;
+
+/// Envelope every subscriber receives
+#[derive(Serialize)]
+struct WebhookEvent<'a, T: Serialize> {
+    id: Uuid,
+    #[serde(rename = "type")]
+    event_type: &'a str,
+    timestamp: chrono::DateTime<Utc>,
+    data: T,
+}
+
+/// Whether an event filter entry such as "vm.power", "vm.*" or "*" is valid
+pub fn valid_event_filter(filter: &str) -> bool {
+    filter == "*"
+        || EVENT_TYPES.contains(&filter)
+        || filter.strip_suffix(".*").is_some_and(|prefix| EVENT_TYPES.iter().any(|t| t.starts_with(&format!("{}.", prefix))))
+}
+
+/// `sha256=<hex>` HMAC over "<timestamp>.<body>" so receivers can reject replays of old payloads
+pub fn sign(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+pub fn generate_secret() -> String {
+    hex::encode(rand::random::<[u8; 32]>())
+}
+
+fn backoff_secs(attempts: i32) -> i64 {
+    BASE_BACKOFF_SECS.saturating_mul(1 << (attempts - 1).clamp(0, 16)).min(MAX_BACKOFF_SECS)
+}
+
+/// Queues infrastructure events for webhook subscribers and delivers them in the background.
+///
+/// Deliveries are rows in `webhook_deliveries`, so queued events survive restarts and every
+/// attempt is visible in the delivery log.
+#[derive(Clone)]
+pub struct WebhookDispatcher {
+    pool: DbPool,
+    wake: Arc<Notify>,
+}
+
+impl WebhookDispatcher {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool, wake: Arc::new(Notify::new()) }
+    }
+
+    /// Queue an event for every enabled subscription whose filter matches
+    pub async fn emit<T: Serialize>(&self, event_type: &str, data: T) {
+        let event_id = Uuid::new_v4();
+        let payload = match serde_json::to_value(WebhookEvent {
+            id: event_id,
+            event_type,
+            timestamp: Utc::now(),
+            data,
+        }) {
+            Ok(p) => p,
+            Err(e) => {
+                tracing::error!("Failed to serialize {} event: {}", event_type, e);
+                return;
+            }
+        };
+
+        let prefix = format!("{}.*", event_type.split('.').next().unwrap_or_default());
+        let result = sqlx::query(
+            r#"
+            INSERT INTO webhook_deliveries (webhook_id, event_id, event_type, payload)
+            SELECT id, $1, $2, $3
+            FROM webhooks
+            WHERE enabled AND (events = '{}' OR $2 = ANY(events) OR $4 = ANY(events) OR '*' = ANY(events))
+            "#
+        )
+        .bind(event_id)
+        .bind(event_type)
+        .bind(&payload)
+        .bind(prefix)
+        .execute(&self.pool)
+        .await;
+
+        match result {
+            Ok(r) if r.rows_affected() > 0 => self.wake.notify_one(),
+            Ok(_) => {}
+            Err(e) => tracing::error!("Failed to queue {} webhook event: {}", event_type, e),
+        }
+    }
+
+    /// Queue a test event for one subscription regardless of its filter
+    pub async fn emit_test(&self, webhook_id: Uuid) -> anyhow::Result<Uuid> {
+        let event_id = Uuid::new_v4();
+        let payload = serde_json::to_value(WebhookEvent {
+            id: event_id,
+            event_type: TEST_EVENT,
+            timestamp: Utc::now(),
+            data: serde_json::json!({ "message": "Test event from FOSSVPS" }),
+        })?;
+
+        let delivery_id = sqlx::query_scalar::<_, Uuid>(
+            "INSERT INTO webhook_deliveries (webhook_id, event_id, event_type, payload) VALUES ($1, $2, $3, $4) RETURNING id"
+        )
+        .bind(webhook_id)
+        .bind(event_id)
+        .bind(TEST_EVENT)
+        .bind(&payload)
+        .fetch_one(&self.pool)
+        .await?;
+
+        self.wake.notify_one();
+        Ok(delivery_id)
+    }
+
+    /// Re-queue a delivery (e.g. one that failed permanently) for an immediate attempt
+    pub async fn redeliver(&self, webhook_id: Uuid, delivery_id: Uuid) -> anyhow::Result<Option<WebhookDelivery>> {
+        let delivery = sqlx::query_as::<_, WebhookDelivery>(
+            r#"
+            UPDATE webhook_deliveries
+            SET status = 'pending', attempts = 0, next_attempt_at = NOW(), delivered_at = NULL
+            WHERE id = $1 AND webhook_id = $2
+            RETURNING id, webhook_id, event_id, event_type, payload, status, attempts, next_attempt_at, last_status_code, last_error, created_at, delivered_at
+            "#
+        )
+        .bind(delivery_id)
+        .bind(webhook_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        if delivery.is_some() {
+            self.wake.notify_one();
+        }
+        Ok(delivery)
+    }
+
+    pub async fn run(self) {
+        let http = reqwest::Client::builder()
+            .timeout(DELIVERY_TIMEOUT)
+            .build()
+            .expect("Failed to build webhook HTTP client");
+
+        loop {
+            tokio::select! {
+                _ = self.wake.notified() => {}
+                _ = tokio::time::sleep(POLL_INTERVAL) => {}
+            }
+
+            // Drain everything that is due before waiting again
+            loop {
+                match self.deliver_due(&http).await {
+                    Ok(n) if n as i64 == BATCH_SIZE => continue,
+                    Ok(_) => break,
+                    Err(e) => {
+                        tracing::error!("Webhook delivery round failed: {}", e);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    async fn deliver_due(&self, http: &reqwest::Client) -> anyhow::Result<usize> {
+        // Claim due deliveries by pushing their next attempt out; a crash mid-send just retries later
+        let due = sqlx::query_as::<_, (Uuid, String, String, String, serde_json::Value, i32)>(
+            r#"
+            UPDATE webhook_deliveries d
+            SET next_attempt_at = NOW() + INTERVAL '5 minutes', attempts = d.attempts + 1
+            FROM webhooks w
+            WHERE w.id = d.webhook_id
+              AND d.id IN (
+                SELECT id FROM webhook_deliveries
+                WHERE status = 'pending' AND next_attempt_at <= NOW()
+                  AND webhook_id IN (SELECT id FROM webhooks WHERE enabled)
+                ORDER BY next_attempt_at
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED
+              )
+            RETURNING d.id, w.url, w.secret, d.event_type, d.payload, d.attempts
+            "#
+        )
+        .bind(BATCH_SIZE)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let count = due.len();
+        let sends = due.into_iter().map(|(id, url, secret, event_type, payload, attempts)| {
+            let http = http.clone();
+            let pool = self.pool.clone();
+            async move {
+                let outcome = send(&http, id, &url, &secret, &event_type, &payload).await;
+                record_attempt(&pool, id, &event_type, attempts, outcome).await;
+            }
+        });
+        futures::future::join_all(sends).await;
+
+        Ok(count)
+    }
+}
+
+/// Result of one HTTP attempt: the status code if we got a response, and an error if it failed
+type AttemptOutcome = (Option<u16>, Option<String>);
+
+async fn send(
+    http: &reqwest::Client,
+    delivery_id: Uuid,
+    url: &str,
+    secret: &str,
+    event_type: &str,
+    payload: &serde_json::Value,
+) -> AttemptOutcome {
+    let body = match serde_json::to_vec(payload) {
+        Ok(b) => b,
+        Err(e) => return (None, Some(e.to_string())),
+    };
+    let timestamp = Utc::now().timestamp();
+
+    let result = http.post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header("X-FossVPS-Event", event_type)
+        .header("X-FossVPS-Delivery", delivery_id.to_string())
+        .header("X-FossVPS-Timestamp", timestamp.to_string())
+        .header("X-FossVPS-Signature", sign(secret, timestamp, &body))
+        .body(body)
+        .send()
+        .await;
+
+    match result {
+        Ok(resp) if resp.status().is_success() => (Some(resp.status().as_u16()), None),
+        Ok(resp) => (Some(resp.status().as_u16()), Some(format!("Receiver returned {}", resp.status()))),
+        Err(e) => (None, Some(e.to_string())),
+    }
+}
+
+async fn record_attempt(pool: &DbPool, id: Uuid, event_type: &str, attempts: i32, (status_code, error): AttemptOutcome) {
+    let result = match &error {
+        None => {
+            sqlx::query("UPDATE webhook_deliveries SET status = 'delivered', delivered_at = NOW(), last_status_code = $1, last_error = NULL WHERE id = $2")
+                .bind(status_code.map(i32::from))
+                .bind(id)
+                .execute(pool)
+                .await
+        }
+        Some(err) if attempts >= MAX_ATTEMPTS => {
+            tracing::warn!("❌ Webhook delivery {} ({}) failed permanently: {}", id, event_type, err);
+            sqlx::query("UPDATE webhook_deliveries SET status = 'failed', last_status_code = $1, last_error = $2 WHERE id = $3")
+                .bind(status_code.map(i32::from))
+                .bind(err)
+                .bind(id)
+                .execute(pool)
+                .await
+        }
+        Some(err) => {
+            let delay = backoff_secs(attempts);
+            tracing::debug!("Webhook delivery {} attempt {} failed, retrying in {}s: {}", id, attempts, delay, err);
+            sqlx::query("UPDATE webhook_deliveries SET next_attempt_at = NOW() + make_interval(secs => $1), last_status_code = $2, last_error = $3 WHERE id = $4")
+                .bind(delay as f64)
+                .bind(status_code.map(i32::from))
+                .bind(err)
+                .bind(id)
+                .execute(pool)
+                .await
+        }
+    };
+
+    if let Err(e) = result {
+        tracing::error!("Failed to record webhook delivery {}: {}", id, e);
+    }
+}
@@ -0,0 +1,52 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// How long a ticket can wait before the WebSocket is opened
+pub const TICKET_TTL: Duration = Duration::from_secs(30);
+
+struct TicketEntry {
+    user_id: Uuid,
+    route: String,
+    expires: Instant,
+}
+
+/// The same route is served with and without the `/api/v1` prefix
+fn normalize_route(path: &str) -> &str {
+    path.strip_prefix("/api/v1").unwrap_or(path)
+}
+
+/// One-time tickets that let a browser open a WebSocket without putting its JWT in the URL
+#[derive(Clone, Default)]
+pub struct WsTickets {
+    tickets: Arc<Mutex<HashMap<String, TicketEntry>>>,
+}
+
+impl WsTickets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Issue a ticket for `user_id`, valid only for the WebSocket at `route`
+    pub fn issue(&self, user_id: Uuid, route: &str) -> String {
+        let ticket = hex::encode(rand::random::<[u8; 32]>());
+
+        let now = Instant::now();
+        let mut tickets = self.tickets.lock().unwrap();
+        tickets.retain(|_, t| t.expires > now);
+        tickets.insert(ticket.clone(), TicketEntry {
+            user_id,
+            route: normalize_route(route).to_string(),
+            expires: now + TICKET_TTL,
+        });
+        ticket
+    }
+
+    /// Consume a ticket, returning its user if it is unexpired and was issued for `route`.
+    /// A ticket presented on the wrong route is burned all the same.
+    pub fn redeem(&self, ticket: &str, route: &str) -> Option<Uuid> {
+        let entry = self.tickets.lock().unwrap().remove(ticket)?;
+        (entry.expires > Instant::now() && entry.route == normalize_route(route)).then_some(entry.user_id)
+    }
+}
@@ -0,0 +1,43 @@
+use axum::extract::FromRef;
+use crate::clients::registry::ClientRegistry;
+use crate::db::DbPool;
+use crate::services::alerts::AlertEngine;
+use crate::services::guest_info::GuestInfoCache;
+use crate::services::inventory::InventoryWatcher;
+use crate::services::metrics::MetricsCollector;
+use crate::services::telemetry::Telemetry;
+use crate::services::vnc_sessions::VncSessions;
+use crate::services::webhooks::WebhookDispatcher;
+use crate::services::ws_tickets::WsTickets;
+
+/// Shared application state handed to every router
+#[derive(Clone, FromRef)]
+pub struct AppState {
+    pub pool: DbPool,
+    pub clients: ClientRegistry,
+    pub metrics: MetricsCollector,
+    pub telemetry: Telemetry,
+    pub alerts: AlertEngine,
+    pub webhooks: WebhookDispatcher,
+    pub inventory: InventoryWatcher,
+    pub vnc_sessions: VncSessions,
+    pub ws_tickets: WsTickets,
+    pub guest_info: GuestInfoCache,
+}
+
+impl AppState {
+    pub fn new(pool: DbPool) -> Self {
+        Self {
+            clients: ClientRegistry::new(pool.clone()),
+            metrics: MetricsCollector::new(),
+            telemetry: Telemetry::new(),
+            alerts: AlertEngine::new(),
+            webhooks: WebhookDispatcher::new(pool.clone()),
+            inventory: InventoryWatcher::new(),
+            vnc_sessions: VncSessions::default(),
+            ws_tickets: WsTickets::new(),
+            guest_info: GuestInfoCache::new(),
+            pool,
+        }
+    }
+}
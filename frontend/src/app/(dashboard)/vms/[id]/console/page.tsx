@@ -71,7 +71,10 @@ export default function ConsolePage({ params }: PageProps) {
                 setPort(vncInfo.port);
             } catch (err: any) {
                 console.error("[Console] Failed to fetch VNC ticket:", err);
-                setError("Failed to obtain security ticket for VNC session");
+                // 422 explains why this guest has no VNC console (Incus VMs only offer SPICE)
+                setError(err.response?.status === 422 && typeof err.response.data === "string"
+                    ? err.response.data
+                    : "Failed to obtain security ticket for VNC session");
             } finally {
                 setIsFetchingTicket(false);
             }
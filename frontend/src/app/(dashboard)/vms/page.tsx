@@ -2,7 +2,7 @@
 
 import { useQuery, useMutation, useQueryClient } from "@tanstack/react-query";
 import { vmService } from "@/services/api";
-import { Monitor, Server, Activity, Play, Square, RefreshCcw, Power, RotateCcw, Loader2, Settings2, HardDrive, Terminal } from "lucide-react";
+import { Monitor, Server, Activity, Play, Square, RefreshCcw, Power, RotateCcw, Loader2, Settings2, HardDrive, Terminal, Network } from "lucide-react";
 import { Button } from "@/components/ui/button";
 import { Card, CardContent } from "@/components/ui/card";
 import { motion, AnimatePresence } from "framer-motion";
@@ -10,7 +10,24 @@ import { cn } from "@/lib/utils";
 import Link from "next/link";
 import { toast } from "sonner";
 import { VMDialog } from "@/components/vms/vm-dialog";
-import { useState } from "react";
+import { useEffect, useState } from "react";
+
+// Guests are matched on node + cluster-wide id so a host migration updates the existing row
+const vmKey = (vm: any) => `${vm.node_id}:${vm.id ?? vm.name}`;
+
+function applyInventoryDiff(vms: any[], changes: any[]) {
+    let next = [...vms];
+    for (const change of changes) {
+        if (change.kind === "removed") {
+            next = next.filter((vm) => vmKey(vm) !== vmKey(change.vm));
+        } else {
+            const index = next.findIndex((vm) => vmKey(vm) === vmKey(change.vm));
+            if (index >= 0) next[index] = change.vm;
+            else next.push(change.vm);
+        }
+    }
+    return next;
+}
 
 export default function VMsPage() {
     const queryClient = useQueryClient();
@@ -19,9 +36,24 @@ export default function VMsPage() {
     const { data: vms, isLoading, isRefetching } = useQuery({
         queryKey: ["vms"],
         queryFn: vmService.list,
-        refetchInterval: 10000,
+        // Fallback only; changes arrive through the inventory event stream below
+        refetchInterval: 60000,
     });
 
+    useEffect(() => {
+        const source = vmService.events();
+        // Sent on connect and after reconnects; replaces whatever the list had
+        source.addEventListener("snapshot", (event) => {
+            const { vms } = JSON.parse((event as MessageEvent).data);
+            queryClient.setQueryData<any[]>(["vms"], vms);
+        });
+        source.addEventListener("diff", (event) => {
+            const { changes } = JSON.parse((event as MessageEvent).data);
+            queryClient.setQueryData<any[]>(["vms"], (current) => current && applyInventoryDiff(current, changes));
+        });
+        return () => source.close();
+    }, [queryClient]);
+
     const powerMutation = useMutation({
         mutationFn: ({ node_id, vm_id, action }: { node_id: string, vm_id: string, action: "start" | "stop" | "shutdown" | "reboot" }) =>
             vmService.powerAction(node_id, vm_id, action),
@@ -126,6 +158,17 @@ export default function VMsPage() {
                                                 <HardDrive className="w-3 h-3 text-accent-secondary" />
                                                 <span className="font-mono">{vm.maxmem ? `${Math.round(vm.maxmem / (1024 ** 3))} GB` : `${vm.memory || 1024} MB`}</span>
                                             </div>
+                                            {vm.ip_addresses?.[0] && (
+                                                <div className="flex items-center gap-1.5 px-2 py-1 rounded-md bg-white/5 text-[10px] border border-white/5" title={vm.ip_addresses.join(", ")}>
+                                                    <Network className="w-3 h-3 text-primary" />
+                                                    <span className="font-mono">{vm.ip_addresses[0]}{vm.ip_addresses.length > 1 ? ` +${vm.ip_addresses.length - 1}` : ""}</span>
+                                                </div>
+                                            )}
+                                            {vm.os_name && (
+                                                <div className="flex items-center gap-1.5 px-2 py-1 rounded-md bg-white/5 text-[10px] border border-white/5">
+                                                    <span className="truncate max-w-[140px]">{vm.os_name}</span>
+                                                </div>
+                                            )}
                                         </div>
 
                                         <div className="grid grid-cols-2 gap-2 pt-2 mt-auto">
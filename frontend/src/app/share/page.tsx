@@ -0,0 +1,57 @@
+"use client";
+
+import { Suspense } from "react";
+import dynamic from "next/dynamic";
+import { useSearchParams } from "next/navigation";
+import { Eye, Monitor } from "lucide-react";
+
+const VNCClient = dynamic(() => import("@/components/vms/vnc-client"), {
+    ssr: false,
+    loading: () => (
+        <div className="w-full h-full flex items-center justify-center bg-card">
+            <div className="w-10 h-10 border-2 border-primary border-t-transparent rounded-full animate-spin" />
+        </div>
+    ),
+});
+
+// Console opened from a share link: no dashboard login, access is limited to the one VM
+function SharedConsole() {
+    const searchParams = useSearchParams();
+    const nodeId = searchParams.get("node");
+    const vmId = searchParams.get("vm");
+    const shareToken = searchParams.get("share");
+    const viewOnly = searchParams.get("view") === "1";
+
+    if (!nodeId || !vmId || !shareToken) {
+        return (
+            <div className="flex h-screen items-center justify-center text-sm text-muted-foreground">
+                This console link is incomplete.
+            </div>
+        );
+    }
+
+    return (
+        <div className="flex h-screen flex-col bg-background">
+            <header className="flex items-center gap-3 border-b border-black/5 dark:border-white/10 px-4 py-3">
+                <Monitor className="h-4 w-4 text-primary" />
+                <span className="text-sm font-semibold">{vmId}</span>
+                {viewOnly && (
+                    <span className="flex items-center gap-1 rounded-md bg-black/5 dark:bg-white/5 px-2 py-0.5 text-xs text-muted-foreground">
+                        <Eye className="h-3 w-3" /> View only
+                    </span>
+                )}
+            </header>
+            <div className="flex-1">
+                <VNCClient nodeId={nodeId} vmId={vmId} shareToken={shareToken} viewOnly={viewOnly} />
+            </div>
+        </div>
+    );
+}
+
+export default function SharedConsolePage() {
+    return (
+        <Suspense>
+            <SharedConsole />
+        </Suspense>
+    );
+}
@@ -14,7 +14,7 @@ import { Card, CardContent, CardHeader, CardTitle } from "@/components/ui/card";
 import { Activity, Cpu, Database, Server, HardDrive, Clock } from "lucide-react";
 import { Select, SelectContent, SelectItem, SelectTrigger, SelectValue } from "@/components/ui/select";
 import { useQuery } from "@tanstack/react-query";
-import { nodeService } from "@/services/api";
+import { nodeService, wsTicketService } from "@/services/api";
 
 interface Metric {
     cpu: number;
@@ -69,67 +69,83 @@ export function RealtimeMetrics() {
     useEffect(() => {
         if (!mounted) return;
 
-        // Add token to WebSocket URL for authentication
-        const token = localStorage.getItem("access_token");
-        const wsUrl = `${process.env.NEXT_PUBLIC_WS_URL || "ws://localhost:3001"}/api/v1/metrics${selectedNode !== "all" ? `?node_id=${selectedNode}` : ''}${token ? `${selectedNode !== "all" ? '&' : '?'}token=${token}` : ''}`;
-        const socket = new WebSocket(wsUrl);
+        let socket: WebSocket | null = null;
+        let cancelled = false;
 
-        socket.onmessage = (event) => {
-            const update: Metric = JSON.parse(event.data);
+        const connect = async () => {
+            const params = new URLSearchParams();
+            if (selectedNode !== "all") params.append("node_id", selectedNode);
+            let wsUrl: string;
+            try {
+                wsUrl = await wsTicketService.connectUrl("/api/v1/metrics", params);
+            } catch {
+                return;
+            }
+            if (cancelled) return;
+            socket = new WebSocket(wsUrl);
+
+            socket.onmessage = (event) => {
+                const update: Metric = JSON.parse(event.data);
             
-            // Update latest metrics per node
-            setLatestMetrics((prev) => {
-                const newMap = new Map(prev);
-                newMap.set(update.node_id, update);
-                return newMap;
-            });
+                // Update latest metrics per node
+                setLatestMetrics((prev) => {
+                    const newMap = new Map(prev);
+                    newMap.set(update.node_id, update);
+                    return newMap;
+                });
 
-            // Update chart data
-            setData((prev) => {
-                let newPoint: Metric | AggregatedMetric;
+                // Update chart data
+                setData((prev) => {
+                    let newPoint: Metric | AggregatedMetric;
                 
-                if (selectedNode === "all") {
-                    // Aggregate metrics from all nodes
-                    const allNodeMetrics = Array.from(latestMetrics.values());
-                    if (allNodeMetrics.length === 0) {
-                        newPoint = update;
-                    } else {
-                        // Include the new update in aggregation
-                        const metricsToAggregate = [...allNodeMetrics.filter(m => m.node_id !== update.node_id), update];
-                        const avgCpu = metricsToAggregate.reduce((sum, m) => sum + m.cpu, 0) / metricsToAggregate.length;
-                        const avgRam = metricsToAggregate.reduce((sum, m) => sum + m.ram, 0) / metricsToAggregate.length;
-                        const avgDisk = metricsToAggregate.filter(m => m.disk !== null && m.disk !== undefined).reduce((sum, m) => sum + (m.disk || 0), 0) / metricsToAggregate.filter(m => m.disk).length || 0;
+                    if (selectedNode === "all") {
+                        // Aggregate metrics from all nodes
+                        const allNodeMetrics = Array.from(latestMetrics.values());
+                        if (allNodeMetrics.length === 0) {
+                            newPoint = update;
+                        } else {
+                            // Include the new update in aggregation
+                            const metricsToAggregate = [...allNodeMetrics.filter(m => m.node_id !== update.node_id), update];
+                            const avgCpu = metricsToAggregate.reduce((sum, m) => sum + m.cpu, 0) / metricsToAggregate.length;
+                            const avgRam = metricsToAggregate.reduce((sum, m) => sum + m.ram, 0) / metricsToAggregate.length;
+                            const avgDisk = metricsToAggregate.filter(m => m.disk !== null && m.disk !== undefined).reduce((sum, m) => sum + (m.disk || 0), 0) / metricsToAggregate.filter(m => m.disk).length || 0;
                         
-                        newPoint = {
-                            cpu: avgCpu,
-                            ram: avgRam,
-                            disk: avgDisk,
-                            timestamp: update.timestamp,
-                            node_count: metricsToAggregate.length,
-                        };
+                            newPoint = {
+                                cpu: avgCpu,
+                                ram: avgRam,
+                                disk: avgDisk,
+                                timestamp: update.timestamp,
+                                node_count: metricsToAggregate.length,
+                            };
+                        }
+                    } else {
+                        newPoint = update;
                     }
-                } else {
-                    newPoint = update;
-                }
 
-                const newData = [...prev, newPoint];
-                // Keep last 30 data points
-                if (newData.length > 30) {
-                    return newData.slice(1);
-                }
-                return newData;
-            });
-        };
+                    const newData = [...prev, newPoint];
+                    // Keep last 30 data points
+                    if (newData.length > 30) {
+                        return newData.slice(1);
+                    }
+                    return newData;
+                });
+            };
 
-        socket.onerror = (error) => {
-            // Silently handle WebSocket errors
-        };
+            socket.onerror = (error) => {
+                // Silently handle WebSocket errors
+            };
 
-        socket.onclose = () => {
-            // Silently handle WebSocket close
+            socket.onclose = () => {
+                // Silently handle WebSocket close
+            };
         };
 
-        return () => socket.close();
+        connect();
+
+        return () => {
+            cancelled = true;
+            socket?.close();
+        };
     }, [selectedNode, mounted, latestMetrics]);
 
     if (!mounted) {
@@ -41,8 +41,9 @@ export function NodeDialog({ node, open, onOpenChange }: NodeDialogProps) {
             onOpenChange(false);
         },
         onError: (err: any) => {
+            // A rejected connection test returns its report, with the reason in `error`
             toast.error("Failed to update node", {
-                description: err.response?.data?.message || "Internal server error"
+                description: err.response?.data?.error || err.response?.data?.message || "Internal server error"
             });
         }
     });
@@ -130,6 +131,26 @@ export function NodeDialog({ node, open, onOpenChange }: NodeDialogProps) {
                         </div>
                     </div>
 
+                    {node.tls_mode === "pinned" && node.tls_fingerprint && (
+                        <div className="space-y-2">
+                            <Label className="text-xs uppercase tracking-widest text-muted-foreground font-bold">Pinned Certificate</Label>
+                            <p className="text-[10px] font-mono break-all text-muted-foreground">{node.tls_fingerprint}</p>
+                            <Button
+                                variant="outline"
+                                size="sm"
+                                className="w-full"
+                                disabled={updateMutation.isPending}
+                                onClick={() => {
+                                    if (confirm("Forget the pinned certificate? The node stays disconnected until you trust one again.")) {
+                                        updateMutation.mutate({ tls_fingerprint: "" });
+                                    }
+                                }}
+                            >
+                                Forget Pinned Certificate
+                            </Button>
+                        </div>
+                    )}
+
                     <Separator className="bg-white/5 my-4" />
 
                     <div className="bg-destructive/10 border border-destructive/20 rounded-xl p-4">
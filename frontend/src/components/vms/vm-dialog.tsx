@@ -2,7 +2,7 @@
 
 import { useState } from "react";
 import { useQuery, useMutation, useQueryClient } from "@tanstack/react-query";
-import { vmService } from "@/services/api";
+import { mediaService, vmService, VmResize } from "@/services/api";
 import {
     Dialog,
     DialogContent,
@@ -15,7 +15,7 @@ import { Button } from "@/components/ui/button";
 import { Input } from "@/components/ui/input";
 import { Label } from "@/components/ui/label";
 import { Tabs, TabsContent, TabsList, TabsTrigger } from "@/components/ui/tabs";
-import { Cpu, HardDrive, Settings2, Save, Loader2, Info } from "lucide-react";
+import { Cpu, HardDrive, Settings2, Save, Loader2, Info, Disc, XIcon } from "lucide-react";
 import { toast } from "sonner";
 
 interface VMDialogProps {
@@ -26,23 +26,30 @@ interface VMDialogProps {
 
 export function VMDialog({ vm, open, onOpenChange }: VMDialogProps) {
     const queryClient = useQueryClient();
-    const [config, setConfig] = useState<any>({
+    // The VM list reports memory in bytes
+    const initial = {
         cores: vm.cores || 1,
-        memory: vm.memory || 1024,
-    });
+        memory: vm.memory ? Math.round(vm.memory / (1024 * 1024)) : 1024,
+    };
+    const [config, setConfig] = useState({ ...initial, diskGrow: 0 });
     const [isoPath, setIsoPath] = useState("");
+    const [setting, setSetting] = useState({ key: "", value: "" });
 
-    const { data: details, isLoading: detailsLoading } = useQuery({
+    const { data: details, isLoading: detailsLoading, refetch: refetchDetails } = useQuery({
         queryKey: ["vm-details", vm.node_id, vm.internal_id],
         queryFn: () => vmService.getDetails(vm.node_id, vm.internal_id),
         enabled: open,
     });
 
     const updateMutation = useMutation({
-        mutationFn: (newConfig: any) => vmService.updateConfig(vm.node_id, vm.internal_id, newConfig),
-        onSuccess: () => {
+        mutationFn: (resize: VmResize) => vmService.resize(vm.node_id, vm.internal_id, resize),
+        onSuccess: (outcome) => {
             queryClient.invalidateQueries({ queryKey: ["vms"] });
-            toast.success("VM configuration updated successfully");
+            if (outcome.reboot_required) {
+                toast.success("VM configuration updated", { description: "Restart the VM to apply all changes" });
+            } else {
+                toast.success("VM configuration updated successfully");
+            }
         },
         onError: (err: any) => {
             toast.error("Failed to update configuration", {
@@ -51,6 +58,55 @@ export function VMDialog({ vm, open, onOpenChange }: VMDialogProps) {
         }
     });
 
+    // Raw config edits carry the digest the details were read at, so concurrent changes are not overwritten
+    const settingMutation = useMutation({
+        mutationFn: ({ key, value }: { key: string; value: string }) =>
+            vmService.updateConfig(vm.node_id, vm.internal_id, { [key]: value }, details?.digest),
+        onSuccess: () => {
+            setSetting({ key: "", value: "" });
+            refetchDetails();
+            queryClient.invalidateQueries({ queryKey: ["vms"] });
+            toast.success("Setting updated");
+        },
+        onError: (err: any) => {
+            if (err.response?.status === 409) {
+                // The response is the current config; show it so the edit can be checked against it
+                queryClient.setQueryData(["vm-details", vm.node_id, vm.internal_id], err.response.data);
+                toast.error("The configuration was changed by someone else", {
+                    description: "The details now show the current values; review them and save again"
+                });
+            } else if (err.response?.status === 428) {
+                refetchDetails();
+                toast.error("Configuration version unknown", {
+                    description: "Reload the details and save again"
+                });
+            } else {
+                toast.error("Failed to update setting", {
+                    description: err.response?.data?.message || "Internal server error"
+                });
+            }
+        }
+    });
+
+    const { data: library } = useQuery({
+        queryKey: ["media-library", vm.node_id],
+        queryFn: () => mediaService.library(vm.node_id),
+        enabled: open,
+    });
+
+    const ejectMutation = useMutation({
+        mutationFn: () => mediaService.eject(vm.node_id, vm.internal_id),
+        onSuccess: () => {
+            setIsoPath("");
+            toast.success("Media ejected");
+        },
+        onError: (err: any) => {
+            toast.error("Failed to eject media", {
+                description: err.response?.data?.message || "Internal server error"
+            });
+        }
+    });
+
     const mountMutation = useMutation({
         mutationFn: (path: string) => vmService.mountMedia(vm.node_id, vm.internal_id, path),
         onSuccess: () => {
@@ -64,7 +120,12 @@ export function VMDialog({ vm, open, onOpenChange }: VMDialogProps) {
     });
 
     const handleSaveConfig = () => {
-        updateMutation.mutate(config);
+        // Only send what changed so untouched settings are not rewritten
+        updateMutation.mutate({
+            cores: config.cores !== initial.cores ? config.cores : undefined,
+            memory_mib: config.memory !== initial.memory ? config.memory : undefined,
+            disk_grow_gib: config.diskGrow > 0 ? config.diskGrow : undefined,
+        });
     };
 
     const handleMountIso = () => {
@@ -115,7 +176,7 @@ export function VMDialog({ vm, open, onOpenChange }: VMDialogProps) {
                                 />
                             </div>
                             <div className="space-y-2">
-                                <Label htmlFor="memory" className="text-xs uppercase tracking-widest text-muted-foreground font-bold">Memory (MB)</Label>
+                                <Label htmlFor="memory" className="text-xs uppercase tracking-widest text-muted-foreground font-bold">Memory (MiB)</Label>
                                 <Input
                                     id="memory"
                                     type="number"
@@ -124,6 +185,17 @@ export function VMDialog({ vm, open, onOpenChange }: VMDialogProps) {
                                     className="glass-surface border-black/5 dark:border-white/10 bg-black/5 dark:bg-white/5"
                                 />
                             </div>
+                            <div className="space-y-2">
+                                <Label htmlFor="disk-grow" className="text-xs uppercase tracking-widest text-muted-foreground font-bold">Grow Boot Disk (GiB)</Label>
+                                <Input
+                                    id="disk-grow"
+                                    type="number"
+                                    min={0}
+                                    value={config.diskGrow}
+                                    onChange={(e) => setConfig({ ...config, diskGrow: parseInt(e.target.value) || 0 })}
+                                    className="glass-surface border-black/5 dark:border-white/10 bg-black/5 dark:bg-white/5"
+                                />
+                            </div>
                         </div>
                         <Button
                             className="w-full btn-premium bg-primary hover:bg-primary/90 font-bold"
@@ -152,8 +224,24 @@ export function VMDialog({ vm, open, onOpenChange }: VMDialogProps) {
                                 />
                             </div>
                             <p className="text-xs text-muted-foreground italic">
-                                Format: storage:type/filename. Leaf blank to unmount.
+                                Format: storage:iso/filename, or pick one from the library.
                             </p>
+                            {library && library.some((item) => item.kind === "iso") && (
+                                <div className="space-y-1 max-h-[160px] overflow-auto pr-2 custom-scrollbar">
+                                    {library.filter((item) => item.kind === "iso").map((item) => (
+                                        <button
+                                            key={item.id}
+                                            type="button"
+                                            onClick={() => setIsoPath(item.id)}
+                                            className={`flex w-full items-center gap-2 rounded-md px-2 py-1.5 text-left text-xs font-mono hover:bg-white/10 ${isoPath === item.id ? "bg-primary/20 text-primary" : "text-muted-foreground"}`}
+                                        >
+                                            <Disc className="w-3 h-3 shrink-0" />
+                                            <span className="truncate">{item.name}</span>
+                                            <span className="ml-auto shrink-0">{item.storage}</span>
+                                        </button>
+                                    ))}
+                                </div>
+                            )}
                         </div>
                         <Button
                             className="w-full btn-premium bg-primary hover:bg-primary/90 font-bold"
@@ -167,6 +255,19 @@ export function VMDialog({ vm, open, onOpenChange }: VMDialogProps) {
                             )}
                             Update Media
                         </Button>
+                        <Button
+                            variant="outline"
+                            className="w-full font-bold"
+                            onClick={() => ejectMutation.mutate()}
+                            disabled={ejectMutation.isPending}
+                        >
+                            {ejectMutation.isPending ? (
+                                <Loader2 className="w-4 h-4 animate-spin mr-2" />
+                            ) : (
+                                <XIcon className="w-4 h-4 mr-2" />
+                            )}
+                            Eject
+                        </Button>
                     </TabsContent>
 
                     <TabsContent value="info" className="py-4">
@@ -175,13 +276,47 @@ export function VMDialog({ vm, open, onOpenChange }: VMDialogProps) {
                                 <Loader2 className="w-8 h-8 animate-spin text-primary" />
                             </div>
                         ) : (
-                            <div className="space-y-2 max-h-[300px] overflow-auto pr-2 custom-scrollbar">
-                                {details && Object.entries(details).map(([key, value]: [string, any]) => (
-                                    <div key={key} className="flex justify-between items-center py-2 border-b border-white/5 last:border-0">
-                                        <span className="text-xs font-mono text-muted-foreground">{key}</span>
-                                        <span className="text-xs font-mono text-foreground font-medium truncate max-w-[200px]">{JSON.stringify(value)}</span>
-                                    </div>
-                                ))}
+                            <div className="space-y-4">
+                                <div className="space-y-2 max-h-[300px] overflow-auto pr-2 custom-scrollbar">
+                                    {details && Object.entries(details).filter(([key]) => key !== "digest").map(([key, value]: [string, any]) => (
+                                        <button
+                                            key={key}
+                                            type="button"
+                                            onClick={() => setSetting({ key, value: typeof value === "string" ? value : JSON.stringify(value) })}
+                                            className="flex w-full justify-between items-center py-2 border-b border-white/5 last:border-0 text-left hover:bg-white/5"
+                                        >
+                                            <span className="text-xs font-mono text-muted-foreground">{key}</span>
+                                            <span className="text-xs font-mono text-foreground font-medium truncate max-w-[200px]">{JSON.stringify(value)}</span>
+                                        </button>
+                                    ))}
+                                </div>
+                                <div className="grid grid-cols-2 gap-2">
+                                    <Input
+                                        placeholder="Setting"
+                                        value={setting.key}
+                                        onChange={(e) => setSetting({ ...setting, key: e.target.value })}
+                                        className="glass-surface border-black/5 dark:border-white/10 bg-black/5 dark:bg-white/5 font-mono text-xs"
+                                    />
+                                    <Input
+                                        placeholder="Value"
+                                        value={setting.value}
+                                        onChange={(e) => setSetting({ ...setting, value: e.target.value })}
+                                        className="glass-surface border-black/5 dark:border-white/10 bg-black/5 dark:bg-white/5 font-mono text-xs"
+                                    />
+                                </div>
+                                <Button
+                                    variant="outline"
+                                    className="w-full font-bold"
+                                    onClick={() => settingMutation.mutate(setting)}
+                                    disabled={!setting.key || !details?.digest || settingMutation.isPending}
+                                >
+                                    {settingMutation.isPending ? (
+                                        <Loader2 className="w-4 h-4 animate-spin mr-2" />
+                                    ) : (
+                                        <Save className="w-4 h-4 mr-2" />
+                                    )}
+                                    Save Setting
+                                </Button>
                             </div>
                         )}
                     </TabsContent>
@@ -2,16 +2,19 @@
 
 import { useEffect, useRef, useState, useCallback } from "react";
 import { toast } from "sonner";
+import { wsTicketService } from "@/services/api";
 
 interface VNCClientProps {
     nodeId: string;
     vmId: string; // decoded path (e.g., pve/qemu/100)
     ticket?: string;
     port?: number;
+    shareToken?: string; // console share link token, used instead of a login
+    viewOnly?: boolean;
     onStatusChange?: (status: string) => void;
 }
 
-export default function VNCClient({ nodeId, vmId, ticket, port, onStatusChange }: VNCClientProps) {
+export default function VNCClient({ nodeId, vmId, ticket, port, shareToken, viewOnly = false, onStatusChange }: VNCClientProps) {
     const containerRef = useRef<HTMLDivElement>(null);
     const rfbRef = useRef<any>(null);
     const [status, setStatus] = useState<string>("Connecting...");
@@ -37,16 +40,22 @@ export default function VNCClient({ nodeId, vmId, ticket, port, onStatusChange }
             try {
                 if (cancelled || !containerRef.current) return;
 
-                // Using HttpOnly cookies when available; also append access_token as query for cross-site WSS
-                const wsBaseUrl = process.env.NEXT_PUBLIC_WS_URL || "ws://localhost:3001";
                 const params = new URLSearchParams();
                 if (ticket) params.append('ticket', ticket);
                 if (port) params.append('port', port.toString());
-                const token = typeof window !== "undefined" ? localStorage.getItem("access_token") : null;
-                if (token) params.append('token', token);
 
                 const safeVmId = encodeURIComponent(vmId);
-                const wsUrl = `${wsBaseUrl}/api/v1/vms/console/${nodeId}/${safeVmId}${params.toString() ? `?${params.toString()}` : '' }`;
+                const path = `/api/v1/vms/console/${nodeId}/${safeVmId}`;
+                let wsUrl: string;
+                if (shareToken) {
+                    params.append('share', shareToken);
+                    const wsBaseUrl = process.env.NEXT_PUBLIC_WS_URL || "ws://localhost:3001";
+                    wsUrl = `${wsBaseUrl}${path}?${params.toString()}`;
+                } else {
+                    if (viewOnly) params.append('view_only', 'true');
+                    wsUrl = await wsTicketService.connectUrl(path, params);
+                }
+                if (cancelled || !containerRef.current) return;
 
                 // Dynamically import RFB to avoid CommonJS issues
                 const { default: RFB } = await import('@novnc/novnc/lib/rfb');
@@ -68,7 +77,7 @@ export default function VNCClient({ nodeId, vmId, ticket, port, onStatusChange }
                 rfb.dragViewport = false;
                 
                 // Critical for input handling - enable full keyboard control
-                rfb.viewOnly = false;  // Allow interaction
+                rfb.viewOnly = viewOnly;  // Allow interaction unless the session is view-only
                 rfb.focusOnClick = true;  // Auto-focus on click
                 
                 // Get the canvas and configure keyboard capture
@@ -136,7 +145,7 @@ export default function VNCClient({ nodeId, vmId, ticket, port, onStatusChange }
                 rfbRef.current = null;
             }
         };
-    }, [nodeId, vmId, ticket, port, updateStatus, mounted]);
+    }, [nodeId, vmId, ticket, port, shareToken, viewOnly, updateStatus, mounted]);
 
     // Expose sendCtrlAltDel for parent component
     useEffect(() => {
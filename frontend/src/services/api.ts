@@ -55,6 +55,8 @@ api.interceptors.response.use(
     }
 );
 
+export type NodeTlsMode = "system" | "custom_ca" | "pinned";
+
 export interface Node {
     id: string;
     name: string;
@@ -63,6 +65,20 @@ export interface Node {
     status: "online" | "offline" | "error";
     api_key?: string;
     api_secret?: string;
+    tls_mode?: NodeTlsMode;
+    tls_ca_cert?: string | null;
+    tls_fingerprint?: string | null;
+    // Certificate seen on first contact (or after a change), waiting for an admin to trust it
+    tls_pending_fingerprint?: string | null;
+}
+
+export interface NodeTlsStatus {
+    tls_mode: NodeTlsMode;
+    trusted_fingerprint: string | null;
+    pending_fingerprint: string | null;
+    // Only set by a probe
+    presented_fingerprint: string | null;
+    trusted: boolean | null;
 }
 
 export interface VM {
@@ -76,6 +92,21 @@ export interface VM {
     memory?: number;
     maxmem?: number;
     node_name?: string;
+    guest_type?: "vm" | "container";
+    // Reported by the guest agent / Incus instance state while running
+    guest_agent?: boolean;
+    ip_addresses?: string[];
+    os_name?: string | null;
+    os_version?: string | null;
+}
+
+export interface VmResize {
+    cores?: number;
+    sockets?: number;      // Proxmox QEMU only
+    memory_mib?: number;
+    balloon_mib?: number;  // Proxmox QEMU only
+    disk_grow_gib?: number;
+    disk?: string;
 }
 
 export const nodeService = {
@@ -95,19 +126,43 @@ export const nodeService = {
         const { data } = await api.delete(`nodes/${id}`);
         return data;
     },
+    getTls: async (id: string) => {
+        const { data } = await api.get<NodeTlsStatus>(`nodes/${id}/tls`);
+        return data;
+    },
+    // Reads the certificate a pinned node presents now; a new one is recorded as pending
+    probeTls: async (id: string) => {
+        const { data } = await api.post<NodeTlsStatus>(`nodes/${id}/tls/probe`);
+        return data;
+    },
+    trustFingerprint: async (id: string, fingerprint: string) => {
+        const { data } = await api.post<Node>(`nodes/${id}/tls/trust`, { fingerprint });
+        return data;
+    },
 };
 
 export const vmService = {
     list: async () => {
-        const { data } = await api.get<VM[]>("vms");
-        return data;
+        const { data } = await api.get<{ items: VM[], total: number }>("vms");
+        return data.items;
+    },
+    // Server-sent inventory changes (auth via the access_token cookie)
+    events: () => {
+        const base = apiBaseUrl.endsWith("/") ? apiBaseUrl : `${apiBaseUrl}/`;
+        return new EventSource(`${base}vms/events`, { withCredentials: true });
     },
     powerAction: async (node_id: string, vm_id: string, action: "start" | "stop" | "shutdown" | "reboot") => {
         const { data } = await api.post("vms/power", { node_id, vm_id, action });
         return data;
     },
-    updateConfig: async (node_id: string, vm_id: string, config: any) => {
-        const { data } = await api.patch("vms/config", { node_id, vm_id, config });
+    // `digest` comes from getDetails; a 409 means someone else changed the config and carries the current one
+    updateConfig: async (node_id: string, vm_id: string, config: any, digest: string) => {
+        const { data } = await api.patch("vms/config", { node_id, vm_id, config, digest });
+        return data;
+    },
+    // Checked against host capacity; disk_grow_gib grows the boot/root disk unless `disk` is given
+    resize: async (node_id: string, vm_id: string, resize: VmResize) => {
+        const { data } = await api.post<{ reboot_required: boolean }>("vms/resize", { node_id, vm_id, ...resize });
         return data;
     },
     getDetails: async (node_id: string, vm_id: string) => {
@@ -126,6 +181,180 @@ export const vmService = {
     },
 };
 
+export interface ConsoleShareLink {
+    id: string;
+    node_id: string;
+    vm_id: string;
+    label: string | null;
+    view_only: boolean;
+    expires_at: string;
+    revoked_at: string | null;
+    created_at: string;
+    token?: string; // only returned on creation
+}
+
+export const consoleShareService = {
+    list: async (node_id?: string, vm_id?: string) => {
+        const { data } = await api.get<ConsoleShareLink[]>("vms/console/shares", { params: { node_id, vm_id } });
+        return data;
+    },
+    create: async (share: { node_id: string; vm_id: string; label?: string; view_only?: boolean; expires_in_secs?: number }) => {
+        const { data } = await api.post<ConsoleShareLink>("vms/console/shares", share);
+        return data;
+    },
+    revoke: async (id: string) => {
+        await api.delete(`vms/console/shares/${id}`);
+    },
+    // Public page that opens the console with the share token
+    linkFor: (share: ConsoleShareLink) => {
+        const params = new URLSearchParams({ node: share.node_id, vm: share.vm_id, share: share.token ?? "" });
+        if (share.view_only) params.append("view", "1");
+        return `${window.location.origin}/share?${params.toString()}`;
+    },
+};
+
+export interface ConsolePermission {
+    id: string;
+    user_id: string;
+    username: string;
+    node_id: string;
+    vm_id: string;
+    access: "view" | "control";
+    granted_by: string | null;
+    created_at: string;
+}
+
+export const consolePermissionService = {
+    list: async (params: { user_id?: string; node_id?: string; vm_id?: string } = {}) => {
+        const { data } = await api.get<ConsolePermission[]>("vms/console/permissions", { params });
+        return data;
+    },
+    set: async (permission: { user_id: string; node_id: string; vm_id: string; access: "view" | "control" }) => {
+        const { data } = await api.put<ConsolePermission>("vms/console/permissions", permission);
+        return data;
+    },
+    remove: async (id: string) => {
+        await api.delete(`vms/console/permissions/${id}`);
+    },
+};
+
+export type KeyCombo = "ctrl-alt-del" | "alt-f2" | "sysrq";
+
+export const consoleInputService = {
+    // Type into a live console session (US layout), e.g. to paste a long password
+    typeText: async (sessionId: string, text: string, delay_ms?: number) => {
+        await api.post(`vms/console/sessions/${sessionId}/type`, { text, delay_ms });
+    },
+    sendKeys: async (sessionId: string, combo: KeyCombo, sysrq_key?: string) => {
+        await api.post(`vms/console/sessions/${sessionId}/keys`, { combo, sysrq_key });
+    },
+    // Proxmox QEMU guests only; works without an open console
+    sendKeysToVm: async (nodeId: string, vmId: string, combo: KeyCombo, sysrq_key?: string) => {
+        await api.post(`vms/console/${nodeId}/${encodeURIComponent(vmId)}/sendkey`, { combo, sysrq_key });
+    },
+};
+
+export interface IpConfig {
+    ip?: string | null;        // "dhcp" or CIDR
+    gateway?: string | null;
+    ip6?: string | null;       // "dhcp", "auto" or CIDR
+    gateway6?: string | null;
+}
+
+export interface CloudInitConfig {
+    user: string | null;
+    password_set: boolean;
+    ssh_keys: string[];
+    ip_configs: IpConfig[];
+    nameservers: string[];
+    search_domain: string | null;
+    // Incus only
+    user_data: string | null;
+    network_config: string | null;
+}
+
+// Omitted fields are unchanged; empty strings/lists clear them
+export type CloudInitUpdate = Partial<Omit<CloudInitConfig, "password_set">> & { password?: string };
+
+export const cloudInitService = {
+    get: async (node_id: string, vm_id: string) => {
+        const { data } = await api.get<CloudInitConfig>("vms/cloud-init", { params: { node_id, vm_id } });
+        return data;
+    },
+    update: async (node_id: string, vm_id: string, changes: CloudInitUpdate) => {
+        await api.put("vms/cloud-init", { node_id, vm_id, ...changes });
+    },
+    // Proxmox QEMU guests only
+    regenerate: async (node_id: string, vm_id: string) => {
+        await api.post("vms/cloud-init/regenerate", { node_id, vm_id });
+    },
+};
+
+export interface MediaStorage {
+    name: string;
+    storage_type: string;
+    total: number | null;
+    available: number | null;
+}
+
+export interface MediaItem {
+    id: string;            // what mountMedia takes as iso_path
+    name: string;
+    kind: "iso" | "image"; // Incus images are listed but not mountable
+    storage: string;
+    size: number | null;
+    created_at: string | null;
+}
+
+export type ChecksumAlgorithm = "md5" | "sha1" | "sha224" | "sha256" | "sha384" | "sha512";
+
+// `host` picks the Proxmox cluster member; the first one online is used when omitted
+export const mediaService = {
+    storages: async (node_id: string, host?: string) => {
+        const { data } = await api.get<MediaStorage[]>("vms/media/storages", { params: { node_id, host } });
+        return data;
+    },
+    // Without a storage, lists every ISO storage (and Incus images)
+    library: async (node_id: string, storage?: string, host?: string) => {
+        const { data } = await api.get<MediaItem[]>("vms/media/library", { params: { node_id, storage, host } });
+        return data;
+    },
+    remove: async (node_id: string, id: string, host?: string) => {
+        await api.delete("vms/media/library", { params: { node_id, id, host } });
+    },
+    // Streams the file through the backend; a sha256 mismatch deletes the upload and fails with 422
+    upload: async (node_id: string, storage: string, file: File, options: { host?: string; sha256?: string; onProgress?: (fraction: number) => void } = {}) => {
+        const { data } = await api.post<MediaItem & { sha256: string }>("vms/media/upload", file, {
+            params: { node_id, storage, filename: file.name, host: options.host, sha256: options.sha256 },
+            headers: { "Content-Type": "application/octet-stream" },
+            onUploadProgress: (e) => e.total && options.onProgress?.(e.loaded / e.total),
+        });
+        return data;
+    },
+    // Proxmox only; poll taskStatus until `status` is "stopped"
+    download: async (node_id: string, storage: string, url: string, filename: string, options: { host?: string; checksum?: string; checksum_algorithm?: ChecksumAlgorithm; verify_certificates?: boolean } = {}) => {
+        const { data } = await api.post<{ task: string }>("vms/media/download", { node_id, storage, url, filename, ...options });
+        return data.task;
+    },
+    taskStatus: async (node_id: string, task: string) => {
+        const { data } = await api.get<{ status: string; exitstatus?: string }>("vms/media/tasks", { params: { node_id, task } });
+        return data;
+    },
+    eject: async (node_id: string, vm_id: string) => {
+        await api.post("vms/media/eject", { node_id, vm_id });
+    },
+};
+
+export const wsTicketService = {
+    // Exchange the session for a one-time ticket bound to this WebSocket path, so the JWT never ends up in a URL
+    connectUrl: async (path: string, params: URLSearchParams = new URLSearchParams()) => {
+        const { data } = await api.post<{ ticket: string; expires_in: number }>("auth/ws-ticket", { path });
+        params.append("ws_ticket", data.ticket);
+        const wsBaseUrl = process.env.NEXT_PUBLIC_WS_URL || "ws://localhost:3001";
+        return `${wsBaseUrl}${path}?${params.toString()}`;
+    },
+};
+
 export const supportService = {
     sendMessage: async (message: { subject: string, message: string, priority: string }) => {
         const { data } = await api.post("support/message", message);